@@ -0,0 +1,224 @@
+//! Benchmarks of `ChunkedAllocator` over an owner that hands out fake chunks.
+
+#[macro_use]
+extern crate criterion;
+extern crate gfx_backend_empty as empty;
+extern crate gfx_hal;
+extern crate gfx_memory;
+
+use std::ops::Range;
+
+use criterion::Criterion;
+use gfx_hal::MemoryTypeId;
+use gfx_hal::memory::Requirements;
+use gfx_memory::{alignment_shift, Block, ChunkedAllocator, ChunkedBlock, FreeList,
+                 MemoryAllocator, MemoryError, MemorySubAllocator};
+
+type Backend = empty::Backend;
+
+static MEMORY: () = ();
+
+const FREE_LISTS: [FreeList; 3] = [FreeList::Queue, FreeList::Stack, FreeList::Ordered];
+
+const BLOCKS_PER_CHUNK: [usize; 3] = [8, 64, 512];
+
+const COUNT: usize = 1024;
+
+/// Block handed out by `MockOwner`.
+#[derive(Debug)]
+struct MockBlock {
+    range: Range<u64>,
+}
+
+impl Block for MockBlock {
+    type Memory = ();
+
+    fn memory(&self) -> &() {
+        &MEMORY
+    }
+
+    fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+}
+
+/// Owner that allocates consecutive ranges of a fake memory object.
+#[derive(Debug, Default)]
+struct MockOwner {
+    offset: u64,
+    used: u64,
+}
+
+impl MemoryAllocator<Backend> for MockOwner {
+    type Request = ();
+    type Block = MockBlock;
+
+    fn alloc(
+        &mut self,
+        _: &empty::Device,
+        _: (),
+        reqs: Requirements,
+    ) -> Result<MockBlock, MemoryError> {
+        let start = self.offset + alignment_shift(reqs.alignment, self.offset);
+        self.offset = start + reqs.size;
+        self.used += reqs.size;
+        Ok(MockBlock {
+            range: start..self.offset,
+        })
+    }
+
+    fn free(&mut self, _: &empty::Device, block: MockBlock) {
+        self.used -= block.size();
+    }
+
+    fn is_used(&self) -> bool {
+        self.used != 0
+    }
+
+    fn dispose(self, _: &empty::Device) -> Result<(), Self> {
+        if self.is_used() {
+            Err(self)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+struct Bench {
+    allocator: ChunkedAllocator<MockBlock>,
+    owner: MockOwner,
+}
+
+impl Bench {
+    fn new(blocks_per_chunk: usize, free_list: FreeList) -> Self {
+        Bench {
+            allocator: ChunkedAllocator::new(MemoryTypeId(0), blocks_per_chunk, 256, 1 << 24)
+                .with_free_list(free_list),
+            owner: MockOwner::default(),
+        }
+    }
+
+    fn alloc(&mut self, size: u64) -> ChunkedBlock<()> {
+        let reqs = Requirements {
+            type_mask: 1,
+            size,
+            alignment: 256,
+        };
+        MemorySubAllocator::<Backend, MockOwner>::alloc(
+            &mut self.allocator,
+            &mut self.owner,
+            &empty::Device,
+            (),
+            reqs,
+        ).unwrap()
+    }
+
+    fn free(&mut self, block: ChunkedBlock<()>) {
+        MemorySubAllocator::<Backend, MockOwner>::free(
+            &mut self.allocator,
+            &mut self.owner,
+            &empty::Device,
+            block,
+        )
+    }
+
+    fn shrink(&mut self) -> u64 {
+        self.allocator
+            .shrink::<Backend, MockOwner>(&mut self.owner, &empty::Device)
+    }
+
+    fn dispose(self) {
+        let mut owner = self.owner;
+        MemorySubAllocator::<Backend, MockOwner>::dispose(
+            self.allocator,
+            &mut owner,
+            &empty::Device,
+        ).unwrap();
+        owner.dispose(&empty::Device).unwrap();
+    }
+}
+
+/// Run `f` for every combination of tunables.
+fn configurations<F>(c: &mut Criterion, name: &str, f: F)
+where
+    F: Fn(&mut Bench) + Copy + 'static,
+{
+    for &blocks_per_chunk in &BLOCKS_PER_CHUNK {
+        for &free_list in &FREE_LISTS {
+            let id = format!("{}/{:?}/{}", name, free_list, blocks_per_chunk);
+            c.bench_function(&id, move |b| {
+                b.iter(|| {
+                    let mut bench = Bench::new(blocks_per_chunk, free_list);
+                    f(&mut bench);
+                    bench.dispose();
+                })
+            });
+        }
+    }
+}
+
+fn sequential(c: &mut Criterion) {
+    configurations(c, "sequential", |bench| {
+        let blocks = (0..COUNT).map(|_| bench.alloc(1024)).collect::<Vec<_>>();
+        for block in blocks {
+            bench.free(block);
+        }
+    });
+}
+
+fn mixed(c: &mut Criterion) {
+    configurations(c, "mixed", |bench| {
+        let blocks = (0..COUNT)
+            .map(|i| bench.alloc(256 << (i % 8)))
+            .collect::<Vec<_>>();
+        for block in blocks {
+            bench.free(block);
+        }
+    });
+}
+
+fn churn(c: &mut Criterion) {
+    configurations(c, "churn", |bench| {
+        let mut blocks = (0..64).map(|_| bench.alloc(1024)).collect::<Vec<_>>();
+        for i in 0..COUNT {
+            let block = blocks.swap_remove(i * 7 % blocks.len());
+            bench.free(block);
+            blocks.push(bench.alloc(1024));
+        }
+        for block in blocks {
+            bench.free(block);
+        }
+    });
+}
+
+fn bulk_free(c: &mut Criterion) {
+    configurations(c, "bulk_free", |bench| {
+        let blocks = (0..COUNT).map(|_| bench.alloc(1024)).collect::<Vec<_>>();
+        for block in blocks.into_iter().rev() {
+            bench.free(block);
+        }
+    });
+}
+
+fn decline(c: &mut Criterion) {
+    configurations(c, "decline", |bench| {
+        let mut blocks = (0..COUNT).map(|_| bench.alloc(1024)).collect::<Vec<_>>();
+        // Free most blocks while allocating a few, releasing empty chunks as usage declines.
+        for i in 0..COUNT / 2 {
+            let block = blocks.swap_remove(i * 7 % blocks.len());
+            bench.free(block);
+            let block = blocks.swap_remove(i * 13 % blocks.len());
+            bench.free(block);
+            blocks.push(bench.alloc(1024));
+            if i % 64 == 0 {
+                bench.shrink();
+            }
+        }
+        for block in blocks {
+            bench.free(block);
+        }
+    });
+}
+
+criterion_group!(benches, sequential, mixed, churn, bulk_free, decline);
+criterion_main!(benches);
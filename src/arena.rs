@@ -7,7 +7,7 @@ use std::ops::Range;
 use gfx_hal::{Backend, MemoryTypeId};
 use gfx_hal::memory::Requirements;
 
-use {alignment_shift, MemoryAllocator, MemoryError, MemorySubAllocator};
+use {alignment_shift, MemoryAllocator, MemoryError, MemorySubAllocator, MAX_MEMORY_TYPES};
 use block::{Block, RawBlock};
 
 /// Sub-allocator that can be used for short-lived objects.
@@ -38,7 +38,12 @@ impl <T> ArenaAllocator<T> {
     /// - `chunk_size`: The minimum size of the chunks allocated from the underlying allocator
     ///                 in bytes. All memory is allocated in increments of `chunk_size`.
     /// - `id`: ID of the memory type this allocator allocates from.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `id` is not less than `MAX_MEMORY_TYPES`.
     pub fn new(id: MemoryTypeId, chunk_size: u64) -> Self {
+        assert!(id.0 < MAX_MEMORY_TYPES, "Memory type {:?} is out of range", id);
         ArenaAllocator {
             id,
             chunk_size,
@@ -0,0 +1,121 @@
+use std::any::Any;
+use std::fmt::Debug;
+use std::ops::Range;
+
+use alignment_shift;
+use block::Block;
+use chunked::ChunkedBlock;
+
+/// Block of a `ChunkedAllocator` that memory is bump-allocated from, as returned by
+/// `ChunkedAllocator::alloc_bump`.
+///
+/// Bumping has no bookkeeping per allocation, which makes it suitable for many tiny transient
+/// allocations. Allocations can't be freed one by one, instead the whole block is returned to
+/// the allocator at once by freeing `into_block`.
+///
+/// ### Type parameters:
+///
+/// - `M`: hal memory type.
+#[derive(Debug)]
+pub struct BumpChunk<M> {
+    block: ChunkedBlock<M>,
+    /// Offset of the first free byte relative to the start of the block
+    offset: u64,
+}
+
+impl<M> BumpChunk<M>
+where
+    M: Debug + Any,
+{
+    pub(crate) fn new(block: ChunkedBlock<M>) -> Self {
+        BumpChunk { block, offset: 0 }
+    }
+
+    /// Allocate `size` bytes aligned to `alignment` after the previous allocation.
+    ///
+    /// ### Returns
+    ///
+    /// The range of the allocation in the memory object, or `None` if the rest of the block is
+    /// too small.
+    pub fn alloc(&mut self, size: u64, alignment: u64) -> Option<Range<u64>> {
+        let range = self.block.range();
+        let start = range.start + self.offset;
+        let start = start.checked_add(alignment_shift(alignment, start))?;
+        let end = start.checked_add(size)?;
+        if end > range.end {
+            return None;
+        }
+        self.offset = end - range.start;
+        Some(start..end)
+    }
+
+    /// Get the number of bytes after the previous allocation.
+    pub fn remaining(&self) -> u64 {
+        self.block.size() - self.offset
+    }
+
+    /// Make the whole block available again.
+    /// All memory allocated from it so far must no longer be used.
+    pub fn reset(&mut self) {
+        self.offset = 0;
+    }
+
+    /// Take the block to free it to the `ChunkedAllocator` it was allocated from.
+    pub fn into_block(self) -> ChunkedBlock<M> {
+        self.block
+    }
+}
+
+impl<M> Block for BumpChunk<M>
+where
+    M: Debug + Any,
+{
+    type Memory = M;
+
+    #[inline]
+    fn memory(&self) -> &M {
+        self.block.memory()
+    }
+
+    #[inline]
+    fn range(&self) -> Range<u64> {
+        self.block.range()
+    }
+}
+
+#[test]
+fn test_bump() {
+    extern crate gfx_backend_empty as empty;
+
+    use gfx_hal::MemoryTypeId;
+    use gfx_hal::memory::Requirements;
+
+    use MemorySubAllocator;
+    use chunked::ChunkedAllocator;
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 4, 256, 1 << 16);
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 256,
+        alignment: 1,
+    };
+    let mut bump = allocator
+        .alloc_bump::<empty::Backend, _>(&mut owner, &device, (), reqs)
+        .unwrap();
+    let start = bump.range().start;
+
+    assert_eq!(bump.alloc(10, 1), Some(start..start + 10));
+    assert_eq!(bump.alloc(10, 16), Some(start + 16..start + 26));
+    assert_eq!(bump.remaining(), 230);
+    assert_eq!(bump.alloc(231, 1), None);
+    assert_eq!(bump.alloc(230, 1), Some(start + 26..start + 256));
+    bump.reset();
+    assert_eq!(bump.alloc(256, 256), Some(start..start + 256));
+
+    let block = bump.into_block();
+    MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
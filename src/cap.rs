@@ -0,0 +1,123 @@
+use gfx_hal::{Backend, MemoryTypeId};
+use gfx_hal::memory::Requirements;
+
+use {MemoryAllocator, MemoryError};
+
+/// Allocator that rejects allocations larger than a fixed size.
+///
+/// Allocations with `reqs.size` above `max_single` fail with `MemoryError::TooLarge` without
+/// reaching the wrapped allocator, smaller ones are forwarded. Unlike the maximum chunk size of
+/// `ChunkedAllocator` this is a pure policy that works with any allocator, e.g. to catch a
+/// runaway allocation caused by broken content before it exhausts a pool.
+///
+/// ### Type parameters:
+///
+/// - `A`: wrapped allocator
+#[derive(Debug)]
+pub struct CapAllocator<A> {
+    inner: A,
+    max_single: u64,
+}
+
+impl<A> CapAllocator<A> {
+    /// Wrap an allocator.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `inner`: allocator to allocate from
+    /// - `max_single`: largest size of a single allocation in bytes
+    pub fn new(inner: A, max_single: u64) -> Self {
+        CapAllocator { inner, max_single }
+    }
+
+    /// Get the largest size of a single allocation in bytes.
+    pub fn max_single(&self) -> u64 {
+        self.max_single
+    }
+
+    /// Get the wrapped allocator.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Get the wrapped allocator mutably.
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.inner
+    }
+
+    /// Take the wrapped allocator.
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+}
+
+impl<B, A> MemoryAllocator<B> for CapAllocator<A>
+where
+    B: Backend,
+    A: MemoryAllocator<B>,
+{
+    type Request = A::Request;
+    type Block = A::Block;
+
+    fn alloc(
+        &mut self,
+        device: &B::Device,
+        request: A::Request,
+        reqs: Requirements,
+    ) -> Result<A::Block, MemoryError> {
+        if reqs.size > self.max_single {
+            return Err(MemoryError::TooLarge);
+        }
+        self.inner.alloc(device, request, reqs)
+    }
+
+    fn free(&mut self, device: &B::Device, block: A::Block) {
+        self.inner.free(device, block)
+    }
+
+    fn block_memory_type(&self, block: &A::Block) -> Option<MemoryTypeId> {
+        self.inner.block_memory_type(block)
+    }
+
+    fn instance_id(&self) -> Option<usize> {
+        self.inner.instance_id()
+    }
+
+    fn is_used(&self) -> bool {
+        self.inner.is_used()
+    }
+
+    fn dispose(self, device: &B::Device) -> Result<(), Self> {
+        let max_single = self.max_single;
+        self.inner
+            .dispose(device)
+            .map_err(|inner| CapAllocator { inner, max_single })
+    }
+}
+
+#[test]
+fn test_cap() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut allocator = CapAllocator::new(DryRunAllocator::<()>::new(), 1024);
+    let reqs = |size| Requirements {
+        type_mask: 1,
+        size,
+        alignment: 1,
+    };
+
+    match MemoryAllocator::<empty::Backend>::alloc(&mut allocator, &device, (), reqs(1025)) {
+        Err(MemoryError::TooLarge) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+    assert_eq!(allocator.inner().allocations(), 0);
+
+    let block = MemoryAllocator::<empty::Backend>::alloc(&mut allocator, &device, (), reqs(1024))
+        .unwrap();
+    assert_eq!(allocator.inner().allocations(), 1);
+    MemoryAllocator::<empty::Backend>::free(&mut allocator, &device, block);
+    MemoryAllocator::<empty::Backend>::dispose(allocator, &device).unwrap();
+}
@@ -1,18 +1,26 @@
 use std::any::Any;
-use std::cmp::{max, min};
-use std::collections::VecDeque;
-use std::fmt::Debug;
+use std::cmp::{max, min, Reverse};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::error::Error;
+use std::fmt::{self, Debug};
 use std::ops::Range;
 
-use gfx_hal::{Backend, MemoryTypeId};
+#[cfg(feature = "debug-backtrace")]
+use backtrace::Backtrace;
+use gfx_hal::{Backend, Limits, MemoryTypeId};
+use gfx_hal::buffer::Usage;
 use gfx_hal::memory::Requirements;
 
-use {alignment_shift, MemoryAllocator, MemoryError, MemorySubAllocator};
+use {alignment_shift, next_instance_id, MemoryAllocator, MemoryError, MemorySubAllocator,
+     MAX_MEMORY_TYPES};
 use block::{Block, RawBlock};
+use bump::BumpChunk;
+#[cfg(feature = "registry")]
+use registry::Registration;
 
 /// Chunks are super-allocator blocks,
 /// which are then divided into smaller 'blocks'
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 struct FreeBlock {
     /// Index of chunk (big block from super-allocator)
     chunk_index: usize,
@@ -20,6 +28,170 @@ struct FreeBlock {
     block_index: u64,
 }
 
+/// Container used by `ChunkedAllocator` to keep track of free blocks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FreeList {
+    /// Free blocks are kept in a `VecDeque`. Freed blocks are reused first.
+    Queue,
+
+    /// Free blocks are kept in a `Vec` used as a stack. Freed blocks are reused first.
+    /// Cheaper than `Queue` for slab-like usage.
+    Stack,
+
+    /// Free blocks are kept in a `BTreeSet`. The block with the lowest chunk index and offset is
+    /// always allocated first.
+    /// This concentrates used blocks in the first chunks, so `shrink` can free the later ones.
+    Ordered,
+}
+
+impl Default for FreeList {
+    fn default() -> Self {
+        FreeList::Queue
+    }
+}
+
+#[derive(Debug)]
+enum FreeBlocks {
+    Queue(VecDeque<FreeBlock>),
+    Stack(Vec<FreeBlock>),
+    Ordered(BTreeSet<FreeBlock>),
+}
+
+impl FreeBlocks {
+    fn new(free_list: FreeList) -> Self {
+        match free_list {
+            FreeList::Queue => FreeBlocks::Queue(VecDeque::new()),
+            FreeList::Stack => FreeBlocks::Stack(Vec::new()),
+            FreeList::Ordered => FreeBlocks::Ordered(BTreeSet::new()),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match *self {
+            FreeBlocks::Queue(ref free) => free.len(),
+            FreeBlocks::Stack(ref free) => free.len(),
+            FreeBlocks::Ordered(ref free) => free.len(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the free blocks in the order they are allocated.
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = &'a FreeBlock> + 'a> {
+        match *self {
+            FreeBlocks::Queue(ref free) => Box::new(free.iter()),
+            FreeBlocks::Stack(ref free) => Box::new(free.iter().rev()),
+            FreeBlocks::Ordered(ref free) => Box::new(free.iter()),
+        }
+    }
+
+    /// Take the block to allocate next.
+    fn pop(&mut self) -> Option<FreeBlock> {
+        match *self {
+            FreeBlocks::Queue(ref mut free) => free.pop_front(),
+            FreeBlocks::Stack(ref mut free) => free.pop(),
+            FreeBlocks::Ordered(ref mut free) => free.pop_first(),
+        }
+    }
+
+    /// Get the block `pop` would take.
+    fn first(&self) -> Option<FreeBlock> {
+        match *self {
+            FreeBlocks::Queue(ref free) => free.front().cloned(),
+            FreeBlocks::Stack(ref free) => free.last().cloned(),
+            FreeBlocks::Ordered(ref free) => free.iter().next().cloned(),
+        }
+    }
+
+    /// Get the block `pop_last` would take.
+    fn last(&self) -> Option<FreeBlock> {
+        match *self {
+            FreeBlocks::Ordered(ref free) => free.iter().next_back().cloned(),
+            _ => self.iter().max().cloned(),
+        }
+    }
+
+    /// Take the free block with the highest chunk index and offset.
+    fn pop_last(&mut self) -> Option<FreeBlock> {
+        match *self {
+            FreeBlocks::Queue(ref mut free) => {
+                let index = (0..free.len()).max_by_key(|&index| free[index])?;
+                free.remove(index)
+            }
+            FreeBlocks::Stack(ref mut free) => {
+                let index = (0..free.len()).max_by_key(|&index| free[index])?;
+                Some(free.remove(index))
+            }
+            FreeBlocks::Ordered(ref mut free) => free.pop_last(),
+        }
+    }
+
+    /// Return a freed block.
+    fn push(&mut self, free_block: FreeBlock) {
+        match *self {
+            FreeBlocks::Queue(ref mut free) => free.push_front(free_block),
+            FreeBlocks::Stack(ref mut free) => free.push(free_block),
+            FreeBlocks::Ordered(ref mut free) => {
+                free.insert(free_block);
+            }
+        }
+    }
+
+    /// Add blocks of a new chunk. They are allocated in the order given.
+    fn extend<I>(&mut self, blocks: I)
+    where
+        I: DoubleEndedIterator<Item = FreeBlock>,
+    {
+        match *self {
+            FreeBlocks::Queue(ref mut free) => free.extend(blocks),
+            FreeBlocks::Stack(ref mut free) => free.extend(blocks.rev()),
+            FreeBlocks::Ordered(ref mut free) => free.extend(blocks),
+        }
+    }
+
+    fn remove(&mut self, free_block: &FreeBlock) -> bool {
+        match *self {
+            FreeBlocks::Queue(ref mut free) => match free.iter().position(|f| f == free_block) {
+                Some(index) => free.remove(index).is_some(),
+                None => false,
+            },
+            FreeBlocks::Stack(ref mut free) => match free.iter().position(|f| f == free_block) {
+                Some(index) => {
+                    free.remove(index);
+                    true
+                }
+                None => false,
+            },
+            FreeBlocks::Ordered(ref mut free) => free.remove(free_block),
+        }
+    }
+
+    /// Replace the free blocks, keeping the kind of container. The blocks are kept in the order
+    /// of `iter`.
+    #[cfg(feature = "testing")]
+    fn assign(&mut self, blocks: &[FreeBlock]) {
+        let blocks = blocks.iter().cloned();
+        match *self {
+            FreeBlocks::Queue(ref mut free) => *free = blocks.collect(),
+            FreeBlocks::Stack(ref mut free) => *free = blocks.rev().collect(),
+            FreeBlocks::Ordered(ref mut free) => *free = blocks.collect(),
+        }
+    }
+
+    fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&FreeBlock) -> bool,
+    {
+        match *self {
+            FreeBlocks::Queue(ref mut free) => free.retain(f),
+            FreeBlocks::Stack(ref mut free) => free.retain(f),
+            FreeBlocks::Ordered(ref mut free) => free.retain(f),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct ChunkedNode<T> {
     id: MemoryTypeId,
@@ -27,31 +199,131 @@ struct ChunkedNode<T> {
     chunk_size: u64,
     /// Size of small blocks
     block_size: u64,
+    /// Use all blocks that fit into chunks larger than `chunk_size`
+    fill_chunks: bool,
+    /// Number of blocks in allocated chunks
+    blocks: usize,
     /// List of free blocks
-    free: VecDeque<FreeBlock>,
-    /// List of allocated chunks
-    chunks: Vec<T>,
+    free: FreeBlocks,
+    /// Used blocks planned to be relocated by defragmentation, with their target blocks
+    moving: BTreeMap<FreeBlock, FreeBlock>,
+    /// Target blocks of planned relocations, with the blocks relocated into them
+    targets: BTreeMap<FreeBlock, FreeBlock>,
+    /// Number of blocks covered by used blocks that cover more than one, by first block
+    spans: BTreeMap<FreeBlock, usize>,
+    /// Number of times each block was freed
+    #[cfg(feature = "checks")]
+    generations: ::std::collections::BTreeMap<FreeBlock, u64>,
+    /// List of allocated chunks. Freed chunks leave an empty slot behind so indices of the
+    /// remaining chunks stay valid.
+    chunks: Vec<Option<T>>,
+    /// Number of blocks of each chunk that are not free, including blocks freed with
+    /// `ChunkedAllocator::free_deferred`
+    used_blocks: Vec<usize>,
+    /// Number of chunks allocated from super-allocator so far
+    grows: u64,
+    /// Highest number of chunks allocated at once
+    peak_chunks: usize,
+    /// Number of blocks handed out so far
+    allocations: u64,
+    /// Number of blocks handed out and not freed yet
+    outstanding: usize,
+    /// Allocations, frees and grows over the lifetime of the node, unlike the above counters
+    /// not restored with the state
+    churn: ChurnStats,
+    /// Frame set with `ChunkedAllocator::set_frame`
+    frame: u64,
+    /// Time the next access is stamped with if LRU tracking is enabled, brought up to the
+    /// latest time of all nodes by the allocator before the node is used
+    clock: usize,
+    /// Access times and frames of used blocks if LRU tracking or frame stamping is enabled
+    stamps: Stamps,
+    /// Node should grow ahead of the next allocation
+    prefetch: bool,
+    /// Number of unused chunks kept by `shrink`
+    min_free_chunks: usize,
+    /// Number of unused chunks above which `free` shrinks the node
+    max_free_chunks: Option<usize>,
+    /// Blocks were freed since the last `shrink`
+    reclaim: bool,
+    /// Allocate the free block with the highest chunk index and offset first
+    from_end: bool,
+    /// Number of colors allocations rotate through, see `ChunkedAllocator::with_coloring`
+    colors: u64,
+    /// Color of the block to allocate next
+    next_color: u64,
+    /// Index of the node in the `ChunkedAllocator`
+    #[cfg(feature = "checks")]
+    index: u8,
+    /// Span and call stack of the allocation of each used block
+    #[cfg(feature = "debug-backtrace")]
+    backtraces: BTreeMap<FreeBlock, (usize, Backtrace)>,
 }
 
 impl<T> ChunkedNode<T> {
-    fn new(id: MemoryTypeId, chunk_size: u64, block_size: u64) -> Self {
+    fn new(
+        id: MemoryTypeId,
+        chunk_size: u64,
+        block_size: u64,
+        free_list: FreeList,
+        fill_chunks: bool,
+    ) -> Self {
+        assert!(id.0 < MAX_MEMORY_TYPES, "Memory type {:?} is out of range", id);
         ChunkedNode {
             id,
             chunk_size,
             block_size,
-            free: VecDeque::new(),
+            fill_chunks,
+            blocks: 0,
+            free: FreeBlocks::new(free_list),
+            moving: BTreeMap::new(),
+            targets: BTreeMap::new(),
+            spans: BTreeMap::new(),
+            #[cfg(feature = "checks")]
+            generations: ::std::collections::BTreeMap::new(),
             chunks: Vec::new(),
+            used_blocks: Vec::new(),
+            grows: 0,
+            peak_chunks: 0,
+            allocations: 0,
+            outstanding: 0,
+            churn: ChurnStats::default(),
+            frame: 0,
+            clock: 0,
+            stamps: Stamps::default(),
+            prefetch: false,
+            min_free_chunks: 0,
+            max_free_chunks: None,
+            reclaim: false,
+            from_end: false,
+            colors: 1,
+            next_color: 0,
+            #[cfg(feature = "checks")]
+            index: 0,
+            #[cfg(feature = "debug-backtrace")]
+            backtraces: BTreeMap::new(),
         }
     }
 
     fn is_used(&self) -> bool {
-        // All blocks are free
-        self.count() != self.free.len()
+        // Not all blocks are free
+        self.free.len() < self.count()
     }
 
     fn count(&self) -> usize {
-        // Blocks count is chunk count multiplied by blocks per chunk
-        self.chunks.len() * self.blocks_per_chunk()
+        self.blocks
+    }
+
+    fn chunk_count(&self) -> usize {
+        self.chunks.iter().filter(|chunk| chunk.is_some()).count()
+    }
+
+    fn chunk(&self, chunk_index: usize) -> &T {
+        self.chunks[chunk_index].as_ref().expect("Chunk is freed")
+    }
+
+    fn has_free(&self) -> bool {
+        !self.free.is_empty()
     }
 
     fn blocks_per_chunk(&self) -> usize {
@@ -59,20 +331,39 @@ impl<T> ChunkedNode<T> {
         (self.chunk_size / self.block_size) as usize
     }
 
+    /// Get the number of blocks the chunk is divided into.
+    fn chunk_blocks(&self, chunk_index: usize) -> usize
+    where
+        T: Block,
+    {
+        if self.fill_chunks {
+            (self.chunk(chunk_index).size() / self.block_size) as usize
+        } else {
+            self.blocks_per_chunk()
+        }
+    }
+
     fn used(&self) -> u64 {
         (self.count() - self.free.len()) as u64 * self.block_size
     }
 
     fn allocated(&self) -> u64 {
-        self.chunks.len() as u64 * self.chunk_size
+        if self.fill_chunks {
+            // Chunks may be larger than `chunk_size`, and all of their blocks are used
+            self.count() as u64 * self.block_size
+        } else {
+            self.chunk_count() as u64 * self.chunk_size
+        }
     }
 
-    fn grow<B, A>(
+    /// Allocate a new chunk from super-allocator without adding its blocks to the free list.
+    /// Returns index of the new chunk.
+    fn alloc_chunk<B, A>(
         &mut self,
         owner: &mut A,
         device: &B::Device,
         request: A::Request,
-    ) -> Result<(), MemoryError>
+    ) -> Result<usize, MemoryError>
     where
         B: Backend,
         T: Block<Memory = B::Memory>,
@@ -81,261 +372,1044 @@ impl<T> ChunkedNode<T> {
         let reqs = Requirements {
             type_mask: 1 << self.id.0,
             size: self.chunk_size,
-            alignment: self.block_size,
+            // Largest power of two dividing the block size
+            alignment: self.block_size & self.block_size.wrapping_neg(),
         };
         // Get a new chunk
         let chunk = owner.alloc(device, request, reqs)?;
         assert_eq!(0, alignment_shift(reqs.alignment, chunk.range().start));
         assert!(chunk.size() >= self.chunk_size);
+        if let Some(id) = owner.block_memory_type(&chunk) {
+            assert_eq!(id, self.id, "Underlying allocator returned memory of wrong type");
+        }
+
+        let chunk_index = self.place_chunk(chunk);
+        self.grows += 1;
+        self.churn.grows += 1;
+
+        Ok(chunk_index)
+    }
+
+    /// Add a chunk to the list without adding its blocks to the free list.
+    /// Returns index of the chunk.
+    fn place_chunk(&mut self, chunk: T) -> usize
+    where
+        T: Block,
+    {
+        // Reuse a slot of a freed chunk or append a new one
+        let chunk_index = self.chunks
+            .iter()
+            .position(|chunk| chunk.is_none())
+            .unwrap_or(self.chunks.len());
 
-        let blocks_per_chunk = self.blocks_per_chunk();
+        // Place the new chunk in the list
+        if chunk_index == self.chunks.len() {
+            self.chunks.push(Some(chunk));
+        } else {
+            self.chunks[chunk_index] = Some(chunk);
+        }
+        self.blocks += self.chunk_blocks(chunk_index);
+        self.peak_chunks = max(self.peak_chunks, self.chunk_count());
+        chunk_index
+    }
 
-        // `len()` will return the next index to use
-        let chunk_index = self.chunks.len();
+    fn grow<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        request: A::Request,
+    ) -> Result<(), MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        let chunk_index = self.alloc_chunk(owner, device, request)?;
+        let blocks = self.chunk_blocks(chunk_index);
 
         // Fill the free list with new blocks
-        self.free.extend((0..blocks_per_chunk).map(|i| FreeBlock {
+        self.free.extend((0..blocks).map(|i| FreeBlock {
             chunk_index,
             block_index: i as u64,
         }));
 
-        // Place the new chunk in the list
-        self.chunks.push(chunk);
-
         Ok(())
     }
 
-    fn alloc_no_grow<M>(&mut self) -> Option<ChunkedBlock<M>>
+    /// Allocate a new chunk and return a single block spanning all of its blocks.
+    fn alloc_whole_chunk<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        request: A::Request,
+    ) -> Result<ChunkedBlock<B::Memory>, MemoryError>
     where
-        M: Debug + Any,
-        T: Block<Memory = M>,
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
     {
-        // Find a free block
-        self.free.pop_front().map(|free_block| {
-            // Memory offset is block index times block size
-            // plus chunk memory offset
-            let offset = free_block.block_index * self.block_size
-                + self.chunks[free_block.chunk_index].range().start;
-            let block = RawBlock::new(
-                self.chunks[free_block.chunk_index].memory(),
-                offset..self.block_size + offset,
-            );
-            // Remember what chunk the block came from
-            ChunkedBlock(block, free_block.chunk_index)
-        })
+        let chunk_index = self.alloc_chunk(owner, device, request)?;
+        let span = self.chunk_blocks(chunk_index);
+        let chunk = self.chunk(chunk_index);
+        let offset = chunk.range().start;
+        let block = RawBlock::new(chunk.memory(), offset..span as u64 * self.block_size + offset);
+        let tag = self.tag(
+            FreeBlock {
+                chunk_index,
+                block_index: 0,
+            },
+            span,
+        );
+        Ok(ChunkedBlock(block, tag))
     }
-}
 
-impl<B, O, T> MemorySubAllocator<B, O> for ChunkedNode<T>
-where
-    B: Backend,
-    T: Block<Memory = B::Memory>,
-    O: MemoryAllocator<B, Block = T>,
-{
-    type Request = O::Request;
-    type Block = ChunkedBlock<B::Memory>;
+    /// Free all chunks which have no used blocks.
+    /// Returns the total size of freed chunks.
+    fn shrink<B, A>(&mut self, owner: &mut A, device: &B::Device) -> u64
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        self.reclaim = false;
+        let keep = self.min_free_chunks;
+        self.shrink_keeping(owner, device, keep)
+    }
 
-    fn alloc(
+    /// Free all chunks which have no used blocks but the first `keep` of them.
+    /// Returns the total size of freed chunks.
+    fn shrink_keeping<B, A>(&mut self, owner: &mut A, device: &B::Device, mut keep: usize) -> u64
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        // Count free blocks of each chunk
+        let mut free = vec![0; self.chunks.len()];
+        for free_block in self.free.iter() {
+            free[free_block.chunk_index] += 1;
+        }
+
+        let mut freed = 0;
+        for (chunk_index, count) in free.into_iter().enumerate() {
+            if self.chunks[chunk_index].is_some() && count == self.chunk_blocks(chunk_index) {
+                if keep > 0 {
+                    keep -= 1;
+                    continue;
+                }
+                freed += self.release_chunk(owner, device, chunk_index);
+            }
+        }
+        self.trim_chunks();
+        freed
+    }
+
+    /// Free all chunks but the first one. None of the blocks may be used.
+    /// Returns the total size of freed chunks.
+    fn soft_reset<B, A>(&mut self, owner: &mut A, device: &B::Device) -> u64
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        debug_assert!(!self.is_used());
+        let chunk_indices = (0..self.chunks.len())
+            .filter(|&chunk_index| self.chunks[chunk_index].is_some())
+            .skip(1)
+            .collect::<Vec<_>>();
+
+        self.reclaim = false;
+        let mut freed = 0;
+        for chunk_index in chunk_indices {
+            freed += self.release_chunk(owner, device, chunk_index);
+        }
+        self.trim_chunks();
+        freed
+    }
+
+    /// Free the chunk with specified index if none of its blocks are used.
+    fn free_chunk<B, A>(
         &mut self,
-        owner: &mut O,
+        owner: &mut A,
         device: &B::Device,
-        request: O::Request,
-        reqs: Requirements,
-    ) -> Result<ChunkedBlock<B::Memory>, MemoryError> {
-        // Check memory type
-        if (1 << self.id.0) & reqs.type_mask == 0 {
-            return Err(MemoryError::NoCompatibleMemoryType);
+        chunk_index: usize,
+    ) -> Result<(), MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        let chunk = self.take_chunk(chunk_index)?;
+        owner.free(device, chunk);
+        Ok(())
+    }
+
+    /// Return unused chunk to the super-allocator, removing its blocks from the free list.
+    /// Returns the size of the chunk.
+    fn release_chunk<B, A>(&mut self, owner: &mut A, device: &B::Device, chunk_index: usize) -> u64
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        let chunk = self.remove_chunk(chunk_index);
+        let size = chunk.size();
+        owner.free(device, chunk);
+        size
+    }
+
+    /// Remove chunk from the list, removing its blocks from the free list.
+    fn remove_chunk(&mut self, chunk_index: usize) -> T
+    where
+        T: Block,
+    {
+        self.blocks -= self.chunk_blocks(chunk_index);
+        let chunk = self.chunks[chunk_index].take().expect("Chunk is freed");
+        if let Some(used) = self.used_blocks.get_mut(chunk_index) {
+            *used = 0;
         }
+        self.free
+            .retain(|free_block| free_block.chunk_index != chunk_index);
+        chunk
+    }
 
-        // Try to allocate a block
-        let block = match self.alloc_no_grow() {
-            Some(block) => block,
-            None => {
-                // Grow from super-allocator
-                self.grow(owner, device, request)?;
-                self.alloc_no_grow().expect("Just growed")
-            }
+    /// Remove the chunk with specified index if none of its blocks are used, without freeing
+    /// it.
+    fn take_chunk(&mut self, chunk_index: usize) -> Result<T, MemoryError>
+    where
+        T: Block,
+    {
+        // Panics if there is no such chunk
+        self.chunk(chunk_index);
+        let free = self.free
+            .iter()
+            .filter(|free_block| free_block.chunk_index == chunk_index)
+            .count();
+        if free != self.chunk_blocks(chunk_index) {
+            return Err(MemoryError::ChunkInUse);
+        }
+        let chunk = self.remove_chunk(chunk_index);
+        self.trim_chunks();
+        Ok(chunk)
+    }
+
+    /// Remove the chunk with specified index without freeing it, together with the state of
+    /// its free blocks and of the used blocks starting at `used`.
+    fn take_moved_chunk(&mut self, chunk_index: usize, used: &[FreeBlock]) -> MovedChunk<T>
+    where
+        T: Block,
+    {
+        let free = self.free
+            .iter()
+            .filter(|free_block| free_block.chunk_index == chunk_index)
+            .map(|free_block| free_block.block_index)
+            .collect();
+        let used = used.iter()
+            .map(|first| MovedBlock {
+                block_index: first.block_index,
+                span: self.spans.remove(first),
+                stamp: self.stamps.take(first),
+                #[cfg(feature = "debug-backtrace")]
+                backtrace: self.backtraces.remove(first),
+            })
+            .collect::<Vec<_>>();
+        self.outstanding -= used.len();
+        // Relocations from or into the chunk are forgotten
+        self.moving.retain(|source, target| {
+            source.chunk_index != chunk_index && target.chunk_index != chunk_index
+        });
+        self.targets.retain(|target, source| {
+            source.chunk_index != chunk_index && target.chunk_index != chunk_index
+        });
+        #[cfg(feature = "checks")]
+        let generations = {
+            let moved = self.generations
+                .iter()
+                .filter(|&(free_block, _)| free_block.chunk_index == chunk_index)
+                .map(|(free_block, &generation)| (free_block.block_index, generation))
+                .collect::<Vec<_>>();
+            self.generations
+                .retain(|free_block, _| free_block.chunk_index != chunk_index);
+            moved
         };
+        let chunk = self.remove_chunk(chunk_index);
+        self.trim_chunks();
+        MovedChunk {
+            chunk,
+            free,
+            used,
+            #[cfg(feature = "checks")]
+            generations,
+        }
+    }
 
-        // Check that block meets the requirements.
-        assert!(block.size() >= reqs.size);
-        assert_eq!(block.range().start & (reqs.alignment - 1), 0);
-        Ok(block)
+    /// Add a chunk taken from another node of the same configuration by `take_moved_chunk`.
+    /// Returns index of the chunk.
+    fn put_moved_chunk(&mut self, moved: MovedChunk<T>) -> usize
+    where
+        T: Block,
+    {
+        let MovedChunk {
+            chunk,
+            free,
+            mut used,
+            #[cfg(feature = "checks")]
+            generations,
+        } = moved;
+        let chunk_index = self.place_chunk(chunk);
+        let used_blocks = self.chunk_blocks(chunk_index) - free.len();
+        self.count_used(chunk_index, used_blocks);
+        self.free.extend(free.into_iter().map(|block_index| FreeBlock {
+            chunk_index,
+            block_index,
+        }));
+        #[cfg(feature = "checks")]
+        self.generations.extend(generations.into_iter().map(|(block_index, generation)| {
+            let free_block = FreeBlock {
+                chunk_index,
+                block_index,
+            };
+            (free_block, generation)
+        }));
+        self.outstanding += used.len();
+        // Accesses are stamped anew in the order they happened in the other node
+        used.sort_by_key(|block| block.stamp.and_then(|stamp| stamp.access));
+        for block in used {
+            let first = FreeBlock {
+                chunk_index,
+                block_index: block.block_index,
+            };
+            if let Some(span) = block.span {
+                self.spans.insert(first, span);
+            }
+            if let Some(stamp) = block.stamp {
+                if let (true, Some(frame)) = (self.stamps.frames, stamp.frame) {
+                    self.stamps.blocks.entry(first).or_default().frame = Some(frame);
+                }
+                if stamp.access.is_some() {
+                    self.touch(first);
+                }
+            }
+            #[cfg(feature = "debug-backtrace")]
+            {
+                if let Some(backtrace) = block.backtrace {
+                    self.backtraces.insert(first, backtrace);
+                }
+            }
+        }
+        chunk_index
     }
 
-    fn free(&mut self, _owner: &mut O, _device: &B::Device, block: ChunkedBlock<B::Memory>) {
-        assert_eq!(block.range().start % self.block_size, 0);
-        assert_eq!(block.size(), self.block_size);
-        let offset = block.range().start;
-        let block_memory: *const B::Memory = block.memory();
+    /// Check if the block is the only used block of its chunk.
+    fn is_sole_user<M>(&self, block: &ChunkedBlock<M>) -> bool
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        let chunk_index = self.first_block(block).chunk_index;
+        let free = self.free
+            .iter()
+            .filter(|free_block| free_block.chunk_index == chunk_index)
+            .count();
+        free + block.1.span == self.chunk_blocks(chunk_index)
+    }
 
-        // Dispose block retreiving chunk index
-        let chunk_index = unsafe {
-            block.0.dispose();
-            block.1
-        };
+    /// Remove the chunk of the block, which must be the only used block of it, without freeing it.
+    /// Returns the chunk and the range of the block.
+    fn detach<M>(&mut self, block: ChunkedBlock<M>) -> (T, Range<u64>)
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        debug_assert!(self.is_sole_user(&block));
+        let range = block.range();
+        let (first, _) = self.take_block(block);
+        let chunk = self.remove_chunk(first.chunk_index);
+        self.trim_chunks();
+        (chunk, range)
+    }
 
-        // Confirm the chunk index
-        assert!(::std::ptr::eq(
-            self.chunks[chunk_index].memory(),
-            block_memory
-        ));
+    /// Count `span` blocks of the chunk as used.
+    fn count_used(&mut self, chunk_index: usize, span: usize) {
+        if self.used_blocks.len() <= chunk_index {
+            self.used_blocks.resize(chunk_index + 1, 0);
+        }
+        self.used_blocks[chunk_index] += span;
+    }
 
-        // Calculate the block index inside the chunk
-        let block_index = (offset - self.chunks[chunk_index].range().start) / self.block_size;
+    /// Get the number of allocated chunks without used blocks.
+    fn unused_chunks(&self) -> usize {
+        (0..self.chunks.len())
+            .filter(|&chunk_index| {
+                self.chunks[chunk_index].is_some()
+                    && self.used_blocks.get(chunk_index).cloned().unwrap_or(0) == 0
+            })
+            .count()
+    }
 
-        // Push the block back into the 'free blocks' list
-        self.free.push_front(FreeBlock {
-            block_index,
-            chunk_index,
-        });
+    /// Drop trailing empty slots.
+    fn trim_chunks(&mut self) {
+        while self.chunks.last().map(|chunk| chunk.is_none()).unwrap_or(false) {
+            self.chunks.pop();
+        }
     }
 
-    fn dispose(mut self, owner: &mut O, device: &B::Device) -> Result<(), Self> {
-        if self.is_used() {
-            Err(self)
+    fn alloc_no_grow<M>(&mut self) -> Option<ChunkedBlock<M>>
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        // Find a free block
+        let free_block = if self.colors > 1 {
+            self.pop_colored()?
+        } else if self.from_end {
+            self.free.pop_last()?
         } else {
-            for chunk in self.chunks.drain(..) {
-                owner.free(device, chunk);
+            self.free.pop()?
+        };
+        Some(self.block(free_block))
+    }
+
+    /// Get the free block `alloc_no_grow` would take next.
+    fn next_free(&self) -> Option<FreeBlock>
+    where
+        T: Block,
+    {
+        if self.colors > 1 {
+            let colored = self.free
+                .iter()
+                .find(|free_block| self.color(free_block) == self.next_color);
+            if let Some(&free_block) = colored {
+                return Some(free_block);
             }
-            Ok(())
+        }
+        if self.from_end {
+            self.free.last()
+        } else {
+            self.free.first()
         }
     }
-}
 
-/// Sub-allocator that can be used for long-lived objects.
-///
-/// This allocator allocates memory in chunks containing `blocks_per_chunk` equally sized blocks
-/// from the underlying allocator, up to a maximum chunk size of `max_chunk_size` bytes. It rounds
-/// up the requested allocation size to the closest power of two and returns a single block from a
-/// chunk.
-///
-/// This allocator can only allocate memory `max_chunk_size` bytes in size or less.
-///
-/// ### Type parameters:
-///
-/// - `T`: type of bigger blocks this allocator sub-allocates from.
-#[derive(Debug)]
-pub struct ChunkedAllocator<T> {
-    id: MemoryTypeId,
-    blocks_per_chunk: usize,
-    min_block_size: u64,
-    max_chunk_size: u64,
-    nodes: Vec<ChunkedNode<T>>,
-}
+    /// Get the color of the free block, which is its offset in the memory object in blocks
+    /// modulo the number of colors.
+    fn color(&self, free_block: &FreeBlock) -> u64
+    where
+        T: Block,
+    {
+        let offset = self.chunk(free_block.chunk_index).range().start / self.block_size;
+        (offset + free_block.block_index) % self.colors
+    }
 
-impl<T> ChunkedAllocator<T> {
-    /// Create a new chunked allocator.
-    ///
-    /// ### Parameters:
-    ///
-    /// - `blocks_per_chunk`: The number of blocks in each chunk allocated from the underlying
-    ///                       allocator.
-    /// - `min_block_size`: The minimum block size used by this allocator in bytes. Allocations
-    ///                     significantly than this may incur much larger overhead.
-    /// - `max_chunk_size`: The maximum size of chunks allocated from the underlying allocator
-    ///                     in bytes. Blocks larger than this cannot be allocated.
-    /// - `id`: ID of the memory type this allocator allocates from.
-    ///
-    /// ### Panics
-    ///
-    /// Panics if `min_block_size` or `max_chunk_size` are not a power of two.
-    pub fn new(
-        id: MemoryTypeId,
-        blocks_per_chunk: usize,
-        min_block_size: u64,
-        max_chunk_size: u64,
-    ) -> Self {
-        assert!(min_block_size.is_power_of_two());
-        assert!(max_chunk_size.is_power_of_two());
-        ChunkedAllocator {
-            id,
-            blocks_per_chunk,
-            min_block_size,
-            max_chunk_size,
-            nodes: Vec::new(),
+    /// Take a free block of the next color, or the block `alloc_no_grow` would take without
+    /// coloring if there is none.
+    fn pop_colored(&mut self) -> Option<FreeBlock>
+    where
+        T: Block,
+    {
+        let colored = self.free
+            .iter()
+            .find(|free_block| self.color(free_block) == self.next_color)
+            .cloned();
+        let free_block = match colored {
+            Some(free_block) => {
+                self.free.remove(&free_block);
+                free_block
+            }
+            None if self.from_end => self.free.pop_last()?,
+            None => self.free.pop()?,
+        };
+        self.next_color = (self.color(&free_block) + 1) % self.colors;
+        Some(free_block)
+    }
+
+    /// Allocate a free block of a chunk that is bound to `memory`.
+    fn alloc_in_memory<M>(&mut self, memory: &M) -> Option<ChunkedBlock<M>>
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        let free_block = self.free
+            .iter()
+            .find(|free_block| {
+                ::std::ptr::eq(self.chunk(free_block.chunk_index).memory(), memory)
+            })
+            .cloned()?;
+        self.free.remove(&free_block);
+        Some(self.block(free_block))
+    }
+
+    /// Allocate the free block with the lowest offset in its memory object that ends at or before
+    /// `max_offset`.
+    fn alloc_low<M>(&mut self, max_offset: u64) -> Option<ChunkedBlock<M>>
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        let free_block = {
+            let offset = |free_block: &FreeBlock| {
+                self.chunk(free_block.chunk_index).range().start
+                    + free_block.block_index * self.block_size
+            };
+            self.free
+                .iter()
+                .filter(|free_block| offset(free_block) + self.block_size <= max_offset)
+                .min_by_key(|free_block| offset(free_block))
+                .cloned()?
+        };
+        self.free.remove(&free_block);
+        Some(self.block(free_block))
+    }
+
+    /// Allocate the free block at `offset` from the start of the chunk.
+    fn alloc_at<M>(
+        &mut self,
+        chunk_index: usize,
+        offset: u64,
+    ) -> Result<ChunkedBlock<M>, MemoryError>
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        let exists = self.chunks
+            .get(chunk_index)
+            .map(Option::is_some)
+            .unwrap_or(false);
+        if !exists || offset % self.block_size != 0
+            || offset / self.block_size >= self.chunk_blocks(chunk_index) as u64
+        {
+            return Err(MemoryError::InvalidBlock);
+        }
+        let free_block = FreeBlock {
+            chunk_index,
+            block_index: offset / self.block_size,
+        };
+        if !self.free.remove(&free_block) {
+            return Err(MemoryError::BlockInUse);
         }
+        Ok(self.block(free_block))
     }
 
-    /// Check if any of the blocks allocated by this allocator are still in use.
-    /// If this function returns `false`, the allocator can be `dispose`d.
-    pub fn is_used(&self) -> bool {
-        self.nodes.iter().any(ChunkedNode::is_used)
+    /// Create block for the free block taken from the free list.
+    fn block<M>(&mut self, free_block: FreeBlock) -> ChunkedBlock<M>
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        // Memory offset is block index times block size
+        // plus chunk memory offset
+        let block = {
+            let chunk = self.chunk(free_block.chunk_index);
+            let offset = free_block.block_index * self.block_size + chunk.range().start;
+            RawBlock::new(chunk.memory(), offset..self.block_size + offset)
+        };
+        // Remember what chunk the block came from
+        ChunkedBlock(block, self.tag(free_block, 1))
     }
 
-    /// Get memory type of the allocator
-    pub fn memory_type(&self) -> MemoryTypeId {
-        self.id
+    /// Create tag of a block covering `span` blocks starting from `first`.
+    fn tag(&mut self, first: FreeBlock, span: usize) -> ChunkedTag {
+        self.allocations += 1;
+        self.outstanding += 1;
+        self.churn.allocations += 1;
+        if span > 1 {
+            self.spans.insert(first, span);
+        }
+        self.count_used(first.chunk_index, span);
+        self.touch(first);
+        if self.stamps.frames {
+            self.stamps.blocks.entry(first).or_default().frame = Some(self.frame);
+        }
+        #[cfg(feature = "debug-backtrace")]
+        self.backtraces.insert(first, (span, Backtrace::new()));
+        ChunkedTag {
+            chunk_index: first.chunk_index,
+            span,
+            parent: None,
+            scope: None,
+            #[cfg(feature = "checks")]
+            generation: self.generation(&first),
+            #[cfg(feature = "checks")]
+            node: self.index,
+        }
     }
 
-    /// Get minimum block size
-    pub fn min_block_size(&self) -> u64 {
-        self.min_block_size
+    /// Stamp the used block starting at `first` with the current time if LRU tracking is
+    /// enabled.
+    fn touch(&mut self, first: FreeBlock) {
+        if self.stamps.lru {
+            let time = self.clock;
+            self.clock += 1;
+            self.stamps.touch(first, time);
+        }
     }
 
-    /// Get maximum chunk size
-    pub fn max_chunk_size(&self) -> u64 {
-        self.max_chunk_size
+    fn handle(&self, first: FreeBlock) -> BlockHandle {
+        BlockHandle {
+            block_size: self.block_size,
+            chunk_index: first.chunk_index,
+            offset: first.block_index * self.block_size,
+        }
     }
 
-    /// Get the number of chunks per block
-    pub fn blocks_per_chunk(&self) -> usize {
-        self.blocks_per_chunk
+    /// Get the access time and the first block of the least recently used block.
+    fn lru_candidate(&self) -> Option<(usize, FreeBlock)> {
+        self.stamps
+            .order
+            .iter()
+            .next()
+            .map(|(&time, &first)| (time, first))
     }
 
-    /// Retrieves the block backing an allocation.
-    pub fn underlying_block<M: Debug + Any>(&self, block: &ChunkedBlock<M>) -> &T {
-        let index = self.pick_node(block.size());
-        &self.nodes[index as usize].chunks[block.1]
+    #[cfg(feature = "checks")]
+    fn generation(&self, free_block: &FreeBlock) -> u64 {
+        self.generations.get(free_block).cloned().unwrap_or(0)
     }
 
-    /// Get the total size of all blocks allocated by this allocator.
-    pub fn used(&self) -> u64 {
-        self.nodes.iter().map(|node| node.used()).sum()
+    /// Get the first block covered by the block.
+    fn first_block<M>(&self, block: &ChunkedBlock<M>) -> FreeBlock
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        let chunk_index = block.1.chunk_index;
+        let offset = block.range().start - self.chunk(chunk_index).range().start;
+        FreeBlock {
+            chunk_index,
+            block_index: offset / self.block_size,
+        }
     }
 
-    /// Get the total size of all chunks allocated by this allocator.
-    pub fn allocated(&self) -> u64 {
-        self.nodes.iter().map(|node| node.allocated()).sum()
+    fn free_block<M>(&mut self, block: ChunkedBlock<M>)
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        let (first, span) = self.take_block(block);
+        self.release_blocks(first, span);
     }
 
-    fn block_size(&self, index: u8) -> u64 {
-        self.min_block_size * (1u64 << (index as u8))
+    /// Check and dispose the block without returning it to the free list yet.
+    /// Returns the first block covered and the number of blocks covered.
+    fn take_block<M>(&mut self, block: ChunkedBlock<M>) -> (FreeBlock, usize)
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        if let Err(error) = self.check(&block) {
+            panic!("{}: {:?}", error, block);
+        }
+        // There can't be more free blocks than blocks
+        assert!(
+            self.free.len() + block.1.span <= self.count(),
+            "Block freed more than once: {:?}",
+            block
+        );
+        let first = self.first_block(&block);
+        #[cfg(feature = "debug-backtrace")]
+        self.backtraces.remove(&first);
+        self.outstanding -= 1;
+        self.churn.frees += 1;
+        if block.1.span > 1 {
+            self.spans.remove(&first);
+        }
+        self.stamps.remove(&first);
+
+        // Dispose block retreiving its tag
+        let ChunkedTag { span, .. } = unsafe {
+            block.0.dispose();
+            block.1
+        };
+        (first, span)
     }
 
-    fn chunk_size(&self, index: u8) -> u64 {
-        min(
-            self.block_size(index) * self.blocks_per_chunk as u64,
-            self.max_chunk_size,
-        )
+    /// Return `span` blocks starting from `first` to the free list, and free unused chunks
+    /// beyond the free-chunk limit.
+    fn release<B, A>(&mut self, owner: &mut A, device: &B::Device, first: FreeBlock, span: usize)
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        self.release_blocks(first, span);
+        if let Some(max_free) = self.max_free_chunks {
+            let keep = max(max_free, self.min_free_chunks);
+            if self.unused_chunks() > keep {
+                self.shrink_keeping(owner, device, keep);
+            }
+        }
     }
 
-    fn pick_node(&self, size: u64) -> u8 {
-        // blocks can't be larger than max_chunk_size
-        debug_assert!(size <= self.max_chunk_size);
-        let bits = ::std::mem::size_of::<usize>() * 8;
-        assert_ne!(size, 0);
-        let node = (bits - ((size - 1) / self.min_block_size).leading_zeros() as usize) as u8;
-        debug_assert!(size <= self.block_size(node));
-        debug_assert!(node == 0 || size > self.block_size(node - 1));
-        node
+    /// Return `span` blocks starting from `first` to the free list.
+    fn release_blocks(&mut self, first: FreeBlock, span: usize) {
+        let FreeBlock {
+            chunk_index,
+            block_index: first,
+        } = first;
+        self.reclaim = true;
+        if let Some(used) = self.used_blocks.get_mut(chunk_index) {
+            *used = used.saturating_sub(span);
+        }
+
+        // Push all spanned blocks back into the 'free blocks' list at once
+        for block_index in (first..first + span as u64).rev() {
+            let free_block = FreeBlock {
+                block_index,
+                chunk_index,
+            };
+            if !self.moving.is_empty() {
+                // Relocation is complete
+                if let Some(target) = self.moving.remove(&free_block) {
+                    self.targets.remove(&target);
+                }
+                // Relocation is abandoned
+                if let Some(source) = self.targets.remove(&free_block) {
+                    self.moving.remove(&source);
+                }
+            }
+            #[cfg(feature = "checks")]
+            {
+                *self.generations.entry(free_block).or_insert(0) += 1;
+            }
+            self.free.push(free_block);
+        }
     }
 
-    fn grow(&mut self, index: u8) {
-        assert!(self.chunk_size(index) <= self.max_chunk_size);
-        let len = self.nodes.len() as u8;
-        let id = self.id;
+    /// Plan relocations of used blocks from the sparsest chunks into free blocks of denser ones,
+    /// until `relocations` holds `max_relocations` entries.
+    fn defragment<M>(&mut self, max_relocations: usize, relocations: &mut Vec<Relocation<M>>)
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        let blocks = (0..self.chunks.len())
+            .map(|index| {
+                if self.chunks[index].is_some() {
+                    self.chunk_blocks(index)
+                } else {
+                    0
+                }
+            })
+            .collect::<Vec<_>>();
 
-        let range = len..index + 1;
-        self.nodes.reserve(range.len());
-        for index in range {
-            let node = ChunkedNode::new(id, self.chunk_size(index), self.block_size(index));
-            self.nodes.push(node);
+        // Count used blocks of each chunk as if planned relocations were complete
+        let mut used = blocks.clone();
+        for free_block in self.free.iter() {
+            used[free_block.chunk_index] -= 1;
+        }
+        for moving in self.moving.keys() {
+            used[moving.chunk_index] -= 1;
+        }
+        let free = self.free.iter().cloned().collect::<BTreeSet<_>>();
+
+        // Sparsest chunks are evacuated first
+        let mut sources = (0..self.chunks.len())
+            .filter(|&index| used[index] > 0 && used[index] < blocks[index])
+            .collect::<Vec<_>>();
+        sources.sort_by_key(|&index| used[index]);
+
+        for source in sources {
+            let movable = (0..blocks[source] as u64)
+                .map(|block_index| FreeBlock {
+                    chunk_index: source,
+                    block_index,
+                })
+                .filter(|block| {
+                    !free.contains(block) && !self.moving.contains_key(block)
+                        && !self.targets.contains_key(block)
+                })
+                .collect::<Vec<_>>();
+
+            for moving in movable {
+                if relocations.len() >= max_relocations {
+                    return;
+                }
+
+                // Move into the densest chunk that has free blocks and not sparser than source
+                let target = (0..self.chunks.len())
+                    .filter(|&index| {
+                        index != source && used[index] >= used[source]
+                            && used[index] < blocks[index]
+                    })
+                    .max_by_key(|&index| used[index]);
+                let target = match target {
+                    Some(target) => target,
+                    None => break,
+                };
+                let free_block = self.free
+                    .iter()
+                    .find(|free_block| free_block.chunk_index == target)
+                    .cloned()
+                    .expect("Target chunk has free blocks");
+                self.free.remove(&free_block);
+                used[target] += 1;
+                used[source] -= 1;
+
+                let block = self.block(free_block);
+
+                let chunk = self.chunk(source);
+                let offset = moving.block_index * self.block_size + chunk.range().start;
+                relocations.push(Relocation {
+                    memory: chunk.memory(),
+                    range: offset..self.block_size + offset,
+                    block,
+                });
+                self.moving.insert(moving, free_block);
+                self.targets.insert(free_block, moving);
+            }
+        }
+    }
+
+    /// Allocate just enough new chunks to hold all used blocks and plan relocations of the used
+    /// blocks into them.
+    fn compact<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        request: A::Request,
+        relocations: &mut Vec<Relocation<B::Memory>>,
+    ) -> Result<(), MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+        A::Request: Clone,
+    {
+        let free = self.free.iter().cloned().collect::<BTreeSet<_>>();
+        // Blocks covering several blocks are relocated as a unit, so their chunks are left alone
+        let pinned = self.spans
+            .keys()
+            .map(|first| first.chunk_index)
+            .collect::<BTreeSet<_>>();
+        let movable = (0..self.chunks.len())
+            .filter(|&index| self.chunks[index].is_some() && !pinned.contains(&index))
+            .flat_map(|chunk_index| {
+                (0..self.chunk_blocks(chunk_index) as u64).map(move |block_index| FreeBlock {
+                    chunk_index,
+                    block_index,
+                })
+            })
+            .filter(|block| {
+                !free.contains(block) && !self.moving.contains_key(block)
+                    && !self.targets.contains_key(block)
+            })
+            .collect::<Vec<_>>();
+        let sources = movable
+            .iter()
+            .map(|block| block.chunk_index)
+            .collect::<BTreeSet<_>>();
+
+        // Compacting only pays off if it takes fewer chunks than are in use now
+        let per_chunk = self.blocks_per_chunk();
+        let needed = (movable.len() + per_chunk - 1) / per_chunk;
+        if needed >= sources.len() {
+            return Ok(());
+        }
+
+        let mut targets = Vec::new();
+        let mut chunks = Vec::new();
+        for _ in 0..needed {
+            let chunk_index = match self.alloc_chunk(owner, device, request.clone()) {
+                Ok(chunk_index) => chunk_index,
+                Err(error) => {
+                    // Chunks allocated so far are freed again
+                    for chunk_index in chunks {
+                        self.release_chunk(owner, device, chunk_index);
+                    }
+                    self.trim_chunks();
+                    return Err(error);
+                }
+            };
+            chunks.push(chunk_index);
+            targets.extend((0..self.chunk_blocks(chunk_index) as u64).map(|block_index| {
+                FreeBlock {
+                    chunk_index,
+                    block_index,
+                }
+            }));
+        }
+        let mut targets = targets.into_iter();
+
+        for moving in movable {
+            let free_block = targets.next().expect("New chunks hold all used blocks");
+            let block = self.block(free_block);
+            let chunk = self.chunk(moving.chunk_index);
+            let offset = moving.block_index * self.block_size + chunk.range().start;
+            relocations.push(Relocation {
+                memory: chunk.memory(),
+                range: offset..self.block_size + offset,
+                block,
+            });
+            self.moving.insert(moving, free_block);
+            self.targets.insert(free_block, moving);
+        }
+
+        // Rest of the new chunks is available for allocation
+        self.free.extend(targets);
+        Ok(())
+    }
+
+    #[cfg(feature = "testing")]
+    fn save_state(&self) -> NodeState
+    where
+        T: Block,
+    {
+        NodeState {
+            block_size: self.block_size,
+            chunks: self.chunks
+                .iter()
+                .map(|chunk| chunk.as_ref().map(Block::size))
+                .collect(),
+            free: self.free.iter().cloned().collect(),
+            grows: self.grows,
+            peak_chunks: self.peak_chunks,
+            allocations: self.allocations,
+            prefetch: self.prefetch,
+            reclaim: self.reclaim,
+            #[cfg(feature = "checks")]
+            generations: self.generations.clone(),
+        }
+    }
+
+    /// Put the node without chunks into the saved state, with `chunks` in place of the saved
+    /// chunks. Returns the used blocks, except for the ones covered by `deferred`.
+    #[cfg(feature = "testing")]
+    fn restore_state<M>(
+        &mut self,
+        chunks: Vec<Option<T>>,
+        state: &NodeState,
+        deferred: &[&DeferredFree],
+    ) -> Vec<ChunkedBlock<M>>
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        assert_eq!(self.chunk_count(), 0, "Size class already has chunks");
+        assert_eq!(
+            self.block_size, state.block_size,
+            "State was saved with a different configuration"
+        );
+        self.chunks = chunks;
+        self.blocks = (0..self.chunks.len())
+            .filter(|&chunk_index| self.chunks[chunk_index].is_some())
+            .map(|chunk_index| self.chunk_blocks(chunk_index))
+            .sum();
+        self.free.assign(&state.free);
+        self.grows = state.grows;
+        self.peak_chunks = state.peak_chunks;
+        self.prefetch = state.prefetch;
+        self.reclaim = state.reclaim;
+        #[cfg(feature = "checks")]
+        {
+            self.generations = state.generations.clone();
+        }
+
+        self.used_blocks.clear();
+        let mut unused = state.free.iter().cloned().collect::<BTreeSet<_>>();
+        for deferred in deferred {
+            self.count_used(deferred.first.chunk_index, deferred.span);
+            unused.extend((0..deferred.span as u64).map(|offset| FreeBlock {
+                chunk_index: deferred.first.chunk_index,
+                block_index: deferred.first.block_index + offset,
+            }));
+        }
+        let mut blocks = Vec::new();
+        for chunk_index in 0..self.chunks.len() {
+            if self.chunks[chunk_index].is_none() {
+                continue;
+            }
+            for block_index in 0..self.chunk_blocks(chunk_index) as u64 {
+                let free_block = FreeBlock {
+                    chunk_index,
+                    block_index,
+                };
+                if !unused.contains(&free_block) {
+                    blocks.push(self.block(free_block));
+                }
+            }
+        }
+        self.allocations = state.allocations;
+        blocks
+    }
+
+    /// Check that the free list only holds distinct blocks of allocated chunks.
+    fn check_invariants(&self, node: usize) -> Result<(), InvariantViolation>
+    where
+        T: Block,
+    {
+        let mut seen = BTreeSet::new();
+        for free_block in self.free.iter() {
+            let violation = InvariantViolation::InvalidFreeBlock {
+                node,
+                chunk_index: free_block.chunk_index,
+                block_index: free_block.block_index,
+            };
+            match self.chunks.get(free_block.chunk_index) {
+                Some(&Some(_)) => {}
+                _ => return Err(violation),
+            }
+            if free_block.block_index >= self.chunk_blocks(free_block.chunk_index) as u64 {
+                return Err(violation);
+            }
+            if !seen.insert(*free_block) {
+                return Err(InvariantViolation::DuplicateFreeBlock {
+                    node,
+                    chunk_index: free_block.chunk_index,
+                    block_index: free_block.block_index,
+                });
+            }
+        }
+        if self.free.len() > self.count() {
+            return Err(InvariantViolation::TooManyFreeBlocks { node });
+        }
+        Ok(())
+    }
+
+    /// Check that the block is returned as a whole.
+    fn check<M>(&self, block: &ChunkedBlock<M>) -> Result<(), MemoryError>
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        #[cfg(feature = "paranoid")]
+        self.check_tag(block)?;
+        let chunk = self.chunk(block.1.chunk_index);
+
+        // Confirm the chunk index
+        assert!(::std::ptr::eq(chunk.memory(), block.memory()));
+
+        // Block must cover exactly the span of blocks recorded in its tag
+        let start = block.range().start;
+        let size = block.whole_size();
+        if size != block.1.span as u64 * self.block_size || !chunk.contains(block)
+            || start + size > chunk.range().end
+            || (start - chunk.range().start) % self.block_size != 0
+        {
+            return Err(MemoryError::PartialFree);
+        }
+        Ok(())
+    }
+
+    /// Check that the chunk recorded in the tag is the only chunk containing the block,
+    /// scanning all chunks.
+    #[cfg(feature = "paranoid")]
+    fn check_tag<M>(&self, block: &ChunkedBlock<M>) -> Result<(), MemoryError>
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        let mut containing = self.chunks
+            .iter()
+            .enumerate()
+            .filter(|&(_, chunk)| chunk.as_ref().is_some_and(|chunk| chunk.contains(block)))
+            .map(|(chunk_index, _)| chunk_index);
+        match (containing.next(), containing.next()) {
+            (Some(chunk_index), None) if chunk_index == block.1.chunk_index => Ok(()),
+            _ => Err(MemoryError::CorruptTag),
         }
     }
 }
 
-impl<B, O, T> MemorySubAllocator<B, O> for ChunkedAllocator<T>
+impl<B, O, T> MemorySubAllocator<B, O> for ChunkedNode<T>
 where
     B: Backend,
     T: Block<Memory = B::Memory>,
@@ -351,57 +1425,6449 @@ where
         request: O::Request,
         reqs: Requirements,
     ) -> Result<ChunkedBlock<B::Memory>, MemoryError> {
-        if max(reqs.size, reqs.alignment) > self.max_chunk_size {
-            return Err(MemoryError::OutOfMemory);
+        // Check memory type
+        if (1 << self.id.0) & reqs.type_mask == 0 {
+            return Err(MemoryError::NoCompatibleMemoryType);
         }
-        let index = self.pick_node(max(reqs.size, reqs.alignment));
-        self.grow(index);
-        self.nodes[index as usize].alloc(owner, device, request, reqs)
+
+        // Try to allocate a block
+        let block = match self.alloc_no_grow() {
+            Some(block) => block,
+            None => {
+                // Grow from super-allocator
+                self.grow(owner, device, request)?;
+                self.alloc_no_grow().expect("Just growed")
+            }
+        };
+
+        // Check that block meets the requirements.
+        assert!(block.size() >= reqs.size);
+        assert_eq!(block.range().start & (reqs.alignment - 1), 0);
+        Ok(block)
     }
 
     fn free(&mut self, owner: &mut O, device: &B::Device, block: ChunkedBlock<B::Memory>) {
-        let index = self.pick_node(block.size());
-        self.nodes[index as usize].free(owner, device, block);
+        let (first, span) = self.take_block(block);
+        self.release(owner, device, first, span);
     }
 
     fn dispose(mut self, owner: &mut O, device: &B::Device) -> Result<(), Self> {
         if self.is_used() {
             Err(self)
         } else {
-            for node in self.nodes.drain(..) {
-                node.dispose(owner, device).unwrap();
+            for chunk in self.chunks.drain(..).flatten() {
+                owner.free(device, chunk);
             }
             Ok(())
         }
     }
 }
 
-/// `Block` type returned by `ChunkedAllocator`.
-#[derive(Debug)]
-pub struct ChunkedBlock<M>(pub(crate) RawBlock<M>, pub(crate) usize);
+/// Block and chunk size of a size class of a `ChunkedAllocator`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NodeSpec {
+    /// Index of the size class
+    pub index: u8,
+    /// Size of the blocks of the size class in bytes
+    pub block_size: u64,
+    /// Size of the chunks allocated from the underlying allocator for the size class in bytes
+    pub chunk_size: u64,
+}
 
-impl<M> Block for ChunkedBlock<M>
-where
-    M: Debug + Any,
-{
-    type Memory = M;
+/// Strategy that defines the size classes of a `ChunkedAllocator` and maps requests to them.
+///
+/// Size classes are indexed from 0 up to `node_count`, and block sizes must strictly increase
+/// with the index. The chunk size of a size class must be a multiple of its block size.
+pub trait NodeSelector {
+    /// Get the number of size classes.
+    fn node_count(&self) -> u8;
 
-    #[inline(always)]
+    /// Get the size class with the index, which is less than `node_count`.
+    fn node(&self, index: u8) -> NodeSpec;
+
+    /// Pick the size class to allocate a block meeting the requirements from.
+    ///
+    /// ### Returns
+    ///
+    /// `None` if no size class can hold blocks of the requested size.
+    fn select(&self, reqs: &Requirements) -> Option<NodeSpec>;
+
+    /// Pick the size class to allocate a block of `size` bytes from, that is aligned to more
+    /// than its size. Used instead of `select` for such requests.
+    ///
+    /// Blocks are aligned to the largest power of two their size is a multiple of, so by
+    /// default this picks the smallest size class with blocks of at least `size` bytes that are
+    /// a multiple of `alignment`, instead of rounding `size` up to `alignment`.
+    ///
+    /// ### Returns
+    ///
+    /// `None` if no size class can hold blocks of the requested size and alignment.
+    fn select_aligned(&self, size: u64, alignment: u64) -> Option<NodeSpec> {
+        (0..self.node_count())
+            .map(|index| self.node(index))
+            .find(|spec| spec.block_size >= size && spec.block_size % alignment == 0)
+    }
+
+    /// Get the index of the smallest size class with blocks of at least `size` bytes, or of the
+    /// largest size class if there is none. `size` is never zero.
+    fn node_for_size(&self, size: u64) -> u8 {
+        let max_index = self.node_count() - 1;
+        (0..max_index)
+            .find(|&index| self.node(index).block_size >= size)
+            .unwrap_or(max_index)
+    }
+}
+
+/// Default `NodeSelector` with block sizes doubling from `min_block_size` up to
+/// `max_chunk_size`, where requests are rounded up to the closest power of two.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PowerOfTwoSelector {
+    blocks_per_chunk: usize,
+    min_block_size: u64,
+    max_chunk_size: u64,
+}
+
+impl PowerOfTwoSelector {
+    /// Create a new selector.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `blocks_per_chunk`: number of blocks in each chunk
+    /// - `min_block_size`: block size of the smallest size class, a power of two
+    /// - `max_chunk_size`: maximum size of chunks, which is the block size of the largest
+    ///   size class
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `blocks_per_chunk` is zero, `min_block_size` is not a power of two or
+    /// `min_block_size` is greater than `max_chunk_size`.
+    pub fn new(blocks_per_chunk: usize, min_block_size: u64, max_chunk_size: u64) -> Self {
+        assert_ne!(blocks_per_chunk, 0);
+        assert!(min_block_size.is_power_of_two());
+        assert!(min_block_size <= max_chunk_size);
+        PowerOfTwoSelector {
+            blocks_per_chunk,
+            min_block_size,
+            max_chunk_size,
+        }
+    }
+
+    /// Get the number of blocks in each chunk.
+    pub fn blocks_per_chunk(&self) -> usize {
+        self.blocks_per_chunk
+    }
+
+    fn max_index(&self) -> u8 {
+        (64 - ((self.max_chunk_size - 1) / self.min_block_size).leading_zeros()) as u8
+    }
+}
+
+impl NodeSelector for PowerOfTwoSelector {
+    fn node_count(&self) -> u8 {
+        self.max_index() + 1
+    }
+
+    fn node(&self, index: u8) -> NodeSpec {
+        // Blocks of the largest size class are exactly `max_chunk_size`, and chunks hold whole
+        // blocks even if `max_chunk_size` is not a power of two
+        let block_size = if index >= self.max_index() {
+            self.max_chunk_size
+        } else {
+            self.min_block_size << index
+        };
+        let blocks = min(self.blocks_per_chunk as u64, self.max_chunk_size / block_size);
+        NodeSpec {
+            index,
+            block_size,
+            chunk_size: block_size * max(blocks, 1),
+        }
+    }
+
+    fn select(&self, reqs: &Requirements) -> Option<NodeSpec> {
+        let size = max(reqs.size, reqs.alignment);
+        if size > self.max_chunk_size {
+            None
+        } else {
+            Some(self.node(self.node_for_size(size)))
+        }
+    }
+
+    fn node_for_size(&self, size: u64) -> u8 {
+        // Clamp to the largest size class, which `node` never shifts to, so oversized sizes
+        // can't overflow the shift
+        let node = (64 - ((size - 1) / self.min_block_size).leading_zeros()) as u8;
+        min(node, self.max_index())
+    }
+}
+
+/// Round `size` up to the closest power of two, same as `PowerOfTwoSelector`.
+///
+/// This is the rounding function to pass to `RoundingSelector::new` if no other policy is
+/// needed.
+pub fn round_to_power_of_two(size: u64) -> u64 {
+    size.next_power_of_two()
+}
+
+/// `NodeSelector` with a size class for every block size a function rounds requested sizes to.
+///
+/// The function replaces the power of two rounding of `PowerOfTwoSelector`, so any size policy,
+/// e.g. explicit size classes or a fixed granularity, can be expressed with it. It must round
+/// sizes up, never return a smaller size for a larger one and round the sizes it returns to
+/// themselves. Sizes are rounded to at most `max_chunk_size`, which is the block size of the
+/// largest size class.
+#[derive(Clone, Debug)]
+pub struct RoundingSelector {
+    round: fn(u64) -> u64,
+    blocks_per_chunk: usize,
+    /// Block sizes of all size classes in increasing order
+    block_sizes: Vec<u64>,
+}
+
+impl RoundingSelector {
+    /// Create a new selector, calling `round` to find the block sizes of all size classes.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `round`: function rounding a requested size up to the block size to serve it from
+    /// - `blocks_per_chunk`: maximum number of blocks in each chunk
+    /// - `max_chunk_size`: maximum size of chunks, which is the block size of the largest
+    ///   size class
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `blocks_per_chunk` or `max_chunk_size` are zero, `round` rounds a size down or
+    /// there would be more than 255 size classes.
+    pub fn new(round: fn(u64) -> u64, blocks_per_chunk: usize, max_chunk_size: u64) -> Self {
+        assert_ne!(blocks_per_chunk, 0);
+        assert_ne!(max_chunk_size, 0);
+        let mut block_sizes = Vec::new();
+        let mut size = 1;
+        loop {
+            let block_size = min(round(size), max_chunk_size);
+            assert!(block_size >= size, "Size {} is rounded down to {}", size, block_size);
+            block_sizes.push(block_size);
+            assert!(block_sizes.len() <= 255, "Rounding yields more than 255 size classes");
+            if block_size == max_chunk_size {
+                break;
+            }
+            size = block_size + 1;
+        }
+        RoundingSelector {
+            round,
+            blocks_per_chunk,
+            block_sizes,
+        }
+    }
+
+    /// Get the maximum number of blocks in each chunk.
+    pub fn blocks_per_chunk(&self) -> usize {
+        self.blocks_per_chunk
+    }
+
+    fn max_chunk_size(&self) -> u64 {
+        *self.block_sizes.last().unwrap()
+    }
+}
+
+impl NodeSelector for RoundingSelector {
+    fn node_count(&self) -> u8 {
+        self.block_sizes.len() as u8
+    }
+
+    fn node(&self, index: u8) -> NodeSpec {
+        // Chunks hold whole blocks and are never larger than `max_chunk_size`, unless a single
+        // block is
+        let block_size = self.block_sizes[index as usize];
+        let blocks = min(self.blocks_per_chunk as u64, self.max_chunk_size() / block_size);
+        NodeSpec {
+            index,
+            block_size,
+            chunk_size: block_size * max(blocks, 1),
+        }
+    }
+
+    /// Picks the size class of the rounded size, or the next larger one whose blocks are
+    /// aligned to `reqs.alignment`.
+    fn select(&self, reqs: &Requirements) -> Option<NodeSpec> {
+        let size = max(reqs.size, reqs.alignment);
+        if size > self.max_chunk_size() {
+            return None;
+        }
+        (self.node_for_size(size)..self.node_count())
+            .map(|index| self.node(index))
+            .find(|spec| spec.block_size % reqs.alignment == 0)
+    }
+
+    fn node_for_size(&self, size: u64) -> u8 {
+        let max_chunk_size = self.max_chunk_size();
+        let block_size = min((self.round)(min(size, max_chunk_size)), max_chunk_size);
+        let index = match self.block_sizes.binary_search(&block_size) {
+            Ok(index) | Err(index) => index,
+        };
+        min(index, self.block_sizes.len() - 1) as u8
+    }
+}
+
+/// Sub-allocator that can be used for long-lived objects.
+///
+/// This allocator allocates memory in chunks containing `blocks_per_chunk` equally sized blocks
+/// from the underlying allocator, up to a maximum chunk size of `max_chunk_size` bytes. It rounds
+/// up the requested allocation size to the closest power of two and returns a single block from a
+/// chunk.
+///
+/// This allocator can only allocate memory `max_chunk_size` bytes in size or less.
+///
+/// Chunks are requested with a `type_mask` containing only the allocator's memory type. If the
+/// underlying allocator reports memory types via `MemoryAllocator::block_memory_type` this is
+/// verified, otherwise it is trusted to honor the mask.
+///
+/// The allocator remembers the `MemoryAllocator::instance_id` of the first owner it is used
+/// with, and panics if it is later passed a different owner, e.g. when disposed.
+///
+/// Other size classes can be used with `ChunkedAllocatorBuilder::build_with_selector`.
+///
+/// With the `registry` feature every allocator is listed by `registry::snapshot` while alive.
+///
+/// ### Type parameters:
+///
+/// - `T`: type of bigger blocks this allocator sub-allocates from.
+/// - `S`: strategy defining the size classes.
+#[derive(Debug)]
+pub struct ChunkedAllocator<T, S = PowerOfTwoSelector> {
+    id: MemoryTypeId,
+    selector: S,
+    /// Size classes of the selector, with chunk sizes as allocated from the underlying allocator
+    ladder: Vec<NodeSpec>,
+    owner_min_alignment: u64,
+    chunk_granularity: u64,
+    fill_chunks: bool,
+    free_list: FreeList,
+    pressure_threshold: Option<u64>,
+    low_water: Option<usize>,
+    min_free_chunks: Vec<usize>,
+    max_free_chunks: Vec<Option<usize>>,
+    from_end: Vec<bool>,
+    colors: u64,
+    /// Churn counted up to the last `frame_boundary`
+    frame_start: ChurnStats,
+    churn_limit: Option<usize>,
+    strict_churn: bool,
+    on_churn_limit: Option<Callback<ChurnStats>>,
+    lru: bool,
+    frame_stamps: bool,
+    /// Frame set with `set_frame`
+    frame: u64,
+    auto_shrink: Option<usize>,
+    /// Frees since the last automatic `shrink`
+    frees_since_shrink: usize,
+    on_grow: Option<Callback<GrowEvent>>,
+    heap_size: Option<u64>,
+    warn_fraction: f32,
+    on_heap_warning: Option<Callback<HeapWarning>>,
+    /// Allocated chunks reached the warning threshold at the last check, so the warning isn't
+    /// repeated until they drop below it
+    heap_warned: bool,
+    reserved: Vec<usize>,
+    /// Instance ID of the owner chunks are allocated from
+    owner: Option<usize>,
+    /// ID telling the scope handles of different allocators apart
+    instance_id: usize,
+    scopes: Vec<Scope>,
+    deferred: BTreeMap<u64, Vec<DeferredFree>>,
+    /// Samples recorded by `record_sample`, of which the last `history_len` are kept
+    history: Vec<ChunkedSample>,
+    history_len: usize,
+    #[cfg(feature = "registry")]
+    registration: Registration,
+    nodes: Vec<ChunkedNode<T>>,
+}
+
+/// Per-block metadata of the used blocks of a node, shared by LRU tracking and frame stamping
+/// so enabling both costs a single map entry per block.
+#[derive(Debug, Default)]
+struct Stamps {
+    /// Whether access times are recorded
+    lru: bool,
+    /// Whether allocation frames are recorded
+    frames: bool,
+    blocks: BTreeMap<FreeBlock, Stamp>,
+    /// Used blocks by access time
+    order: BTreeMap<usize, FreeBlock>,
+}
+
+/// Metadata of a used block.
+#[derive(Clone, Copy, Debug, Default)]
+struct Stamp {
+    /// Time the block was last accessed
+    access: Option<usize>,
+    /// Frame the block was allocated in
+    frame: Option<u64>,
+}
+
+impl Stamps {
+    /// Enable or disable recording of each kind of stamp, dropping the stamps of disabled kinds.
+    fn enable(&mut self, lru: bool, frames: bool) {
+        self.lru = lru;
+        self.frames = frames;
+        if !lru {
+            self.order.clear();
+        }
+        self.blocks = ::std::mem::take(&mut self.blocks)
+            .into_iter()
+            .filter_map(|(first, mut stamp)| {
+                if !lru {
+                    stamp.access = None;
+                }
+                if !frames {
+                    stamp.frame = None;
+                }
+                if stamp.access.is_some() || stamp.frame.is_some() {
+                    Some((first, stamp))
+                } else {
+                    None
+                }
+            })
+            .collect();
+    }
+
+    fn touch(&mut self, first: FreeBlock, time: usize) {
+        let stamp = self.blocks.entry(first).or_default();
+        if let Some(old) = stamp.access.replace(time) {
+            self.order.remove(&old);
+        }
+        self.order.insert(time, first);
+    }
+
+    fn remove(&mut self, first: &FreeBlock) {
+        self.take(first);
+    }
+
+    fn take(&mut self, first: &FreeBlock) -> Option<Stamp> {
+        let stamp = self.blocks.remove(first)?;
+        if let Some(time) = stamp.access {
+            self.order.remove(&time);
+        }
+        Some(stamp)
+    }
+}
+
+/// Callback set with e.g. `ChunkedAllocator::on_grow`.
+struct Callback<E>(Box<dyn FnMut(E) + Send + Sync>);
+
+impl<E> Debug for Callback<E> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("Callback")
+    }
+}
+
+/// Blocks freed by `ChunkedAllocator::free_deferred` waiting to be returned to a node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct DeferredFree {
+    node: usize,
+    first: FreeBlock,
+    span: usize,
+}
+
+/// Chunk moved to another allocator by `ChunkedAllocator::transfer_block`.
+struct MovedChunk<T> {
+    chunk: T,
+    /// Indices of the free blocks
+    free: Vec<u64>,
+    used: Vec<MovedBlock>,
+    /// Number of times each block was freed, by block index
+    #[cfg(feature = "checks")]
+    generations: Vec<(u64, u64)>,
+}
+
+/// Used block of a `MovedChunk`.
+struct MovedBlock {
+    /// Index of the first block covered
+    block_index: u64,
+    /// Number of blocks covered if more than one
+    span: Option<usize>,
+    stamp: Option<Stamp>,
+    #[cfg(feature = "debug-backtrace")]
+    backtrace: Option<(usize, Backtrace)>,
+}
+
+impl<T> ChunkedAllocator<T> {
+    /// Create a new chunked allocator.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `blocks_per_chunk`: The number of blocks in each chunk allocated from the underlying
+    ///   allocator.
+    /// - `min_block_size`: The minimum block size used by this allocator in bytes. Allocations
+    ///   significantly smaller than this may incur much larger overhead.
+    /// - `max_chunk_size`: The maximum size of chunks allocated from the underlying allocator
+    ///   in bytes. Requests with a larger size or alignment fail with
+    ///   `MemoryError::TooLarge`.
+    /// - `id`: ID of the memory type this allocator allocates from.
+    ///
+    /// Setting `min_block_size` equal to `max_chunk_size` yields an allocator with a single size
+    /// class where every chunk holds exactly one block.
+    ///
+    /// This is a shorthand for `ChunkedAllocatorBuilder`.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if the builder would return a `ConfigError`: if `id` is not less than
+    /// `MAX_MEMORY_TYPES`, `blocks_per_chunk` is zero, `min_block_size` or `max_chunk_size` are
+    /// not a power of two, or if `min_block_size` is greater than `max_chunk_size`.
+    pub fn new(
+        id: MemoryTypeId,
+        blocks_per_chunk: usize,
+        min_block_size: u64,
+        max_chunk_size: u64,
+    ) -> Self {
+        ChunkedAllocatorBuilder::new(id)
+            .with_blocks_per_chunk(blocks_per_chunk)
+            .with_min_block_size(min_block_size)
+            .with_max_chunk_size(max_chunk_size)
+            .build()
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Get the number of chunks per block
+    pub fn blocks_per_chunk(&self) -> usize {
+        self.selector.blocks_per_chunk()
+    }
+
+    /// Sub-allocate blocks of `block_size` bytes from a single block provided by the caller.
+    ///
+    /// The view never allocates from or frees to an underlying allocator, the block is handed
+    /// back by `SingleBlockChunked::into_block` once all blocks are free. This is useful for
+    /// transient memory, e.g. per render pass, whose lifetime is managed by the caller.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `block_size` is zero or larger than `block`.
+    pub fn single_block_view(block: T, block_size: u64) -> SingleBlockChunked<T>
+    where
+        T: Block,
+    {
+        SingleBlockChunked::new(block, block_size)
+    }
+}
+
+impl<T, S> ChunkedAllocator<T, S>
+where
+    S: NodeSelector,
+{
+    /// Create an allocator with the same configuration and policies as this one, but without
+    /// any chunks, scopes or reservations. Callbacks are not copied.
+    pub fn clone_config(&self) -> Self
+    where
+        S: Clone,
+    {
+        ChunkedAllocator {
+            id: self.id,
+            selector: self.selector.clone(),
+            ladder: self.ladder.clone(),
+            owner_min_alignment: self.owner_min_alignment,
+            chunk_granularity: self.chunk_granularity,
+            fill_chunks: self.fill_chunks,
+            free_list: self.free_list,
+            pressure_threshold: self.pressure_threshold,
+            low_water: self.low_water,
+            min_free_chunks: self.min_free_chunks.clone(),
+            max_free_chunks: self.max_free_chunks.clone(),
+            from_end: self.from_end.clone(),
+            colors: self.colors,
+            frame_start: ChurnStats::default(),
+            churn_limit: self.churn_limit,
+            strict_churn: self.strict_churn,
+            on_churn_limit: None,
+            lru: self.lru,
+            frame_stamps: self.frame_stamps,
+            frame: 0,
+            auto_shrink: self.auto_shrink,
+            frees_since_shrink: 0,
+            on_grow: None,
+            heap_size: self.heap_size,
+            warn_fraction: self.warn_fraction,
+            on_heap_warning: None,
+            heap_warned: false,
+            reserved: Vec::new(),
+            owner: None,
+            instance_id: next_instance_id(),
+            scopes: Vec::new(),
+            deferred: BTreeMap::new(),
+            history: Vec::new(),
+            history_len: self.history_len,
+            #[cfg(feature = "registry")]
+            registration: Registration::new(self.id),
+            nodes: Vec::new(),
+        }
+    }
+
+    /// Set the container used to keep track of free blocks.
+    /// Only affects size classes that haven't been used yet.
+    pub fn with_free_list(mut self, free_list: FreeList) -> Self {
+        self.free_list = free_list;
+        self
+    }
+
+    /// Set the minimum alignment of blocks allocated by the underlying allocator.
+    ///
+    /// Chunks smaller than this are rounded up to it, so chunks of the smallest size classes hold
+    /// more blocks instead of wasting the padding the underlying allocator would insert.
+    /// Only affects size classes that haven't been used yet.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `alignment` is not a power of two, is greater than `max_chunk_size` or a
+    /// rounded chunk size doesn't fit into `u64`.
+    pub fn with_owner_min_alignment(mut self, alignment: u64) -> Self {
+        assert!(alignment.is_power_of_two());
+        self.owner_min_alignment = alignment;
+        self.update_ladder();
+        self
+    }
+
+    /// Round the size of chunks up to a multiple of `granularity`.
+    ///
+    /// Some drivers work best with device allocations of a multiple of a large page size. The
+    /// extra space of the rounded up chunks is divided into additional blocks, so chunks may
+    /// be larger than `max_chunk_size`. Only affects size classes that haven't been used yet.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `granularity` is zero or a rounded chunk size doesn't fit into `u64`.
+    pub fn with_chunk_granularity(mut self, granularity: u64) -> Self {
+        assert_ne!(granularity, 0);
+        self.chunk_granularity = granularity;
+        self.update_ladder();
+        self
+    }
+
+    /// Set whether blocks are always allocated from the first chunk that has free blocks.
+    ///
+    /// This keeps later chunks empty so they can be released by `shrink` as usage declines.
+    /// Enabling it is the same as using `FreeList::Ordered`, disabling it restores the default
+    /// free list if `FreeList::Ordered` was set.
+    pub fn with_compacting_allocation(self, compacting: bool) -> Self {
+        if compacting {
+            self.with_free_list(FreeList::Ordered)
+        } else if self.free_list == FreeList::Ordered {
+            self.with_free_list(FreeList::default())
+        } else {
+            self
+        }
+    }
+
+    /// Set whether to use all blocks that fit into chunks larger than requested.
+    ///
+    /// By default chunks are divided into exactly `blocks_per_chunk` blocks and any memory the
+    /// underlying allocator returns beyond that is unused. Only affects size classes that haven't
+    /// been used yet.
+    pub fn with_fill_chunks(mut self, fill_chunks: bool) -> Self {
+        self.fill_chunks = fill_chunks;
+        self
+    }
+
+    /// Request prefetching of a chunk whenever an allocation leaves fewer than `threshold` free
+    /// blocks in its size class.
+    ///
+    /// Allocations don't grow ahead themselves, they only mark the size class. Chunks are
+    /// allocated by `process_prefetch`, which can be called off the hot path.
+    pub fn with_low_water_prefetch(mut self, threshold: usize) -> Self {
+        self.low_water = Some(threshold);
+        self
+    }
+
+    /// Spread successive allocations of each size class across `num_colors` offset colors.
+    ///
+    /// The color of a block is its offset in the memory object in blocks, modulo `num_colors`.
+    /// Allocations take a free block of the color after the one of the previous allocation if
+    /// there is one, instead of densely packing blocks. This can reduce memory channel and cache
+    /// set conflicts for memory-bound workloads, at the cost of a scan over the free blocks.
+    /// `1` disables coloring, which is the default.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `num_colors` is zero.
+    pub fn with_coloring(mut self, num_colors: u64) -> Self {
+        assert_ne!(num_colors, 0);
+        self.colors = num_colors;
+        for node in &mut self.nodes {
+            node.colors = num_colors;
+            node.next_color = 0;
+        }
+        self
+    }
+
+    /// Limit the number of allocations and frees between two calls to `frame_boundary`.
+    ///
+    /// Allocating and freeing many blocks every frame often means that something which should
+    /// be persistent is recreated each frame. Exceeding the limit is reported by
+    /// `churn_exceeded`, and by `frame_boundary` to the callback set with `on_churn_limit`. In
+    /// strict mode allocations fail with `MemoryError::ChurnLimit` instead once the limit is
+    /// reached, until the next `frame_boundary`.
+    pub fn with_churn_limit(mut self, limit: usize, strict: bool) -> Self {
+        self.churn_limit = Some(limit);
+        self.strict_churn = strict;
+        self
+    }
+
+    /// Track when each used block was last accessed, see `touch` and `lru_candidate`.
+    ///
+    /// Allocating a block counts as an access. The allocator never evicts blocks itself, this
+    /// is meant for caches built on top of it that have to decide what to free. Besides the
+    /// per-block stamp every access updates an index of the blocks ordered by access time.
+    /// Blocks allocated before enabling it are only tracked once touched.
+    pub fn with_lru_tracking(mut self, enabled: bool) -> Self {
+        self.lru = enabled;
+        for node in &mut self.nodes {
+            node.stamps.enable(enabled, self.frame_stamps);
+        }
+        self
+    }
+
+    /// Stamp every allocation with the frame set by `set_frame`, to find allocations that live
+    /// longer than intended with `allocations_older_than`.
+    ///
+    /// The frame is kept next to the access time of `with_lru_tracking`, so combining both adds
+    /// no further lookups. Blocks allocated before enabling it are never reported.
+    pub fn with_frame_stamps(mut self, enabled: bool) -> Self {
+        self.frame_stamps = enabled;
+        for node in &mut self.nodes {
+            node.stamps.enable(self.lru, enabled);
+        }
+        self
+    }
+
+    /// Call `shrink` on every `every_n_frees`th free, so unused chunks are periodically released
+    /// without the caller having to schedule calls to `shrink`.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `every_n_frees` is zero.
+    pub fn with_auto_shrink(mut self, every_n_frees: usize) -> Self {
+        assert_ne!(every_n_frees, 0);
+        self.auto_shrink = Some(every_n_frees);
+        self.frees_since_shrink = 0;
+        self
+    }
+
+    /// Stop calling `shrink` on frees, undoing `with_auto_shrink`.
+    pub(crate) fn disable_auto_shrink(&mut self) {
+        self.auto_shrink = None;
+    }
+
+    /// Set the number of samples recorded by `record_sample` that are kept. 256 by default.
+    pub fn with_history_len(mut self, len: usize) -> Self {
+        self.history_len = len;
+        let start = self.history.len().saturating_sub(len);
+        self.history.drain(..start);
+        self
+    }
+
+    /// Reserve the number of chunks of each size class recorded in `layout`.
+    /// The chunks are allocated by `reserve_layout`.
+    pub fn with_reserved_layout(mut self, layout: ChunkedLayout) -> Self {
+        self.reserved = layout.chunks;
+        self
+    }
+
+    /// Check if any of the blocks allocated by this allocator are still in use.
+    /// If this function returns `false`, the allocator can be `dispose`d.
+    ///
+    /// A block is in use if it isn't available for allocation for any reason, i.e. it is either
+    /// handed out or reserved, see `handed_out_count` and `reserved_count`.
+    pub fn is_used(&self) -> bool {
+        self.nodes.iter().any(ChunkedNode::is_used)
+    }
+
+    /// Get the number of blocks that are handed out and not freed yet.
+    ///
+    /// Blocks spanning several blocks of their size class, like those allocated with
+    /// `alloc_whole_chunk`, count once per block spanned.
+    pub fn handed_out_count(&self) -> usize {
+        let unavailable = self.nodes
+            .iter()
+            .map(|node| node.count() - node.free.len())
+            .sum::<usize>();
+        unavailable - self.reserved_count()
+    }
+
+    /// Get the number of allocations that are handed out and not freed yet.
+    ///
+    /// Unlike `handed_out_count` every allocated block counts once, regardless of how many
+    /// blocks of its size class it spans. Blocks freed with `free_deferred` are not counted.
+    /// Each size class keeps its count up to date on allocations and frees, so this doesn't
+    /// iterate the blocks.
+    pub fn outstanding_allocations(&self) -> usize {
+        self.nodes.iter().map(|node| node.outstanding).sum()
+    }
+
+    /// Get the number of allocations from the size class with the index that are handed out and
+    /// not freed yet. See `outstanding_allocations`.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `index` is greater than the index of the largest size class.
+    pub fn outstanding_allocations_of_node(&self, index: u8) -> usize {
+        assert!(index <= self.max_index(), "Size class {} is out of range", index);
+        self.nodes
+            .get(index as usize)
+            .map(|node| node.outstanding)
+            .unwrap_or(0)
+    }
+
+    /// Get the number of blocks that are freed but not available for allocation yet, i.e.
+    /// freed with `free_deferred` and not collected.
+    pub fn reserved_count(&self) -> usize {
+        self.deferred
+            .values()
+            .flat_map(|frees| frees.iter())
+            .map(|deferred| deferred.span)
+            .sum()
+    }
+
+    /// Get the size of the blocks of a size class, as used by the allocator.
+    ///
+    /// Size classes are indexed from the smallest one, same as `ChunkedView::size_classes`.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if there is no size class with the index.
+    pub fn block_size_of_node(&self, index: u8) -> u64 {
+        assert!(index <= self.max_index(), "Size class {} is out of range", index);
+        self.block_size(index)
+    }
+
+    /// Get the size of the chunks of a size class, as allocated from the underlying allocator.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if there is no size class with the index.
+    pub fn chunk_size_of_node(&self, index: u8) -> u64 {
+        assert!(index <= self.max_index(), "Size class {} is out of range", index);
+        self.chunk_size(index)
+    }
+
+    /// Get the index of the smallest size class with blocks of at least `size` bytes, which the
+    /// allocator serves allocations of that size from.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `size` is zero or greater than `max_chunk_size`.
+    pub fn node_index_for_size(&self, size: u64) -> u8 {
+        assert!(
+            size != 0 && size <= self.max_chunk_size(),
+            "Size {} is out of range",
+            size
+        );
+        self.pick_node(size).expect("Size is in range")
+    }
+
+    /// Get the number of blocks meeting the requirements that can be allocated without
+    /// allocating new chunks.
+    ///
+    /// This has no side effects, so it can be used to plan work ahead of stalls caused by
+    /// growing. Requirements this allocator can't serve have no blocks available.
+    pub fn available_for(&self, reqs: &Requirements) -> usize {
+        if (1 << self.id.0) & reqs.type_mask == 0 {
+            return 0;
+        }
+        self.pick_node_for(*reqs)
+            .ok()
+            .and_then(|index| self.nodes.get(index as usize))
+            .map(|node| node.free.len())
+            .unwrap_or(0)
+    }
+
+    /// Check that blocks with each of the alignments can be allocated, e.g. right after
+    /// construction with the alignments all resources need.
+    ///
+    /// An alignment can be served if it is a power of two and the blocks of some size class are
+    /// aligned to it. Allocations with an alignment that can't be served fail with
+    /// `MemoryError::TooLarge`.
+    ///
+    /// ### Returns
+    ///
+    /// The error for the first alignment that can't be served.
+    pub fn validate_for_alignments(&self, alignments: &[u64]) -> Result<(), AlignmentError> {
+        for &alignment in alignments {
+            if !alignment.is_power_of_two() {
+                return Err(AlignmentError::NotPowerOfTwo(alignment));
+            }
+            let reqs = Requirements {
+                type_mask: 1 << self.id.0,
+                size: 1,
+                alignment,
+            };
+            if self.pick_node_for(reqs).is_err() {
+                return Err(AlignmentError::Unsatisfiable(alignment));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check if serving `reqs` would grow the allocator beyond `cap` bytes of chunks.
+    ///
+    /// Like `available_for` this has no side effects, so schedulers can defer allocations that
+    /// aren't critical while memory is tight instead of allocating a large chunk now.
+    /// Requirements this allocator can't serve never exceed the cap.
+    pub fn would_exceed_cap(&self, reqs: &Requirements, cap: u64) -> bool {
+        if (1 << self.id.0) & reqs.type_mask == 0 {
+            return false;
+        }
+        let index = match self.pick_node_for(*reqs) {
+            Ok(index) => index,
+            Err(_) => return false,
+        };
+        let chunk_size = match self.nodes.get(index as usize) {
+            Some(node) if node.has_free() => return false,
+            Some(node) => node.chunk_size,
+            None => self.chunk_size(index),
+        };
+        self.allocated() + chunk_size > cap
+    }
+
+    /// Take a snapshot of the state of each size class.
+    ///
+    /// The snapshot doesn't borrow the allocator and holds no memory objects, so it can be sent
+    /// to another thread for inspection.
+    pub fn view(&self) -> ChunkedView {
+        ChunkedView {
+            memory_type: self.id,
+            size_classes: self.nodes
+                .iter()
+                .map(|node| SizeClassView {
+                    block_size: node.block_size,
+                    chunk_size: node.chunk_size,
+                    chunks: node.chunk_count(),
+                    blocks: node.count(),
+                    free_blocks: node.free.len(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Report the lifetime usage of every size class, smallest block size first.
+    ///
+    /// Size classes that never served an allocation are candidates for removal, e.g. by
+    /// lowering `max_chunk_size` or with a custom `NodeSelector`.
+    pub fn class_utilization(&self) -> Vec<ClassUtil> {
+        (0..self.max_index() as usize + 1)
+            .map(|index| {
+                let node = self.nodes.get(index);
+                ClassUtil {
+                    block_size: self.block_size(index as u8),
+                    chunk_size: self.chunk_size(index as u8),
+                    peak_chunks: node.map(|node| node.peak_chunks).unwrap_or(0),
+                    allocations: node.map(|node| node.allocations).unwrap_or(0),
+                }
+            })
+            .collect()
+    }
+
+    /// Get the number of chunks currently allocated for each size class.
+    pub fn layout(&self) -> ChunkedLayout {
+        ChunkedLayout {
+            chunks: self.nodes.iter().map(ChunkedNode::chunk_count).collect(),
+        }
+    }
+
+    /// Iterate over the memory type, memory object and size of every chunk allocated from the
+    /// underlying allocator, e.g. to export the device memory backing this allocator to another
+    /// API.
+    ///
+    /// Chunks are yielded per size class, smallest block size first. Several chunks may share a
+    /// memory object if the underlying allocator sub-allocates them.
+    pub fn backing_allocations<'a, M>(&'a self) -> impl Iterator<Item = (MemoryTypeId, &'a M, u64)>
+    where
+        M: 'a,
+        T: Block<Memory = M>,
+    {
+        let id = self.id;
+        self.nodes
+            .iter()
+            .flat_map(|node| node.chunks.iter().filter_map(Option::as_ref))
+            .map(move |chunk| (id, chunk.memory(), chunk.size()))
+    }
+
+    /// Allocate chunks reserved by `with_reserved_layout` until every size class has at least as
+    /// many chunks as recorded in the layout.
+    ///
+    /// Size classes with blocks larger than `max_chunk_size` are ignored. If allocation fails the
+    /// reservation is kept, so this can be called again.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator used to allocate the chunks
+    /// - `device`: device to allocate the memory from
+    /// - `request`: information required by `owner` to allocate each chunk
+    pub fn reserve_layout<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        request: A::Request,
+    ) -> Result<(), MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+        A::Request: Clone,
+    {
+        self.check_owner::<B, A>(owner);
+        for index in 0..self.reserved.len() {
+            let count = self.reserved[index];
+            if count == 0 || index > self.max_index() as usize {
+                continue;
+            }
+            self.grow(index as u8);
+            let node = &mut self.nodes[index];
+            while node.chunk_count() < count {
+                if let Err(error) = node.grow(owner, device, request.clone()) {
+                    self.check_heap_warning();
+                    return Err(error);
+                }
+            }
+        }
+        self.reserved.clear();
+        self.check_heap_warning();
+        Ok(())
+    }
+
+    /// Get memory type of the allocator
+    pub fn memory_type(&self) -> MemoryTypeId {
+        self.id
+    }
+
+    /// Get minimum block size
+    pub fn min_block_size(&self) -> u64 {
+        self.block_size(0)
+    }
+
+    /// Get maximum chunk size
+    pub fn max_chunk_size(&self) -> u64 {
+        self.block_size(self.max_index())
+    }
+
+    /// Get the strategy defining the size classes.
+    pub fn selector(&self) -> &S {
+        &self.selector
+    }
+
+    /// Get the minimum alignment of blocks allocated by the underlying allocator
+    pub fn owner_min_alignment(&self) -> u64 {
+        self.owner_min_alignment
+    }
+
+    /// Get the granularity the size of chunks is rounded up to
+    pub fn chunk_granularity(&self) -> u64 {
+        self.chunk_granularity
+    }
+
+    /// Check if all blocks that fit into chunks larger than requested are used
+    pub fn fill_chunks(&self) -> bool {
+        self.fill_chunks
+    }
+
+    /// Get the container used to keep track of free blocks
+    pub fn free_list(&self) -> FreeList {
+        self.free_list
+    }
+
+    /// Retrieves the block backing an allocation.
+    pub fn underlying_block<M: Debug + Any>(&self, block: &ChunkedBlock<M>) -> &T {
+        let index = self.node_of(block).expect("Block wasn't allocated by this allocator");
+        self.nodes[index].chunk(block.1.chunk_index)
+    }
+
+    /// Allocate a whole chunk of the size class that would serve `reqs` as a single block.
+    ///
+    /// A new chunk is always allocated from the underlying allocator and none of its blocks are
+    /// handed out by regular allocations until the returned block is freed. Freeing the returned
+    /// block makes all blocks of the chunk available at once.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator used to allocate the chunk
+    /// - `device`: device to allocate the memory from
+    /// - `request`: information required by `owner` to allocate the chunk
+    /// - `reqs`: requirements used to pick the size class
+    pub fn alloc_whole_chunk<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        request: A::Request,
+        reqs: Requirements,
+    ) -> Result<ChunkedBlock<B::Memory>, MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        self.check_owner::<B, A>(owner);
+        if (1 << self.id.0) & reqs.type_mask == 0 {
+            return Err(MemoryError::NoCompatibleMemoryType);
+        }
+        self.check_churn()?;
+        let index = self.pick_node_for(reqs)?;
+        self.grow(index);
+        let (grows, free_blocks) = self.node_growth(index);
+        self.sync_clock(index as usize);
+        let block = self.nodes[index as usize].alloc_whole_chunk(owner, device, request)?;
+        self.report_grow(index, grows, free_blocks, &reqs, &block);
+        Ok(block)
+    }
+
+    /// Plan at most `max_relocations` moves of used blocks to compact memory.
+    ///
+    /// Used blocks are moved out of the sparsest chunks first, into free blocks of denser chunks,
+    /// starting from the size classes that waste the most memory. Calling this repeatedly
+    /// eventually compacts every size class, so the work can be spread across frames.
+    ///
+    /// For each returned `Relocation` the caller must copy the data from the relocated block to
+    /// `Relocation::block`, start using the latter and free the relocated block. Chunks emptied
+    /// this way can then be released with `shrink`.
+    pub fn defragment_budgeted<M>(&mut self, max_relocations: usize) -> Vec<Relocation<M>>
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        let mut order = (0..self.nodes.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&index| {
+            let node = &self.nodes[index];
+            Reverse(node.allocated() - node.used())
+        });
+
+        let mut relocations = Vec::new();
+        for index in order {
+            if relocations.len() >= max_relocations {
+                break;
+            }
+            self.sync_clock(index);
+            self.nodes[index].defragment(max_relocations, &mut relocations);
+        }
+        relocations
+    }
+
+    /// Move all used blocks into as few newly allocated chunks as possible.
+    ///
+    /// Unlike `shrink`, which only frees chunks that have no used blocks, this releases the slack
+    /// of partially used chunks back to the underlying allocator. Unused chunks are freed first.
+    /// Then each size class whose used blocks fit into fewer chunks than they occupy gets just
+    /// enough new chunks, and the moves of all its used blocks into them are planned. Chunks
+    /// holding blocks that cover several blocks, e.g. from `alloc_whole_chunk`, stay as they are.
+    ///
+    /// Relocations are handled as with `defragment_budgeted`. Once all of them are complete the
+    /// old chunks are unused and can be freed with `shrink`. Blocks allocated in the meantime
+    /// may still land in old chunks and keep them allocated.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator used to allocate the chunks
+    /// - `device`: device to allocate the memory from
+    /// - `request`: information required by `owner` to allocate each chunk
+    ///
+    /// ### Returns
+    ///
+    /// Relocations of used blocks into the new chunks. Size classes that new chunks can't be
+    /// allocated for are left as they are.
+    pub fn compact_to_fit<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        request: A::Request,
+    ) -> Vec<Relocation<B::Memory>>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+        A::Request: Clone,
+    {
+        self.shrink(owner, device);
+        let mut relocations = Vec::new();
+        for index in 0..self.nodes.len() {
+            self.sync_clock(index);
+            // Failure leaves the size class untouched
+            let _ = self.nodes[index].compact(owner, device, request.clone(), &mut relocations);
+        }
+        self.check_heap_warning();
+        relocations
+    }
+
+    /// Allocate a block, preferably from the same memory object as `hint`.
+    ///
+    /// If the size class that would serve `reqs` has a free block in a chunk bound to `hint`,
+    /// that block is allocated. Otherwise this is the same as a regular allocation. Keeping
+    /// related blocks in one memory object, e.g. buffers referenced by a single descriptor set,
+    /// allows fewer and larger bindings. This is a best-effort hint, not a guarantee.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator used to allocate chunks
+    /// - `device`: device to allocate the memory from
+    /// - `request`: information required by `owner` to allocate a chunk
+    /// - `reqs`: the requirements the memory block must meet
+    /// - `hint`: memory object to allocate from if possible
+    pub fn alloc_near<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        request: A::Request,
+        reqs: Requirements,
+        hint: Option<&B::Memory>,
+    ) -> Result<ChunkedBlock<B::Memory>, MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        if let Some(memory) = hint {
+            self.check_owner::<B, A>(owner);
+            if (1 << self.id.0) & reqs.type_mask == 0 {
+                return Err(MemoryError::NoCompatibleMemoryType);
+            }
+            self.check_churn()?;
+            let index = self.pick_node_for(reqs)?;
+            self.grow(index);
+            self.sync_clock(index as usize);
+            if let Some(block) = self.nodes[index as usize].alloc_in_memory(memory) {
+                self.update_registry();
+                return Ok(block);
+            }
+        }
+        MemorySubAllocator::<B, A>::alloc(self, owner, device, request, reqs)
+    }
+
+    /// Allocate a block meeting the requirements to bump-allocate tiny transient allocations
+    /// from, see `BumpChunk`.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator used to allocate chunks
+    /// - `device`: device to allocate the memory from
+    /// - `request`: information required by `owner` to allocate a chunk
+    /// - `reqs`: the requirements the whole block must meet
+    pub fn alloc_bump<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        request: A::Request,
+        reqs: Requirements,
+    ) -> Result<BumpChunk<B::Memory>, MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        MemorySubAllocator::<B, A>::alloc(self, owner, device, request, reqs).map(BumpChunk::new)
+    }
+
+    /// Allocate a block picking the size class purely by `size`, for hot paths that don't care
+    /// about alignment.
+    ///
+    /// Neither the memory type nor any alignment is checked. The block is still aligned to the
+    /// biggest power of two dividing its block size, which the caller vouches is sufficient.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator used to allocate chunks
+    /// - `device`: device to allocate the memory from
+    /// - `size`: minimal size of the block
+    /// - `request`: information required by `owner` to allocate a chunk
+    pub fn alloc_unaligned<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        size: u64,
+        request: A::Request,
+    ) -> Result<ChunkedBlock<B::Memory>, MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        self.check_owner::<B, A>(owner);
+        let index = self.pick_node(max(size, 1))?;
+        self.check_churn()?;
+        self.grow(index);
+        let (grows, free_blocks) = self.node_growth(index);
+        self.sync_clock(index as usize);
+        let block = {
+            let node = &mut self.nodes[index as usize];
+            match node.alloc_no_grow() {
+                Some(block) => block,
+                None => {
+                    node.grow(owner, device, request)?;
+                    node.alloc_no_grow().expect("Just growed")
+                }
+            }
+        };
+        let reqs = Requirements {
+            type_mask: 1 << self.id.0,
+            size,
+            alignment: 1,
+        };
+        self.report_grow(index, grows, free_blocks, &reqs, &block);
+        self.update_registry();
+        Ok(block)
+    }
+
+    /// Allocate a block that lies entirely within the first `max_offset` bytes of its memory
+    /// object, for bindings that only support low offsets.
+    ///
+    /// Free blocks of the size class are searched for the lowest one that qualifies. If there is
+    /// none a single chunk is grown, and its blocks are searched as well. Where chunks are placed
+    /// is up to the underlying allocator, so this can only succeed if it hands out chunks at low
+    /// offsets, e.g. at the start of dedicated memory objects. A chunk grown in vain is kept for
+    /// regular allocations.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator used to allocate chunks
+    /// - `device`: device to allocate the memory from
+    /// - `request`: information required by `owner` to allocate a chunk
+    /// - `reqs`: the requirements the memory block must meet
+    /// - `max_offset`: offset the block must end at or before
+    ///
+    /// ### Returns
+    ///
+    /// Returns `MemoryError::OutOfMemory` if no block below `max_offset` could be found.
+    pub fn alloc_low<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        request: A::Request,
+        reqs: Requirements,
+        max_offset: u64,
+    ) -> Result<ChunkedBlock<B::Memory>, MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        if (1 << self.id.0) & reqs.type_mask == 0 {
+            return Err(MemoryError::NoCompatibleMemoryType);
+        }
+        let index = self.pick_node_for(reqs)?;
+        let growth = self.before_alloc::<B, A>(owner, device, index)?;
+        let block = {
+            let node = &mut self.nodes[index as usize];
+            match node.alloc_low(max_offset) {
+                Some(block) => Some(block),
+                None => {
+                    node.grow(owner, device, request)?;
+                    node.alloc_low(max_offset)
+                }
+            }
+        };
+        match block {
+            Some(block) => {
+                self.after_alloc(index, growth, &reqs, &block);
+                Ok(block)
+            }
+            None => {
+                // The chunk grown in vain is kept
+                self.update_registry();
+                self.check_heap_warning();
+                Err(MemoryError::OutOfMemory)
+            }
+        }
+    }
+
+    /// Get the handle identifying a used block allocated by this allocator.
+    pub fn handle_of<M>(&self, block: &ChunkedBlock<M>) -> BlockHandle
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        let index = self.node_of(block).expect("Block wasn't allocated by this allocator");
+        let node = &self.nodes[index];
+        node.handle(node.first_block(block))
+    }
+
+    /// Mark the block as accessed now. Does nothing unless enabled by `with_lru_tracking`.
+    pub fn touch<M>(&mut self, block: &ChunkedBlock<M>)
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        let index = self.node_of(block).expect("Block wasn't allocated by this allocator");
+        self.sync_clock(index);
+        let node = &mut self.nodes[index];
+        let first = node.first_block(block);
+        node.touch(first);
+    }
+
+    /// Get the handle of the used block that was accessed least recently, as the one to free
+    /// first under memory pressure.
+    ///
+    /// ### Returns
+    ///
+    /// `None` if there are no tracked blocks, e.g. because LRU tracking is disabled.
+    pub fn lru_candidate(&self) -> Option<BlockHandle> {
+        self.nodes
+            .iter()
+            .filter_map(|node| node.lru_candidate().map(|(time, first)| (time, node, first)))
+            .min_by_key(|&(time, _, _)| time)
+            .map(|(_, node, first)| node.handle(first))
+    }
+
+    /// Bring the clock of the node up to the latest access time of all nodes, so accesses in
+    /// different size classes stay ordered for `lru_candidate`.
+    fn sync_clock(&mut self, index: usize) {
+        if !self.lru || index >= self.nodes.len() {
+            return;
+        }
+        let clock = self.nodes.iter().map(|node| node.clock).max().unwrap_or(0);
+        self.nodes[index].clock = clock;
+    }
+
+    /// Take a used block out of the allocator together with its chunk, e.g. for a persistent
+    /// resource that outlives an otherwise transient allocator.
+    ///
+    /// The chunk is no longer accounted for by the allocator, so it can be disposed while the
+    /// detached block is in use. The chunk must eventually be freed to the underlying allocator
+    /// it was allocated from, see `DetachedBlock::into_chunk`.
+    ///
+    /// ### Returns
+    ///
+    /// If other blocks of the chunk are in use, this will return `Err(block)`.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if the block wasn't allocated by this allocator.
+    pub fn detach<M>(
+        &mut self,
+        block: ChunkedBlock<M>,
+    ) -> Result<DetachedBlock<T>, ChunkedBlock<M>>
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        let index = match self.node_of(&block) {
+            Ok(index) => index,
+            Err(error) => panic!("{}: {:?}", error, block),
+        };
+        if !self.nodes[index].is_sole_user(&block) {
+            return Err(block);
+        }
+        self.release_scope(&block);
+        let (chunk, range) = self.nodes[index].detach(block);
+        self.update_registry();
+        self.check_heap_warning();
+        Ok(DetachedBlock { chunk, range })
+    }
+
+    /// Move a chunk together with its used blocks to another allocator without freeing it to
+    /// the underlying allocator, e.g. to hand memory over from a transient allocator to a
+    /// long-lived one.
+    ///
+    /// The used blocks of the chunk keep their memory and stay valid, but must be freed to
+    /// `dest` from then on. Their tags are updated to the index of the chunk in `dest`, and they
+    /// are no longer accounted to a scope. Their access times, frames, generations and blocks
+    /// freed with `free_deferred` move along with the chunk. The free blocks of the chunk are
+    /// removed from the free list of this allocator and added to the free list of `dest`, which
+    /// frees the chunk to the underlying allocator from then on. Nothing is changed on failure.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `block_size`: block size of the size class the chunk belongs to
+    /// - `chunk_index`: index of the chunk, as returned by `ChunkedBlock::chunk_index`
+    /// - `blocks`: all used blocks of the chunk, except the ones freed with `free_deferred`
+    /// - `dest`: allocator to move the chunk to
+    ///
+    /// ### Returns
+    ///
+    /// The index of the chunk in `dest`. `MemoryError::InvalidBlock` if some of `blocks` don't
+    /// belong to the chunk or are passed twice, and `MemoryError::ChunkInUse` if some used
+    /// blocks of the chunk are missing from `blocks`.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if there is no chunk with such index in the size class, or if `dest` has a
+    /// different memory type, different size classes, different `fill_chunks` or a different
+    /// owner.
+    pub fn transfer_block<M>(
+        &mut self,
+        block_size: u64,
+        chunk_index: usize,
+        blocks: &mut [ChunkedBlock<M>],
+        dest: &mut ChunkedAllocator<T, S>,
+    ) -> Result<usize, MemoryError>
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        assert_eq!(self.id, dest.id, "Chunk is transferred to another memory type");
+        assert!(
+            self.ladder == dest.ladder && self.fill_chunks == dest.fill_chunks,
+            "Chunk is transferred to an allocator with a different configuration"
+        );
+        if let (Some(owner), Some(expected)) = (self.owner, dest.owner) {
+            assert_eq!(
+                expected, owner,
+                "Chunk is transferred to an allocator with a different owner"
+            );
+        }
+        let index = self.size_class_index(block_size);
+        let used = self.chunk_blocks_in_use(index, chunk_index, blocks)?;
+
+        self.sync_clock(index);
+        for block in blocks.iter() {
+            self.release_scope(block);
+        }
+        let moved = self.nodes[index].take_moved_chunk(chunk_index, &used);
+        let mut deferred = Vec::new();
+        for (&frame, frees) in &mut self.deferred {
+            let (moved, kept) = frees.drain(..).partition::<Vec<_>, _>(|deferred| {
+                deferred.node == index && deferred.first.chunk_index == chunk_index
+            });
+            *frees = kept;
+            deferred.extend(moved.into_iter().map(|moved| (frame, moved)));
+        }
+        self.deferred.retain(|_, frees| !frees.is_empty());
+        self.update_registry();
+        self.check_heap_warning();
+
+        if dest.owner.is_none() {
+            dest.owner = self.owner;
+        }
+        dest.grow(index as u8);
+        dest.sync_clock(index);
+        let dest_index = dest.nodes[index].put_moved_chunk(moved);
+        for block in blocks.iter_mut() {
+            block.1.chunk_index = dest_index;
+            block.1.scope = None;
+        }
+        for (frame, mut moved) in deferred {
+            moved.first.chunk_index = dest_index;
+            dest.deferred.entry(frame).or_default().push(moved);
+        }
+        dest.update_registry();
+        dest.check_heap_warning();
+        Ok(dest_index)
+    }
+
+    /// Check that `blocks` are exactly the used blocks of the chunk, except the ones freed with
+    /// `free_deferred`. Returns the first block covered by each of them.
+    fn chunk_blocks_in_use<M>(
+        &self,
+        index: usize,
+        chunk_index: usize,
+        blocks: &[ChunkedBlock<M>],
+    ) -> Result<Vec<FreeBlock>, MemoryError>
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        let node = &self.nodes[index];
+        // Panics if there is no such chunk
+        node.chunk(chunk_index);
+        let mut used = Vec::with_capacity(blocks.len());
+        let mut covered = 0;
+        for block in blocks {
+            if self.node_of(block)? != index || block.1.chunk_index != chunk_index {
+                return Err(MemoryError::InvalidBlock);
+            }
+            node.check(block)?;
+            used.push(node.first_block(block));
+            covered += block.1.span;
+        }
+        if used.iter().collect::<BTreeSet<_>>().len() != used.len() {
+            return Err(MemoryError::InvalidBlock);
+        }
+
+        let free = node.free
+            .iter()
+            .filter(|free_block| free_block.chunk_index == chunk_index)
+            .count();
+        let deferred = self.deferred
+            .values()
+            .flat_map(|frees| frees.iter())
+            .filter(|deferred| deferred.node == index && deferred.first.chunk_index == chunk_index)
+            .map(|deferred| deferred.span)
+            .sum::<usize>();
+        if free + deferred + covered != node.chunk_blocks(chunk_index) {
+            return Err(MemoryError::ChunkInUse);
+        }
+        Ok(used)
+    }
+
+    /// Set the frame allocations are stamped with, see `with_frame_stamps`.
+    pub fn set_frame(&mut self, frame: u64) {
+        self.frame = frame;
+        for node in &mut self.nodes {
+            node.frame = frame;
+        }
+    }
+
+    /// Get the handles of the used blocks allocated before `frame`, oldest first, e.g. to find
+    /// temporary allocations that were never freed.
+    ///
+    /// ### Returns
+    ///
+    /// No handles unless frame stamping is enabled by `with_frame_stamps`.
+    pub fn allocations_older_than(&self, frame: u64) -> Vec<BlockHandle> {
+        let mut stamps = self.nodes
+            .iter()
+            .flat_map(|node| {
+                node.stamps.blocks.iter().filter_map(move |(&first, stamp)| match stamp.frame {
+                    Some(stamp) if stamp < frame => Some((stamp, node.handle(first))),
+                    _ => None,
+                })
+            })
+            .collect::<Vec<_>>();
+        stamps.sort();
+        stamps.into_iter().map(|(_, handle)| handle).collect()
+    }
+
+    /// Get the memory object and range of the block the next allocation meeting `reqs` would
+    /// receive, without allocating it.
+    ///
+    /// This allows e.g. preparing descriptor writes ahead of the allocation. The result stays
+    /// valid until the allocator is used again, except for allocations meeting other
+    /// requirements that are served by other size classes.
+    ///
+    /// ### Returns
+    ///
+    /// `None` if the allocation would fail or would have to grow the size class.
+    pub fn peek_next<M>(&self, reqs: Requirements) -> Option<(*const M, Range<u64>)>
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        if (1 << self.id.0) & reqs.type_mask == 0 {
+            return None;
+        }
+        let node = self.nodes.get(self.pick_node_for(reqs).ok()? as usize)?;
+        let free_block = node.next_free()?;
+        let chunk = node.chunk(free_block.chunk_index);
+        let offset = free_block.block_index * node.block_size + chunk.range().start;
+        Some((chunk.memory() as *const M, offset..offset + node.block_size))
+    }
+
+    /// Allocate the block at the specified place, e.g. to reproduce a captured layout.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `block_size`: block size of the size class to allocate from
+    /// - `chunk_index`: index of the chunk, as returned by `ChunkedBlock::chunk_index`
+    /// - `offset`: offset of the block from the start of the chunk, a multiple of `block_size`
+    ///
+    /// ### Returns
+    ///
+    /// Returns `MemoryError::InvalidBlock` if there is no such chunk or the offset is not the
+    /// offset of a block in it, and `MemoryError::BlockInUse` if the block is already allocated.
+    pub fn alloc_at_offset<M>(
+        &mut self,
+        block_size: u64,
+        chunk_index: usize,
+        offset: u64,
+    ) -> Result<ChunkedBlock<M>, MemoryError>
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        let index = match self.node_with_block_size(block_size) {
+            Some(index) => index as usize,
+            None => return Err(MemoryError::InvalidBlock),
+        };
+        self.sync_clock(index);
+        match self.nodes.get_mut(index) {
+            Some(node) => node.alloc_at(chunk_index, offset),
+            None => Err(MemoryError::InvalidBlock),
+        }
+    }
+
+    /// Allocate a block without growing if there is a free block in a larger size class.
+    ///
+    /// If the size class that would serve `reqs` has no free blocks, the allocated block is
+    /// carved out of a free block of the nearest larger size class instead of allocating a new
+    /// chunk. The rest of the larger block stays unused until the block is freed. Only if no
+    /// larger size class has a free block a new chunk is allocated as usual.
+    ///
+    /// This trades internal fragmentation for fewer allocations from the underlying allocator,
+    /// e.g. under memory pressure.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator used to allocate chunks
+    /// - `device`: device to allocate the memory from
+    /// - `request`: information required by `owner` to allocate a chunk
+    /// - `reqs`: the requirements the memory block must meet
+    pub fn alloc_allow_oversize_split<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        request: A::Request,
+        reqs: Requirements,
+    ) -> Result<ChunkedBlock<B::Memory>, MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        if (1 << self.id.0) & reqs.type_mask == 0 {
+            return Err(MemoryError::NoCompatibleMemoryType);
+        }
+        let index = self.pick_node_for(reqs)?;
+        let growth = self.before_alloc::<B, A>(owner, device, index)?;
+
+        if !self.nodes[index as usize].has_free() {
+            let larger = (index as usize + 1..self.nodes.len())
+                .find(|&larger| self.nodes[larger].has_free());
+            if let Some(larger) = larger {
+                self.sync_clock(larger);
+                let ChunkedBlock(block, mut tag) = self.nodes[larger]
+                    .alloc_no_grow::<B::Memory>()
+                    .expect("Size class has free blocks");
+                // Keep only the beginning of the larger block, which is aligned to its size
+                let range = block.range();
+                let memory = block.memory() as *const _;
+                unsafe { block.dispose() };
+                tag.parent = Some(range.end - range.start);
+                let block_size = self.block_size(index);
+                let block = RawBlock::new(memory, range.start..range.start + block_size);
+                let block = ChunkedBlock(block, tag);
+                self.after_alloc(index, growth, &reqs, &block);
+                return Ok(block);
+            }
+        }
+        let block = self.nodes[index as usize].alloc(owner, device, request, reqs)?;
+        self.after_alloc(index, growth, &reqs, &block);
+        Ok(block)
+    }
+
+    /// Allocate `count` blocks meeting the requirements.
+    ///
+    /// Either all blocks are allocated, or none are: on failure the blocks allocated so far are
+    /// freed before the error is returned.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator used to allocate chunks
+    /// - `device`: device to allocate the memory from
+    /// - `request`: information required by `owner` to allocate a chunk
+    /// - `reqs`: the requirements each memory block must meet
+    /// - `count`: number of blocks to allocate
+    pub fn alloc_many<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        request: A::Request,
+        reqs: Requirements,
+        count: usize,
+    ) -> Result<Vec<ChunkedBlock<B::Memory>>, MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+        A::Request: Clone,
+    {
+        let mut blocks = Vec::with_capacity(count);
+        for _ in 0..count {
+            match MemorySubAllocator::<B, A>::alloc(self, owner, device, request.clone(), reqs) {
+                Ok(block) => blocks.push(block),
+                Err(error) => {
+                    for block in blocks {
+                        MemorySubAllocator::<B, A>::free(self, owner, device, block);
+                    }
+                    return Err(error);
+                }
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Allocate a block of any size between `min` and `max` bytes.
+    ///
+    /// The smallest size class in that range that has a free block is used, so memory that is
+    /// already allocated is reused. Only if there is none the smallest size class of at least
+    /// `min` bytes grows. The size of the block granted is `block.size()`.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator used to allocate chunks
+    /// - `device`: device to allocate the memory from
+    /// - `min`: minimum size of the block in bytes
+    /// - `max`: maximum size of the block in bytes
+    /// - `alignment`: alignment of the block in bytes
+    /// - `request`: information required by `owner` to allocate a chunk
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `min` is greater than `max`.
+    pub fn alloc_flexible<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        min: u64,
+        max: u64,
+        alignment: u64,
+        request: A::Request,
+    ) -> Result<ChunkedBlock<B::Memory>, MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        assert!(min <= max, "Size range {}..={} is empty", min, max);
+        let reqs = |size| Requirements {
+            type_mask: 1 << self.id.0,
+            size,
+            alignment,
+        };
+        let smallest = self.pick_node_for(reqs(min.max(1)))?;
+        let index = (smallest..self.nodes.len() as u8)
+            .take_while(|&index| self.block_size(index) <= max)
+            .find(|&index| {
+                let block_size = self.block_size(index);
+                self.nodes[index as usize].has_free()
+                    && alignment <= block_size & block_size.wrapping_neg()
+            })
+            .unwrap_or(smallest);
+        let block_size = self.block_size(index);
+        if block_size > max {
+            return Err(MemoryError::OutOfMemory);
+        }
+        let reqs = reqs(block_size);
+        MemorySubAllocator::<B, A>::alloc(self, owner, device, request, reqs)
+    }
+
+    /// Allocate a block for the mip tail of a sparse image.
+    ///
+    /// Both the offset and the size of the block are multiples of `granularity`, e.g. the sparse
+    /// image granularity of the device, in addition to meeting `reqs`.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator used to allocate chunks
+    /// - `device`: device to allocate the memory from
+    /// - `reqs`: the requirements the memory block must meet
+    /// - `granularity`: granularity of the offset and size of the block in bytes
+    /// - `request`: information required by `owner` to allocate a chunk
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `granularity` is not a power of two.
+    pub fn alloc_mip_tail<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        reqs: Requirements,
+        granularity: u64,
+        request: A::Request,
+    ) -> Result<ChunkedBlock<B::Memory>, MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        assert!(
+            granularity.is_power_of_two(),
+            "Granularity {} is not a power of two",
+            granularity
+        );
+        // Blocks are aligned to their size, so blocks of a multiple of the granularity are
+        // placed at multiples of it
+        let reqs = Requirements {
+            size: max(reqs.size, 1) + alignment_shift(granularity, max(reqs.size, 1)),
+            alignment: max(reqs.alignment, granularity),
+            ..reqs
+        };
+        MemorySubAllocator::<B, A>::alloc(self, owner, device, request, reqs)
+    }
+
+    /// Free a block and allocate another one, reusing the memory of the freed block if possible.
+    ///
+    /// If `reqs` are served by the same size class as `old`, the block covering the same memory
+    /// as `old` is returned. This keeps e.g. ping-pong buffers in memory that is likely still
+    /// cached or resident, which separate calls to `free` and `alloc` don't guarantee.
+    /// Otherwise the new block is allocated before `old` is freed.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator used to allocate chunks
+    /// - `device`: device to allocate the memory from
+    /// - `old`: block to free
+    /// - `request`: information required by `owner` to allocate a chunk
+    /// - `reqs`: the requirements the new block must meet
+    ///
+    /// ### Returns
+    ///
+    /// The new block. If it can't be allocated, the error is returned together with `old`,
+    /// which stays valid.
+    pub fn realloc<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        old: ChunkedBlock<B::Memory>,
+        request: A::Request,
+        reqs: Requirements,
+    ) -> ReallocResult<B::Memory>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        let index = self.pick_node_for(reqs).ok().map(|index| index as usize);
+        let reuse = match self.node_of(&old) {
+            Ok(node)
+                if Some(node) == index && old.1.span == 1 && old.1.parent.is_none()
+                    && (1 << self.id.0) & reqs.type_mask != 0 =>
+            {
+                Some((node, self.nodes[node].first_block(&old)))
+            }
+            _ => None,
+        };
+
+        if let Some((node, first)) = reuse {
+            self.check_owner::<B, A>(owner);
+            self.release_scope(&old);
+            self.sync_clock(node);
+            // Freeing without the free chunk limits keeps the chunk, so the block is free again
+            let node = &mut self.nodes[node];
+            node.free_block(old);
+            let offset = first.block_index * node.block_size;
+            return Ok(node.alloc_at(first.chunk_index, offset).expect("Block was freed"));
+        }
+        match MemorySubAllocator::<B, A>::alloc(self, owner, device, request, reqs) {
+            Ok(block) => {
+                MemorySubAllocator::<B, A>::free(self, owner, device, old);
+                Ok(block)
+            }
+            Err(error) => Err((error, old)),
+        }
+    }
+
+    /// Verify consistency of the internal state of the allocator.
+    ///
+    /// Every free block must belong to an allocated chunk and be listed once, no size class can
+    /// have more free blocks than blocks, and size classes must be ordered by ascending chunk
+    /// size. Meant to be called by tests after each operation.
+    pub fn check_invariants(&self) -> Result<(), InvariantViolation>
+    where
+        T: Block,
+    {
+        for (index, node) in self.nodes.iter().enumerate() {
+            node.check_invariants(index)?;
+            if index > 0 && node.chunk_size < self.nodes[index - 1].chunk_size {
+                return Err(InvariantViolation::NodesOutOfOrder { node: index });
+            }
+        }
+        Ok(())
+    }
+
+    /// Free a block of memory.
+    ///
+    /// Unlike `free` this doesn't panic if the block doesn't cover exactly the span of blocks it
+    /// was allocated with. The block is returned alongside `MemoryError::PartialFree` instead, and
+    /// the allocator is left untouched. Likewise `MemoryError::InvalidBlock` is returned if the
+    /// size of the block doesn't match any size class of the allocator, or with the `checks`
+    /// feature if the block wasn't allocated from the size class its size matches.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator that was used to allocate the chunks
+    /// - `device`: same device that was used to allocate the block of memory
+    /// - `block`: block of memory to free
+    pub fn try_free<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        block: ChunkedBlock<B::Memory>,
+    ) -> Result<(), (MemoryError, ChunkedBlock<B::Memory>)>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        self.check_owner::<B, A>(owner);
+        let index = match self.node_of(&block) {
+            Ok(index) => index,
+            Err(error) => return Err((error, block)),
+        };
+        if let Err(error) = self.nodes[index].check(&block) {
+            return Err((error, block));
+        }
+        self.release_scope(&block);
+        self.nodes[index].free(owner, device, block);
+        Ok(())
+    }
+
+    /// Free a block once the GPU can no longer access it.
+    ///
+    /// The block is released immediately, but its memory isn't reused until `collect` is called
+    /// with `safe_frame` at or after `frame`. Until then it is still counted as used.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `block`: block of memory to free
+    /// - `frame`: last frame that may access the block
+    pub fn free_deferred<M>(&mut self, block: ChunkedBlock<M>, frame: u64)
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        let node = match self.node_of(&block) {
+            Ok(node) => node,
+            Err(error) => panic!("{}: {:?}", error, block),
+        };
+        self.release_scope(&block);
+        let (first, span) = self.nodes[node].take_block(block);
+        self.deferred
+            .entry(frame)
+            .or_default()
+            .push(DeferredFree { node, first, span });
+    }
+
+    /// Make memory of blocks freed with `free_deferred` for frames up to `safe_frame` available
+    /// for reuse.
+    ///
+    /// Each block is freed the same way as with `free`, so chunks left unused may be freed.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator that was used to allocate the chunks
+    /// - `device`: same device that was used to allocate the chunks
+    /// - `safe_frame`: last frame the GPU is done with
+    ///
+    /// ### Returns
+    ///
+    /// The number of blocks made available.
+    pub fn collect<B, A>(&mut self, owner: &mut A, device: &B::Device, safe_frame: u64) -> usize
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        self.check_owner::<B, A>(owner);
+        let mut count = 0;
+        loop {
+            let frame = match self.deferred.keys().next() {
+                Some(&frame) if frame <= safe_frame => frame,
+                _ => break,
+            };
+            for deferred in self.deferred.remove(&frame).unwrap() {
+                self.nodes[deferred.node].release(owner, device, deferred.first, deferred.span);
+                self.after_free::<B, A>(owner, device);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Get the handle of the named scope, creating the scope if it doesn't exist yet.
+    ///
+    /// Blocks allocated with `alloc_scoped` are accounted to the scope until freed, so memory
+    /// usage can be reported per subsystem with `scope_usage`.
+    pub fn scope(&mut self, name: &str) -> ScopeHandle {
+        let open = self.scopes
+            .iter()
+            .position(|scope| scope.name.as_deref() == Some(name));
+        let index = match open {
+            Some(index) => index,
+            None => {
+                // Reuse the slot of a closed scope, whose handles have an older generation
+                let closed = self.scopes.iter().position(|scope| scope.name.is_none());
+                let index = closed.unwrap_or_else(|| {
+                    self.scopes.push(Scope::default());
+                    self.scopes.len() - 1
+                });
+                self.scopes[index].name = Some(name.to_owned());
+                index
+            }
+        };
+        ScopeHandle {
+            allocator: self.instance_id,
+            index,
+            generation: self.scopes[index].generation,
+        }
+    }
+
+    /// Close a scope, so its name can be used for a new scope.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `scope` is not an open scope of this allocator, or blocks are still accounted
+    /// to it.
+    pub fn close_scope(&mut self, scope: ScopeHandle) {
+        let index = self.scope_index(scope);
+        let scope = &mut self.scopes[index];
+        assert_eq!(scope.stats.blocks, 0, "Scope {:?} has blocks accounted", scope.name);
+        scope.name = None;
+        scope.generation += 1;
+    }
+
+    /// Get usage of the named scope. Unknown scopes have no usage.
+    pub fn scope_usage(&self, name: &str) -> ScopeStats {
+        self.scopes
+            .iter()
+            .find(|scope| scope.name.as_deref() == Some(name))
+            .map(|scope| scope.stats)
+            .unwrap_or_default()
+    }
+
+    /// Get the index of an open scope of this allocator.
+    fn scope_index(&self, scope: ScopeHandle) -> usize {
+        assert_eq!(
+            scope.allocator, self.instance_id,
+            "Scope handle of another allocator"
+        );
+        assert!(
+            self.scopes
+                .get(scope.index)
+                .is_some_and(|open| open.generation == scope.generation),
+            "Scope is closed"
+        );
+        scope.index
+    }
+
+    /// Allocate a block accounted to a scope.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator used to allocate chunks
+    /// - `device`: device to allocate the memory from
+    /// - `scope`: scope returned by `scope` to account the block to
+    /// - `request`: information required by `owner` to allocate a chunk
+    /// - `reqs`: the requirements the memory block must meet
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `scope` is not an open scope of this allocator.
+    pub fn alloc_scoped<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        scope: ScopeHandle,
+        request: A::Request,
+        reqs: Requirements,
+    ) -> Result<ChunkedBlock<B::Memory>, MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        let index = self.scope_index(scope);
+        let mut block = MemorySubAllocator::<B, A>::alloc(self, owner, device, request, reqs)?;
+        let stats = &mut self.scopes[index].stats;
+        stats.bytes += block.size();
+        stats.blocks += 1;
+        block.1.scope = Some(index);
+        Ok(block)
+    }
+
+    fn release_scope<M: Debug + Any>(&mut self, block: &ChunkedBlock<M>) {
+        // Scopes with blocks accounted can't be closed, so the scope of the block is open
+        if let Some(scope) = block.1.scope {
+            let stats = &mut self.scopes[scope].stats;
+            stats.bytes -= block.size();
+            stats.blocks -= 1;
+        }
+    }
+
+    /// Get the total size of all blocks allocated by this allocator.
+    pub fn used(&self) -> u64 {
+        self.nodes.iter().map(|node| node.used()).sum()
+    }
+
+    /// Get the total size of all chunks allocated by this allocator.
+    pub fn allocated(&self) -> u64 {
+        self.nodes.iter().map(|node| node.allocated()).sum()
+    }
+
+    /// Get the total size of all chunks taken from the underlying allocator.
+    ///
+    /// Unlike `allocated` this sums the actual sizes of the chunks, which may be larger than
+    /// requested. Summed over all sub-allocators it should match what the underlying allocator
+    /// reports as used.
+    pub fn owner_bytes_held(&self) -> u64
+    where
+        T: Block,
+    {
+        self.nodes
+            .iter()
+            .flat_map(|node| node.chunks.iter().filter_map(Option::as_ref))
+            .map(|chunk| chunk.size())
+            .sum()
+    }
+
+    /// Record the current memory usage into the history.
+    ///
+    /// Meant to be called once per frame, the history can then be used to plot trends or to
+    /// defragment when fragmentation keeps rising. Samples older than the last `history_len`
+    /// are dropped.
+    pub fn record_sample(&mut self) {
+        if self.history_len == 0 {
+            return;
+        }
+        // Drop old samples in batches, so recording stays cheap and `history` can be a slice
+        if self.history.len() >= self.history_len * 2 {
+            let start = self.history.len() - self.history_len + 1;
+            self.history.drain(..start);
+        }
+        let sample = ChunkedSample {
+            allocated: self.allocated(),
+            used: self.used(),
+        };
+        self.history.push(sample);
+    }
+
+    /// Get the samples recorded by `record_sample`, oldest first.
+    pub fn history(&self) -> &[ChunkedSample] {
+        let start = self.history.len().saturating_sub(self.history_len);
+        &self.history[start..]
+    }
+
+    /// Get the number of chunks currently allocated by this allocator.
+    pub fn chunk_count(&self) -> usize {
+        self.nodes.iter().map(|node| node.chunk_count()).sum()
+    }
+
+    /// Get the number of allocations, frees and chunks allocated since the last call to
+    /// `frame_boundary`.
+    pub fn churn_stats(&self) -> ChurnStats {
+        let total = self.total_churn();
+        ChurnStats {
+            allocations: total.allocations - self.frame_start.allocations,
+            frees: total.frees - self.frame_start.frees,
+            grows: total.grows - self.frame_start.grows,
+        }
+    }
+
+    /// Check if the allocations and frees since the last call to `frame_boundary` exceed the
+    /// limit set with `with_churn_limit`.
+    pub fn churn_exceeded(&self) -> bool {
+        match self.churn_limit {
+            Some(limit) => {
+                let churn = self.churn_stats();
+                churn.allocations + churn.frees > limit
+            }
+            None => false,
+        }
+    }
+
+    /// Start counting churn of a new frame.
+    ///
+    /// ### Returns
+    ///
+    /// The churn of the frame that ended.
+    pub fn frame_boundary(&mut self) -> ChurnStats {
+        let exceeded = self.churn_exceeded();
+        let churn = self.churn_stats();
+        self.frame_start = self.total_churn();
+        if exceeded {
+            if let Some(Callback(ref mut callback)) = self.on_churn_limit {
+                callback(churn);
+            }
+        }
+        churn
+    }
+
+    /// Set a callback that is called by `frame_boundary` with the churn of the frame that ended
+    /// if it exceeded the limit set with `with_churn_limit`, replacing the previous one.
+    ///
+    /// This allows logging frames with excessive churn without checking `churn_exceeded`
+    /// every frame.
+    pub fn on_churn_limit(&mut self, callback: Box<dyn FnMut(ChurnStats) + Send + Sync>) {
+        self.on_churn_limit = Some(Callback(callback));
+    }
+
+    /// Sum the churn of the size classes over the lifetime of the allocator.
+    fn total_churn(&self) -> ChurnStats {
+        self.nodes.iter().fold(ChurnStats::default(), |total, node| ChurnStats {
+            allocations: total.allocations + node.churn.allocations,
+            frees: total.frees + node.churn.frees,
+            grows: total.grows + node.churn.grows,
+        })
+    }
+
+    /// Fail if the churn limit is reached in strict mode.
+    fn check_churn(&self) -> Result<(), MemoryError> {
+        match self.churn_limit {
+            Some(limit) if self.strict_churn => {
+                let churn = self.churn_stats();
+                if churn.allocations + churn.frees >= limit {
+                    return Err(MemoryError::ChurnLimit);
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Get the number of chunks allocated from the underlying allocator over the lifetime of this
+    /// allocator, including chunks that were freed since.
+    pub fn grow_count(&self) -> u64 {
+        self.nodes.iter().map(|node| node.grows).sum()
+    }
+
+    /// Record allocation statistics of this allocator as gauges into a `metrics` recorder.
+    ///
+    /// Gauges are recorded per block size and labeled with `memory_type`, `block_size` and
+    /// `chunk_size`:
+    ///
+    /// - `gfx_memory_chunked_reserved_bytes`: total size of chunks reserved from the
+    ///   underlying allocator
+    /// - `gfx_memory_chunked_used_bytes`: total size of used blocks
+    /// - `gfx_memory_chunked_wasted_bytes`: size of reserved but unused memory
+    /// - `gfx_memory_chunked_block_count`: number of chunks, which are the blocks of the
+    ///   underlying allocator
+    /// - `gfx_memory_chunked_grow_count`: number of chunks allocated over the lifetime
+    #[cfg(feature = "metrics")]
+    pub fn export_metrics<R>(&self, recorder: &R)
+    where
+        R: ::metrics::Recorder,
+    {
+        let memory_type = self.id.0.to_string();
+        ::metrics::with_local_recorder(recorder, || {
+            for node in &self.nodes {
+                let labels = [
+                    ("memory_type", memory_type.clone()),
+                    ("block_size", node.block_size.to_string()),
+                    ("chunk_size", node.chunk_size.to_string()),
+                ];
+                gauge!("gfx_memory_chunked_reserved_bytes", &labels).set(node.allocated() as f64);
+                gauge!("gfx_memory_chunked_used_bytes", &labels).set(node.used() as f64);
+                gauge!("gfx_memory_chunked_wasted_bytes", &labels)
+                    .set((node.allocated() - node.used()) as f64);
+                gauge!("gfx_memory_chunked_block_count", &labels)
+                    .set(node.chunk_count() as f64);
+                gauge!("gfx_memory_chunked_grow_count", &labels).set(node.grows as f64);
+            }
+        });
+    }
+
+    /// Get the soft memory pressure threshold in bytes.
+    pub fn pressure_threshold(&self) -> Option<u64> {
+        self.pressure_threshold
+    }
+
+    /// Set the soft memory pressure threshold in bytes.
+    ///
+    /// When allocating a new chunk would make the total size of allocated chunks exceed the
+    /// threshold, the allocator first frees all unused chunks by calling `shrink`.
+    /// Growing is still allowed beyond the threshold. `None` disables the threshold.
+    pub fn set_pressure_threshold(&mut self, threshold: Option<u64>) {
+        self.pressure_threshold = threshold;
+    }
+
+    /// Set a callback that is called whenever an allocation makes a size class allocate a new
+    /// chunk from the underlying allocator, replacing the previous one.
+    ///
+    /// The event tells which allocation caused the device round-trip, so it can be avoided by
+    /// reserving chunks ahead or tuning the size classes. Chunks allocated ahead of allocations,
+    /// e.g. by `process_prefetch` or `reserve_layout`, are not reported.
+    pub fn on_grow(&mut self, callback: Box<dyn FnMut(GrowEvent) + Send + Sync>) {
+        self.on_grow = Some(Callback(callback));
+    }
+
+    /// Set the size of the heap the memory type of the allocator belongs to, in bytes.
+    ///
+    /// Whenever growing the allocator makes the total size of allocated chunks cross
+    /// `warn_fraction` of the heap size, the callback set with `on_heap_warning` is called. This
+    /// gives an early warning to start evicting before allocations fail. `None` disables the
+    /// warning.
+    pub fn set_heap_size(&mut self, heap_size: Option<u64>) {
+        self.heap_size = heap_size;
+    }
+
+    /// Set the fraction of the heap size that triggers the heap warning. 0.9 by default.
+    pub fn set_warn_fraction(&mut self, warn_fraction: f32) {
+        self.warn_fraction = warn_fraction;
+    }
+
+    /// Set a callback that is called when growing crosses `warn_fraction` of the heap size set
+    /// with `set_heap_size`, replacing the previous one.
+    ///
+    /// The check happens whenever chunks are allocated, including ahead of allocations by
+    /// `process_prefetch`, `reserve_layout` and `compact_to_fit`, so allocations that don't grow
+    /// the allocator cost nothing. The warning is given once until the allocated chunks drop
+    /// below the threshold again.
+    pub fn on_heap_warning(&mut self, callback: Box<dyn FnMut(HeapWarning) + Send + Sync>) {
+        self.on_heap_warning = Some(Callback(callback));
+    }
+
+    /// Get the number of times the size class has grown and its number of free blocks.
+    fn node_growth(&self, index: u8) -> (u64, usize) {
+        let node = &self.nodes[index as usize];
+        (node.grows, node.free.len())
+    }
+
+    /// Report the allocation of the block to the `on_grow` callback if the size class grew since
+    /// it had grown `grows` times and had `free_blocks` free blocks, and check the heap warning.
+    fn report_grow<M>(
+        &mut self,
+        index: u8,
+        grows: u64,
+        free_blocks: usize,
+        reqs: &Requirements,
+        block: &ChunkedBlock<M>,
+    ) where
+        M: Debug + Any,
+    {
+        let chunk_size = {
+            let node = &self.nodes[index as usize];
+            if node.grows == grows {
+                return;
+            }
+            node.chunk_size
+        };
+        if let Some(Callback(ref mut callback)) = self.on_grow {
+            callback(GrowEvent {
+                chunk_size,
+                size: reqs.size,
+                alignment: reqs.alignment,
+                free_blocks,
+                block_size: block.size(),
+            });
+        }
+
+        self.check_heap_warning();
+    }
+
+    /// Run the checks every allocation goes through and prepare the size class to serve it,
+    /// releasing unused chunks first if growing it would exceed the pressure threshold.
+    ///
+    /// ### Returns
+    ///
+    /// The growth of the size class before the allocation, to pass to `after_alloc`.
+    fn before_alloc<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        index: u8,
+    ) -> Result<(u64, usize), MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        self.check_owner::<B, A>(owner);
+        self.check_churn()?;
+        self.grow(index);
+        if let Some(threshold) = self.pressure_threshold {
+            let node = &self.nodes[index as usize];
+            if !node.has_free() && self.allocated() + node.chunk_size > threshold {
+                // Release unused chunks before growing further
+                self.shrink(owner, device);
+            }
+        }
+        let growth = self.node_growth(index);
+        self.sync_clock(index as usize);
+        Ok(growth)
+    }
+
+    /// Finish an allocation from the size class that had grown as `growth` tells before it.
+    fn after_alloc<M>(
+        &mut self,
+        index: u8,
+        growth: (u64, usize),
+        reqs: &Requirements,
+        block: &ChunkedBlock<M>,
+    ) where
+        M: Debug + Any,
+    {
+        if let Some(threshold) = self.low_water {
+            let node = &mut self.nodes[index as usize];
+            if node.free.len() < threshold {
+                node.prefetch = true;
+            }
+        }
+        let (grows, free_blocks) = growth;
+        self.report_grow(index, grows, free_blocks, reqs, block);
+        self.update_registry();
+    }
+
+    /// Finish a free: shrink every `auto_shrink` frees and update the registry and the heap
+    /// warning.
+    fn after_free<B, A>(&mut self, owner: &mut A, device: &B::Device)
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        if let Some(every_n_frees) = self.auto_shrink {
+            self.frees_since_shrink += 1;
+            if self.frees_since_shrink >= every_n_frees {
+                self.frees_since_shrink = 0;
+                self.shrink(owner, device);
+            }
+        }
+        self.update_registry();
+        if self.heap_warned {
+            self.check_heap_warning();
+        }
+    }
+
+    /// Call the `on_heap_warning` callback if the allocated chunks reached the warning threshold
+    /// since the last check, or rearm the warning if they dropped below it.
+    fn check_heap_warning(&mut self) {
+        let heap_size = match self.heap_size {
+            Some(heap_size) => heap_size,
+            None => return,
+        };
+        let threshold = (heap_size as f64 * self.warn_fraction as f64) as u64;
+        let allocated = self.allocated();
+        if allocated < threshold {
+            self.heap_warned = false;
+            return;
+        }
+        if self.heap_warned {
+            return;
+        }
+        self.heap_warned = true;
+        let used = self.used();
+        if let Some(Callback(ref mut callback)) = self.on_heap_warning {
+            callback(HeapWarning {
+                allocated,
+                used,
+                heap_size,
+            });
+        }
+    }
+
+    /// Free all chunks that have no used blocks back to the underlying allocator.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator that was used to allocate the chunks
+    /// - `device`: same device that was used to allocate the chunks
+    ///
+    /// ### Returns
+    ///
+    /// The total size of the freed chunks in bytes.
+    pub fn shrink<B, A>(&mut self, owner: &mut A, device: &B::Device) -> u64
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        self.check_owner::<B, A>(owner);
+        let freed = self.nodes
+            .iter_mut()
+            .map(|node| node.shrink(owner, device))
+            .sum();
+        self.update_registry();
+        self.check_heap_warning();
+        freed
+    }
+
+    /// Free all chunks but one of each size class back to the underlying allocator.
+    ///
+    /// This is in between `shrink`, which keeps all chunks with used blocks, and `dispose`,
+    /// which frees everything. It trims the allocator, e.g. on scene transitions, while keeping
+    /// a warm chunk for every size class that has any, so the next allocations of those sizes
+    /// don't have to grow. Chunks set with `set_min_free_chunks` are not kept.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator that was used to allocate the chunks
+    /// - `device`: same device that was used to allocate the chunks
+    ///
+    /// ### Returns
+    ///
+    /// The total size of the freed chunks in bytes.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if any of the blocks allocated by this allocator are still in use.
+    pub fn soft_reset<B, A>(&mut self, owner: &mut A, device: &B::Device) -> u64
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        assert!(!self.is_used(), "Allocator is reset while blocks are in use");
+        self.check_owner::<B, A>(owner);
+        let freed = self.nodes
+            .iter_mut()
+            .map(|node| node.soft_reset(owner, device))
+            .sum();
+        self.update_registry();
+        self.check_heap_warning();
+        freed
+    }
+
+    /// Free unused chunks of the size classes that had blocks freed since they were last shrunk.
+    ///
+    /// Freeing a block never returns memory to the underlying allocator by itself. Calling this
+    /// once per frame instead of `shrink` reclaims the chunks emptied during the frame in one
+    /// batch, without scanning the size classes that had no frees. Like `shrink` it keeps the
+    /// chunks set with `set_min_free_chunks`.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator that was used to allocate the chunks
+    /// - `device`: same device that was used to allocate the chunks
+    ///
+    /// ### Returns
+    ///
+    /// The total size of the freed chunks in bytes.
+    pub fn flush_reclaim<B, A>(&mut self, owner: &mut A, device: &B::Device) -> u64
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        self.check_owner::<B, A>(owner);
+        let freed = self.nodes
+            .iter_mut()
+            .filter(|node| node.reclaim)
+            .map(|node| node.shrink(owner, device))
+            .sum();
+        self.update_registry();
+        self.check_heap_warning();
+        freed
+    }
+
+    /// Set the number of unused chunks of a size class that `shrink` keeps allocated.
+    ///
+    /// This prevents frequently used size classes from being shrunk only to grow again right
+    /// after. `dispose` still frees all chunks.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `block_size`: block size of the size class
+    /// - `count`: number of unused chunks to keep
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `block_size` is not the block size of any size class.
+    pub fn set_min_free_chunks(&mut self, block_size: u64, count: usize) {
+        let index = self.size_class_index(block_size);
+        if self.min_free_chunks.len() <= index {
+            self.min_free_chunks.resize(index + 1, 0);
+        }
+        self.min_free_chunks[index] = count;
+        if let Some(node) = self.nodes.get_mut(index) {
+            node.min_free_chunks = count;
+        }
+    }
+
+    /// Set the number of unused chunks of a size class above which freeing a block of it
+    /// releases unused chunks, bounding the memory held after a spike of allocations.
+    ///
+    /// Freeing such a block shrinks the size class down to `max_free` unused chunks, or to the
+    /// number set with `set_min_free_chunks` if that is higher. The used blocks of each chunk
+    /// are counted as they are allocated and freed, so the free blocks of the size class are
+    /// only scanned by frees that leave more than that many unused chunks.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `block_size`: block size of the size class
+    /// - `max_free`: number of unused chunks to keep, `None` removes the limit
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `block_size` is not the block size of any size class.
+    pub fn set_free_chunk_limit(&mut self, block_size: u64, max_free: Option<usize>) {
+        let index = self.size_class_index(block_size);
+        if self.max_free_chunks.len() <= index {
+            self.max_free_chunks.resize(index + 1, None);
+        }
+        self.max_free_chunks[index] = max_free;
+        if let Some(node) = self.nodes.get_mut(index) {
+            node.max_free_chunks = max_free;
+        }
+    }
+
+    /// Remove the limits set with `set_free_chunk_limit` from all size classes.
+    pub(crate) fn clear_free_chunk_limits(&mut self) {
+        self.max_free_chunks.clear();
+        for node in &mut self.nodes {
+            node.max_free_chunks = None;
+        }
+    }
+
+    /// Set whether a size class allocates from the end of its chunks.
+    ///
+    /// By default the free blocks are taken in the order of the free list. Size classes that
+    /// allocate from the end take the free block with the highest chunk index and offset
+    /// instead. Dedicating e.g. the size classes of large persistent allocations to the end and
+    /// the rest to the front keeps both kinds apart, which reduces fragmentation. With a free
+    /// list other than `FreeList::Ordered` this has to scan all free blocks of the size class.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `block_size`: block size of the size class
+    /// - `from_end`: whether to allocate from the end
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `block_size` is not the block size of any size class.
+    pub fn set_from_end(&mut self, block_size: u64, from_end: bool) {
+        let index = self.size_class_index(block_size);
+        if self.from_end.len() <= index {
+            self.from_end.resize(index + 1, false);
+        }
+        self.from_end[index] = from_end;
+        if let Some(node) = self.nodes.get_mut(index) {
+            node.from_end = from_end;
+        }
+    }
+
+    /// Free a single chunk back to the underlying allocator if none of its blocks are used.
+    ///
+    /// Unlike `shrink` this doesn't scan other chunks. Indices of the remaining chunks are left
+    /// unchanged.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator that was used to allocate the chunk
+    /// - `device`: same device that was used to allocate the chunk
+    /// - `block_size`: block size of the size class the chunk belongs to
+    /// - `chunk_index`: index of the chunk, as returned by `ChunkedBlock::chunk_index`
+    ///
+    /// ### Returns
+    ///
+    /// Returns `MemoryError::ChunkInUse` if some blocks of the chunk are still in use.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `block_size` is not the block size of any size class or there is no chunk with
+    /// such index in the size class.
+    pub fn free_chunk<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        block_size: u64,
+        chunk_index: usize,
+    ) -> Result<(), MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        self.check_owner::<B, A>(owner);
+        let index = self.size_class_index(block_size);
+        self.nodes[index].free_chunk(owner, device, chunk_index)?;
+        self.update_registry();
+        self.check_heap_warning();
+        Ok(())
+    }
+
+    /// Check if any size class requested prefetching since the last `process_prefetch`.
+    pub fn needs_prefetch(&self) -> bool {
+        self.nodes.iter().any(|node| node.prefetch)
+    }
+
+    /// Allocate a chunk for each size class that requested prefetching.
+    /// See `with_low_water_prefetch`.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator used to allocate the chunks
+    /// - `device`: device to allocate the memory from
+    /// - `request`: information required by `owner` to allocate each chunk
+    pub fn process_prefetch<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        request: A::Request,
+    ) -> Result<(), MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+        A::Request: Clone,
+    {
+        self.check_owner::<B, A>(owner);
+        for node in self.nodes.iter_mut().filter(|node| node.prefetch) {
+            if let Err(error) = node.grow(owner, device, request.clone()) {
+                self.check_heap_warning();
+                return Err(error);
+            }
+            node.prefetch = false;
+        }
+        self.check_heap_warning();
+        Ok(())
+    }
+
+    /// Take all chunks out of the allocator without freeing them to the underlying allocator.
+    ///
+    /// Unlike `dispose` this hands the chunks to the caller, who becomes responsible for freeing
+    /// them. The allocator is left empty and can be used again.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if any of the blocks allocated by this allocator are still in use.
+    pub fn drain_chunks(&mut self) -> ::std::vec::IntoIter<T> {
+        assert!(!self.is_used(), "Chunks are drained while blocks are in use");
+        let mut chunks = Vec::new();
+        for node in &mut self.nodes {
+            node.free.retain(|_| false);
+            node.blocks = 0;
+            node.used_blocks.clear();
+            chunks.extend(node.chunks.drain(..).flatten());
+        }
+        self.update_registry();
+        self.check_heap_warning();
+        chunks.into_iter()
+    }
+
+    /// Assert that the memory of the block wasn't freed since the block was allocated.
+    ///
+    /// Every time a block is freed the generation of the memory it covers is incremented, so
+    /// stale code keeping a block around after its memory was freed and reallocated is caught.
+    ///
+    /// Only available with the `checks` feature.
+    #[cfg(feature = "checks")]
+    pub fn assert_block_current<M>(&self, block: &ChunkedBlock<M>)
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        let index = self.node_of(block).expect("Block wasn't allocated by this allocator");
+        let node = &self.nodes[index];
+        assert_eq!(
+            node.generation(&node.first_block(block)),
+            block.1.generation,
+            "Block is used after free: {:?}",
+            block
+        );
+    }
+
+    /// Save the complete state of the allocator, except for the memory of its chunks.
+    ///
+    /// Along with `restore_state` this lets tests start from a precise, e.g. fragmented, state
+    /// without replaying the allocations that led to it. Scopes are not part of the state.
+    #[cfg(feature = "testing")]
+    pub fn save_state(&self) -> ChunkedState
+    where
+        T: Block,
+    {
+        ChunkedState {
+            nodes: self.nodes.iter().map(ChunkedNode::save_state).collect(),
+            deferred: self.deferred
+                .iter()
+                .flat_map(|(&frame, deferred)| {
+                    deferred.iter().map(move |deferred| (frame, deferred.clone()))
+                })
+                .collect(),
+        }
+    }
+
+    /// Put an allocator without chunks into the state saved by `save_state`.
+    ///
+    /// Chunks of the saved sizes are allocated from `owner` in place of the saved ones. Blocks
+    /// that were used are returned one block each, ordered by chunk index and offset. Blocks
+    /// freed with `free_deferred` are not returned, they are restored as pending instead.
+    /// If allocating a chunk fails, all chunks allocated so far are freed again.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator to allocate the chunks from
+    /// - `device`: device to allocate the memory from
+    /// - `request`: information required by `owner` to allocate each chunk
+    /// - `state`: state saved from an allocator with the same configuration
+    ///
+    /// ### Panics
+    ///
+    /// Panics if the allocator has chunks, or if `state` was saved from an allocator with a
+    /// different configuration.
+    #[cfg(feature = "testing")]
+    pub fn restore_state<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        request: A::Request,
+        state: &ChunkedState,
+    ) -> Result<Vec<ChunkedBlock<B::Memory>>, MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+        A::Request: Clone,
+    {
+        self.check_owner::<B, A>(owner);
+        assert!(
+            self.nodes.iter().all(|node| node.chunk_count() == 0) && self.deferred.is_empty(),
+            "Allocator already has chunks"
+        );
+
+        let mut chunks = Vec::with_capacity(state.nodes.len());
+        let mut error = None;
+        'nodes: for node in &state.nodes {
+            let mut node_chunks = Vec::with_capacity(node.chunks.len());
+            for &size in &node.chunks {
+                let size = match size {
+                    Some(size) => size,
+                    None => {
+                        node_chunks.push(None);
+                        continue;
+                    }
+                };
+                let reqs = Requirements {
+                    type_mask: 1 << self.id.0,
+                    size,
+                    alignment: node.block_size & node.block_size.wrapping_neg(),
+                };
+                match owner.alloc(device, request.clone(), reqs) {
+                    Ok(chunk) => node_chunks.push(Some(chunk)),
+                    Err(err) => {
+                        error = Some(err);
+                        chunks.push(node_chunks);
+                        break 'nodes;
+                    }
+                }
+            }
+            chunks.push(node_chunks);
+        }
+        if let Some(error) = error {
+            for chunk in chunks.into_iter().flatten().flatten() {
+                owner.free(device, chunk);
+            }
+            return Err(error);
+        }
+
+        if let Some(max_index) = state.nodes.len().checked_sub(1) {
+            self.grow(max_index as u8);
+        }
+        let mut blocks = Vec::new();
+        for (index, (node, chunks)) in state.nodes.iter().zip(chunks).enumerate() {
+            let deferred = state
+                .deferred
+                .iter()
+                .filter(|(_, deferred)| deferred.node == index)
+                .map(|(_, deferred)| deferred)
+                .collect::<Vec<_>>();
+            self.sync_clock(index);
+            blocks.extend(self.nodes[index].restore_state(chunks, node, &deferred));
+        }
+        for &(frame, ref deferred) in &state.deferred {
+            self.deferred
+                .entry(frame)
+                .or_default()
+                .push(deferred.clone());
+        }
+        Ok(blocks)
+    }
+
+    /// List the blocks that are still allocated along with the call stack they were allocated
+    /// from, e.g. to find leaks at shutdown.
+    ///
+    /// Blocks handed out by `defragment_budgeted` and `compact_to_fit` report the call stack of
+    /// the relocation. Only available with the `debug-backtrace` feature.
+    #[cfg(feature = "debug-backtrace")]
+    pub fn leaked_allocations(&self) -> Vec<(Range<u64>, &Backtrace)>
+    where
+        T: Block,
+    {
+        self.nodes
+            .iter()
+            .flat_map(|node| {
+                node.backtraces.iter().map(move |(first, &(span, ref backtrace))| {
+                    let start = node.chunk(first.chunk_index).range().start
+                        + first.block_index * node.block_size;
+                    (start..start + span as u64 * node.block_size, backtrace)
+                })
+            })
+            .collect()
+    }
+
+    /// Reclaim as much memory as possible in response to a memory pressure signal from the
+    /// OS or driver (e.g. `onTrimMemory` on Android).
+    ///
+    /// This frees every chunk that has no used blocks. Unlike `shrink` it also frees the chunks
+    /// kept by `set_min_free_chunks`.
+    ///
+    /// ### Returns
+    ///
+    /// The total size of the freed chunks in bytes.
+    pub fn on_memory_pressure<B, A>(&mut self, owner: &mut A, device: &B::Device) -> u64
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        self.check_owner::<B, A>(owner);
+        let freed = self.nodes
+            .iter_mut()
+            .map(|node| {
+                node.reclaim = false;
+                node.shrink_keeping(owner, device, 0)
+            })
+            .sum();
+        self.update_registry();
+        self.check_heap_warning();
+        freed
+    }
+
+    fn block_size(&self, index: u8) -> u64 {
+        self.ladder[index as usize].block_size
+    }
+
+    /// Get index of the largest size class.
+    fn max_index(&self) -> u8 {
+        (self.ladder.len() - 1) as u8
+    }
+
+    fn chunk_size(&self, index: u8) -> u64 {
+        self.ladder[index as usize].chunk_size
+    }
+
+    /// Recompute the size classes after the chunk size rounding changed.
+    fn update_ladder(&mut self) {
+        self.ladder = size_ladder(
+            &self.selector,
+            self.owner_min_alignment,
+            self.chunk_granularity,
+        ).unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    /// Get the index of the smallest size class with blocks of at least `size` bytes, or
+    /// `MemoryError::TooLarge` if no block is that large.
+    fn pick_node(&self, size: u64) -> Result<u8, MemoryError> {
+        assert_ne!(size, 0);
+        if size > self.max_chunk_size() {
+            return Err(MemoryError::TooLarge);
+        }
+        let node = self.selector.node_for_size(size);
+        assert!(node <= self.max_index(), "Size class {} is out of range", node);
+        debug_assert!(size <= self.block_size(node));
+        debug_assert!(node == 0 || size > self.block_size(node - 1));
+        Ok(node)
+    }
+
+    /// Get the index of the size class with exactly the given block size.
+    fn node_with_block_size(&self, block_size: u64) -> Option<u8> {
+        if block_size == 0 {
+            return None;
+        }
+        let index = self.pick_node(block_size).ok()?;
+        if self.block_size(index) == block_size {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    /// Get the index of the size class with the given block size.
+    fn size_class_index(&self, block_size: u64) -> usize {
+        assert!(
+            block_size >= self.min_block_size() && block_size <= self.max_chunk_size(),
+            "Block size {} is out of range",
+            block_size
+        );
+        match self.node_with_block_size(block_size) {
+            Some(index) => index as usize,
+            None => panic!("Block size {} is not a size class", block_size),
+        }
+    }
+
+    /// Pick node to allocate a block meeting the requirements from.
+    fn pick_node_for(&self, reqs: Requirements) -> Result<u8, MemoryError> {
+        // Checked before selecting, no size class can hold larger blocks
+        if max(reqs.size, reqs.alignment) > self.max_chunk_size() {
+            return Err(MemoryError::TooLarge);
+        }
+        let spec = if reqs.alignment > reqs.size {
+            self.selector.select_aligned(reqs.size, reqs.alignment)
+        } else {
+            self.selector.select(&reqs)
+        };
+        let index = match spec {
+            Some(spec) => spec.index,
+            None => return Err(MemoryError::TooLarge),
+        };
+        assert!(index <= self.max_index(), "Size class {} is out of range", index);
+        debug_assert!(max(reqs.size, reqs.alignment) <= self.block_size(index));
+
+        // Blocks of exact `max_chunk_size` may be aligned less than their size
+        let block_size = self.block_size(index);
+        if reqs.alignment > block_size & block_size.wrapping_neg() {
+            return Err(MemoryError::TooLarge);
+        }
+        Ok(index)
+    }
+
+    /// Find the node the block was allocated from.
+    fn node_of<M: Debug + Any>(&self, block: &ChunkedBlock<M>) -> Result<usize, MemoryError> {
+        let span = block.1.span as u64;
+        let size = block.whole_size();
+        if span == 0 || size % span != 0 {
+            return Err(MemoryError::InvalidBlock);
+        }
+
+        // Block size must be exactly the block size of an existing node
+        let index = match self.node_with_block_size(size / span) {
+            Some(index) => index as usize,
+            None => return Err(MemoryError::InvalidBlock),
+        };
+        if index >= self.nodes.len() {
+            return Err(MemoryError::InvalidBlock);
+        }
+
+        // Size of the block must lead back to the node it was allocated from
+        #[cfg(feature = "checks")]
+        {
+            if index != block.1.node as usize {
+                return Err(MemoryError::InvalidBlock);
+            }
+        }
+        Ok(index)
+    }
+
+    #[cfg(feature = "registry")]
+    fn update_registry(&self) {
+        self.registration.set_usage(self.allocated(), self.used());
+    }
+
+    #[cfg(not(feature = "registry"))]
+    #[inline(always)]
+    fn update_registry(&self) {}
+
+    /// Remember the owner on first use and check the same owner is used afterwards.
+    fn check_owner<B, A>(&mut self, owner: &A)
+    where
+        B: Backend,
+        A: MemoryAllocator<B>,
+    {
+        if let Some(id) = owner.instance_id() {
+            let expected = *self.owner.get_or_insert(id);
+            assert_eq!(expected, id, "Allocator is used with a different owner");
+        }
+    }
+
+    fn grow(&mut self, index: u8) {
+        assert!(index <= self.max_index());
+        // Only rounding to the granularity makes chunks larger than `max_chunk_size`
+        let max_chunk_size = self.max_chunk_size().saturating_add(self.chunk_granularity - 1);
+        assert!(self.chunk_size(index) <= max_chunk_size);
+        let len = self.nodes.len() as u8;
+        let id = self.id;
+
+        let range = len..index + 1;
+        self.nodes.reserve(range.len());
+        for index in range {
+            let mut node = ChunkedNode::new(
+                id,
+                self.chunk_size(index),
+                self.block_size(index),
+                self.free_list,
+                self.fill_chunks,
+            );
+            node.min_free_chunks = self.min_free_chunks
+                .get(index as usize)
+                .cloned()
+                .unwrap_or(0);
+            node.max_free_chunks = self.max_free_chunks
+                .get(index as usize)
+                .cloned()
+                .unwrap_or(None);
+            node.from_end = self.from_end
+                .get(index as usize)
+                .cloned()
+                .unwrap_or(false);
+            node.colors = self.colors;
+            node.frame = self.frame;
+            node.stamps.enable(self.lru, self.frame_stamps);
+            #[cfg(feature = "checks")]
+            {
+                node.index = index;
+            }
+            self.nodes.push(node);
+        }
+    }
+}
+
+impl<B, O, T, S> MemorySubAllocator<B, O> for ChunkedAllocator<T, S>
+where
+    B: Backend,
+    T: Block<Memory = B::Memory>,
+    O: MemoryAllocator<B, Block = T>,
+    S: NodeSelector,
+{
+    type Request = O::Request;
+    type Block = ChunkedBlock<B::Memory>;
+
+    fn alloc(
+        &mut self,
+        owner: &mut O,
+        device: &B::Device,
+        request: O::Request,
+        reqs: Requirements,
+    ) -> Result<ChunkedBlock<B::Memory>, MemoryError> {
+        let index = self.pick_node_for(reqs)?;
+        let growth = self.before_alloc::<B, O>(owner, device, index)?;
+        let block = self.nodes[index as usize].alloc(owner, device, request, reqs)?;
+        self.after_alloc(index, growth, &reqs, &block);
+        Ok(block)
+    }
+
+    fn free(&mut self, owner: &mut O, device: &B::Device, block: ChunkedBlock<B::Memory>) {
+        self.check_owner::<B, O>(owner);
+        let index = match self.node_of(&block) {
+            Ok(index) => index,
+            Err(error) => panic!("{}: {:?}", error, block),
+        };
+        self.release_scope(&block);
+        self.nodes[index].free(owner, device, block);
+        self.after_free::<B, O>(owner, device);
+    }
+
+    fn dispose(mut self, owner: &mut O, device: &B::Device) -> Result<(), Self> {
+        self.check_owner::<B, O>(owner);
+        if self.is_used() {
+            Err(self)
+        } else {
+            for node in self.nodes.drain(..) {
+                node.dispose(owner, device).unwrap();
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Builder of a `ChunkedAllocator` that validates the configuration.
+///
+/// `blocks_per_chunk`, `min_block_size` and `max_chunk_size` must be set, see
+/// `ChunkedAllocator::new`. The other options are the same as the `with_*` methods of
+/// `ChunkedAllocator`.
+#[derive(Clone, Debug)]
+pub struct ChunkedAllocatorBuilder {
+    id: MemoryTypeId,
+    blocks_per_chunk: Option<usize>,
+    min_block_size: Option<u64>,
+    max_chunk_size: Option<u64>,
+    exact_max_chunk_size: bool,
+    owner_min_alignment: u64,
+    chunk_granularity: u64,
+    fill_chunks: bool,
+    free_list: FreeList,
+    pressure_threshold: Option<u64>,
+    low_water: Option<usize>,
+    reserved: ChunkedLayout,
+    colors: u64,
+    churn_limit: Option<usize>,
+    strict_churn: bool,
+    lru: bool,
+    frame_stamps: bool,
+    auto_shrink: Option<usize>,
+    history_len: usize,
+}
+
+impl ChunkedAllocatorBuilder {
+    /// Start building an allocator of the specified memory type.
+    pub fn new(id: MemoryTypeId) -> Self {
+        ChunkedAllocatorBuilder {
+            id,
+            blocks_per_chunk: None,
+            min_block_size: None,
+            max_chunk_size: None,
+            exact_max_chunk_size: false,
+            owner_min_alignment: 1,
+            chunk_granularity: 1,
+            fill_chunks: false,
+            free_list: FreeList::default(),
+            pressure_threshold: None,
+            low_water: None,
+            reserved: ChunkedLayout::default(),
+            colors: 1,
+            churn_limit: None,
+            strict_churn: false,
+            lru: false,
+            frame_stamps: false,
+            auto_shrink: None,
+            history_len: 256,
+        }
+    }
+
+    /// Set the number of blocks in each chunk. Must not be zero.
+    pub fn with_blocks_per_chunk(mut self, blocks_per_chunk: usize) -> Self {
+        self.blocks_per_chunk = Some(blocks_per_chunk);
+        self
+    }
+
+    /// Set the minimum block size in bytes. Must be a power of two.
+    pub fn with_min_block_size(mut self, min_block_size: u64) -> Self {
+        self.min_block_size = Some(min_block_size);
+        self
+    }
+
+    /// Set the maximum chunk size in bytes. Must be a power of two.
+    pub fn with_max_chunk_size(mut self, max_chunk_size: u64) -> Self {
+        self.max_chunk_size = Some(max_chunk_size);
+        self
+    }
+
+    /// Allow `max_chunk_size` that is not a power of two.
+    ///
+    /// Blocks of the largest size class are then exactly `max_chunk_size` bytes instead of being
+    /// rounded up to a power of two, e.g. 3MB instead of 4MB. Such blocks are only aligned to the
+    /// largest power of two dividing `max_chunk_size`. Smaller size classes are not affected.
+    pub fn with_exact_max_chunk_size(mut self, exact: bool) -> Self {
+        self.exact_max_chunk_size = exact;
+        self
+    }
+
+    /// See `ChunkedAllocator::with_owner_min_alignment`. Must be a power of two.
+    pub fn with_owner_min_alignment(mut self, alignment: u64) -> Self {
+        self.owner_min_alignment = alignment;
+        self
+    }
+
+    /// See `ChunkedAllocator::with_chunk_granularity`. Must not be zero.
+    pub fn with_chunk_granularity(mut self, granularity: u64) -> Self {
+        self.chunk_granularity = granularity;
+        self
+    }
+
+    /// See `ChunkedAllocator::with_fill_chunks`.
+    pub fn with_fill_chunks(mut self, fill_chunks: bool) -> Self {
+        self.fill_chunks = fill_chunks;
+        self
+    }
+
+    /// See `ChunkedAllocator::with_free_list`.
+    pub fn with_free_list(mut self, free_list: FreeList) -> Self {
+        self.free_list = free_list;
+        self
+    }
+
+    /// See `ChunkedAllocator::set_pressure_threshold`.
+    pub fn with_pressure_threshold(mut self, threshold: Option<u64>) -> Self {
+        self.pressure_threshold = threshold;
+        self
+    }
+
+    /// See `ChunkedAllocator::with_low_water_prefetch`.
+    pub fn with_low_water_prefetch(mut self, threshold: usize) -> Self {
+        self.low_water = Some(threshold);
+        self
+    }
+
+    /// See `ChunkedAllocator::with_coloring`. Must not be zero.
+    pub fn with_coloring(mut self, num_colors: u64) -> Self {
+        self.colors = num_colors;
+        self
+    }
+
+    /// See `ChunkedAllocator::with_churn_limit`.
+    pub fn with_churn_limit(mut self, limit: usize, strict: bool) -> Self {
+        self.churn_limit = Some(limit);
+        self.strict_churn = strict;
+        self
+    }
+
+    /// See `ChunkedAllocator::with_lru_tracking`.
+    pub fn with_lru_tracking(mut self, enabled: bool) -> Self {
+        self.lru = enabled;
+        self
+    }
+
+    /// See `ChunkedAllocator::with_frame_stamps`.
+    pub fn with_frame_stamps(mut self, enabled: bool) -> Self {
+        self.frame_stamps = enabled;
+        self
+    }
+
+    /// See `ChunkedAllocator::with_auto_shrink`. Must not be zero.
+    pub fn with_auto_shrink(mut self, every_n_frees: usize) -> Self {
+        self.auto_shrink = Some(every_n_frees);
+        self
+    }
+
+    /// See `ChunkedAllocator::with_history_len`.
+    pub fn with_history_len(mut self, len: usize) -> Self {
+        self.history_len = len;
+        self
+    }
+
+    /// See `ChunkedAllocator::with_reserved_layout`.
+    pub fn with_reserved_layout(mut self, layout: ChunkedLayout) -> Self {
+        self.reserved = layout;
+        self
+    }
+
+    /// Validate the configuration and create the allocator.
+    pub fn build<T>(self) -> Result<ChunkedAllocator<T>, ConfigError> {
+        let blocks_per_chunk = self.blocks_per_chunk
+            .ok_or(ConfigError::Missing("blocks_per_chunk"))?;
+        let min_block_size = self.min_block_size
+            .ok_or(ConfigError::Missing("min_block_size"))?;
+        let max_chunk_size = self.max_chunk_size
+            .ok_or(ConfigError::Missing("max_chunk_size"))?;
+        if self.id.0 >= MAX_MEMORY_TYPES {
+            return Err(ConfigError::MemoryTypeOutOfRange(self.id));
+        }
+        if blocks_per_chunk == 0 {
+            return Err(ConfigError::Zero("blocks_per_chunk"));
+        }
+        if !min_block_size.is_power_of_two() {
+            return Err(ConfigError::NotPowerOfTwo("min_block_size"));
+        }
+        if max_chunk_size == 0 || !(self.exact_max_chunk_size || max_chunk_size.is_power_of_two()) {
+            return Err(ConfigError::NotPowerOfTwo("max_chunk_size"));
+        }
+        if !self.owner_min_alignment.is_power_of_two() {
+            return Err(ConfigError::NotPowerOfTwo("owner_min_alignment"));
+        }
+        if min_block_size > max_chunk_size {
+            return Err(ConfigError::MinBlockSizeAboveMaxChunkSize);
+        }
+
+        self.build_with_selector(PowerOfTwoSelector {
+            blocks_per_chunk,
+            min_block_size,
+            max_chunk_size,
+        })
+    }
+
+    /// Validate the configuration and create an allocator with the size classes defined by
+    /// `selector`.
+    ///
+    /// `blocks_per_chunk`, `min_block_size`, `max_chunk_size` and `exact_max_chunk_size` are
+    /// ignored, they only configure the default `PowerOfTwoSelector`.
+    pub fn build_with_selector<T, S>(
+        self,
+        selector: S,
+    ) -> Result<ChunkedAllocator<T, S>, ConfigError>
+    where
+        S: NodeSelector,
+    {
+        if self.id.0 >= MAX_MEMORY_TYPES {
+            return Err(ConfigError::MemoryTypeOutOfRange(self.id));
+        }
+        if selector.node_count() == 0 {
+            return Err(ConfigError::Zero("node_count"));
+        }
+        if !self.owner_min_alignment.is_power_of_two() {
+            return Err(ConfigError::NotPowerOfTwo("owner_min_alignment"));
+        }
+        if self.chunk_granularity == 0 {
+            return Err(ConfigError::Zero("chunk_granularity"));
+        }
+        if self.colors == 0 {
+            return Err(ConfigError::Zero("num_colors"));
+        }
+        if self.auto_shrink == Some(0) {
+            return Err(ConfigError::Zero("every_n_frees"));
+        }
+        let ladder = size_ladder(&selector, self.owner_min_alignment, self.chunk_granularity)?;
+
+        Ok(ChunkedAllocator {
+            id: self.id,
+            selector,
+            ladder,
+            owner_min_alignment: self.owner_min_alignment,
+            chunk_granularity: self.chunk_granularity,
+            fill_chunks: self.fill_chunks,
+            free_list: self.free_list,
+            pressure_threshold: self.pressure_threshold,
+            low_water: self.low_water,
+            min_free_chunks: Vec::new(),
+            max_free_chunks: Vec::new(),
+            from_end: Vec::new(),
+            colors: self.colors,
+            frame_start: ChurnStats::default(),
+            churn_limit: self.churn_limit,
+            strict_churn: self.strict_churn,
+            on_churn_limit: None,
+            lru: self.lru,
+            frame_stamps: self.frame_stamps,
+            frame: 0,
+            auto_shrink: self.auto_shrink,
+            frees_since_shrink: 0,
+            on_grow: None,
+            heap_size: None,
+            warn_fraction: 0.9,
+            on_heap_warning: None,
+            heap_warned: false,
+            reserved: self.reserved.chunks,
+            owner: None,
+            instance_id: next_instance_id(),
+            scopes: Vec::new(),
+            deferred: BTreeMap::new(),
+            history: Vec::new(),
+            history_len: self.history_len,
+            #[cfg(feature = "registry")]
+            registration: Registration::new(self.id),
+            nodes: Vec::new(),
+        })
+    }
+}
+
+/// Get the size classes defined by `selector`, with chunk sizes rounded up to at least
+/// `owner_min_alignment` and to a multiple of `granularity`.
+fn size_ladder<S>(
+    selector: &S,
+    owner_min_alignment: u64,
+    granularity: u64,
+) -> Result<Vec<NodeSpec>, ConfigError>
+where
+    S: NodeSelector,
+{
+    // Chunks rounded up to the alignment would be larger than `max_chunk_size`
+    let max_chunk_size = selector.node(selector.node_count() - 1).block_size;
+    if owner_min_alignment > max_chunk_size {
+        return Err(ConfigError::OwnerMinAlignmentAboveMaxChunkSize);
+    }
+    (0..selector.node_count())
+        .map(|index| {
+            let spec = selector.node(index);
+            let size = max(spec.chunk_size, owner_min_alignment);
+            match size.checked_add(granularity - 1) {
+                Some(size) => Ok(NodeSpec {
+                    chunk_size: size / granularity * granularity,
+                    ..spec
+                }),
+                None => Err(ConfigError::ChunkSizeOverflow(index)),
+            }
+        })
+        .collect()
+}
+
+/// Get the smallest `min_block_size` that keeps blocks aligned for all of the buffer usages.
+///
+/// This is the largest offset alignment the device requires for any of the usages, rounded up to
+/// a power of two. Usages without offset alignment requirements need no more than 1 byte.
+///
+/// ### Parameters:
+///
+/// - `limits`: limits of the device
+/// - `usages`: usages of the buffers to bind to the blocks
+pub fn recommended_min_block_size(limits: &Limits, usages: &[Usage]) -> u64 {
+    let alignments = [
+        (
+            Usage::TRANSFER_SRC | Usage::TRANSFER_DST,
+            limits.min_buffer_copy_offset_alignment,
+        ),
+        (
+            Usage::UNIFORM_TEXEL | Usage::STORAGE_TEXEL,
+            limits.min_texel_buffer_offset_alignment,
+        ),
+        (Usage::UNIFORM, limits.min_uniform_buffer_offset_alignment),
+        (Usage::STORAGE, limits.min_storage_buffer_offset_alignment),
+    ];
+    usages
+        .iter()
+        .flat_map(|usage| {
+            alignments
+                .iter()
+                .filter(move |&&(flags, _)| usage.intersects(flags))
+                .map(|&(_, alignment)| alignment)
+        })
+        .fold(1, max)
+        .next_power_of_two()
+}
+
+/// Invalid configuration of a `ChunkedAllocatorBuilder`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// Required parameter isn't set.
+    Missing(&'static str),
+
+    /// Parameter must not be zero.
+    Zero(&'static str),
+
+    /// Parameter must be a power of two.
+    NotPowerOfTwo(&'static str),
+
+    /// `min_block_size` is greater than `max_chunk_size`.
+    MinBlockSizeAboveMaxChunkSize,
+
+    /// Memory type is not less than `MAX_MEMORY_TYPES`.
+    MemoryTypeOutOfRange(MemoryTypeId),
+
+    /// Chunk size of the size class with the index doesn't fit into `u64` once rounded up.
+    ChunkSizeOverflow(u8),
+
+    /// `owner_min_alignment` is greater than `max_chunk_size`.
+    OwnerMinAlignmentAboveMaxChunkSize,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Missing(name) => write!(fmt, "`{}` is not set", name),
+            ConfigError::Zero(name) => write!(fmt, "`{}` must not be zero", name),
+            ConfigError::NotPowerOfTwo(name) => write!(fmt, "`{}` must be a power of two", name),
+            ConfigError::MinBlockSizeAboveMaxChunkSize => {
+                fmt.write_str("`min_block_size` is greater than `max_chunk_size`")
+            }
+            ConfigError::MemoryTypeOutOfRange(id) => {
+                write!(fmt, "Memory type {:?} is out of range", id)
+            }
+            ConfigError::ChunkSizeOverflow(index) => {
+                write!(fmt, "Chunk size of size class {} overflows", index)
+            }
+            ConfigError::OwnerMinAlignmentAboveMaxChunkSize => {
+                fmt.write_str("`owner_min_alignment` is greater than `max_chunk_size`")
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Alignment a `ChunkedAllocator` can't serve, as found by
+/// `ChunkedAllocator::validate_for_alignments`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlignmentError {
+    /// Alignment is not a power of two.
+    NotPowerOfTwo(u64),
+
+    /// No size class has blocks with the alignment.
+    Unsatisfiable(u64),
+}
+
+impl fmt::Display for AlignmentError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AlignmentError::NotPowerOfTwo(alignment) => {
+                write!(fmt, "Alignment {} is not a power of two", alignment)
+            }
+            AlignmentError::Unsatisfiable(alignment) => {
+                write!(fmt, "No size class has blocks aligned to {}", alignment)
+            }
+        }
+    }
+}
+
+impl Error for AlignmentError {}
+
+/// Inconsistency of the internal state found by `ChunkedAllocator::check_invariants`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InvariantViolation {
+    /// Free list holds a block that is not part of an allocated chunk.
+    InvalidFreeBlock {
+        /// Index of the size class
+        node: usize,
+        /// Index of the chunk of the block
+        chunk_index: usize,
+        /// Index of the block within the chunk
+        block_index: u64,
+    },
+
+    /// Free list holds a block more than once.
+    DuplicateFreeBlock {
+        /// Index of the size class
+        node: usize,
+        /// Index of the chunk of the block
+        chunk_index: usize,
+        /// Index of the block within the chunk
+        block_index: u64,
+    },
+
+    /// Size class has more free blocks than blocks.
+    TooManyFreeBlocks {
+        /// Index of the size class
+        node: usize,
+    },
+
+    /// Size class has smaller chunks than the one before it.
+    NodesOutOfOrder {
+        /// Index of the size class
+        node: usize,
+    },
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InvariantViolation::InvalidFreeBlock {
+                node,
+                chunk_index,
+                block_index,
+            } => write!(
+                fmt,
+                "Free block {} of chunk {} of node {} is invalid",
+                block_index, chunk_index, node
+            ),
+            InvariantViolation::DuplicateFreeBlock {
+                node,
+                chunk_index,
+                block_index,
+            } => write!(
+                fmt,
+                "Free block {} of chunk {} of node {} is listed twice",
+                block_index, chunk_index, node
+            ),
+            InvariantViolation::TooManyFreeBlocks { node } => {
+                write!(fmt, "Node {} has more free blocks than blocks", node)
+            }
+            InvariantViolation::NodesOutOfOrder { node } => {
+                write!(fmt, "Node {} has smaller chunks than the node before it", node)
+            }
+        }
+    }
+}
+
+impl Error for InvariantViolation {}
+
+/// Number of chunks of each size class of a `ChunkedAllocator`.
+///
+/// Taken with `ChunkedAllocator::layout` and restored on another allocator with
+/// `ChunkedAllocator::with_reserved_layout`, so it re-grows the same shape up front.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChunkedLayout {
+    chunks: Vec<usize>,
+}
+
+impl ChunkedLayout {
+    /// Get the number of chunks of each size class, smallest block size first.
+    pub fn chunks(&self) -> &[usize] {
+        &self.chunks
+    }
+}
+
+/// Complete state of a `ChunkedAllocator` saved by `ChunkedAllocator::save_state`.
+#[cfg(feature = "testing")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkedState {
+    nodes: Vec<NodeState>,
+    /// Blocks freed with `free_deferred` and the frame they were freed in
+    deferred: Vec<(u64, DeferredFree)>,
+}
+
+#[cfg(feature = "testing")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct NodeState {
+    block_size: u64,
+    /// Size of each chunk, `None` for slots of freed chunks
+    chunks: Vec<Option<u64>>,
+    /// Free blocks in the order of the free list
+    free: Vec<FreeBlock>,
+    grows: u64,
+    peak_chunks: usize,
+    allocations: u64,
+    prefetch: bool,
+    reclaim: bool,
+    #[cfg(feature = "checks")]
+    generations: BTreeMap<FreeBlock, u64>,
+}
+
+/// Snapshot of the state of a `ChunkedAllocator` taken by `ChunkedAllocator::view`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkedView {
+    /// Memory type of the allocator
+    pub memory_type: MemoryTypeId,
+    /// State of each size class, smallest block size first
+    pub size_classes: Vec<SizeClassView>,
+}
+
+/// Scope of a `ChunkedAllocator` returned by `ChunkedAllocator::scope`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScopeHandle {
+    /// Instance ID of the allocator of the scope
+    allocator: usize,
+    index: usize,
+    /// Generation of the scope slot, which is increased when the scope is closed
+    generation: usize,
+}
+
+/// Named scope of a `ChunkedAllocator`.
+#[derive(Debug, Default)]
+struct Scope {
+    /// Name of the scope, `None` if the scope is closed
+    name: Option<String>,
+    stats: ScopeStats,
+    generation: usize,
+}
+
+/// Usage of a scope of a `ChunkedAllocator`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScopeStats {
+    /// Total size of blocks accounted to the scope
+    pub bytes: u64,
+    /// Number of blocks accounted to the scope
+    pub blocks: usize,
+}
+
+/// State of a single size class of a `ChunkedAllocator`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SizeClassView {
+    /// Size of the blocks in bytes
+    pub block_size: u64,
+    /// Size of the chunks in bytes
+    pub chunk_size: u64,
+    /// Number of allocated chunks
+    pub chunks: usize,
+    /// Number of blocks in allocated chunks
+    pub blocks: usize,
+    /// Number of free blocks
+    pub free_blocks: usize,
+}
+
+/// Memory usage of a `ChunkedAllocator` recorded by `ChunkedAllocator::record_sample`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkedSample {
+    /// Total size of allocated chunks in bytes
+    pub allocated: u64,
+    /// Total size of used blocks in bytes
+    pub used: u64,
+}
+
+impl ChunkedSample {
+    /// Get the fraction of allocated memory that is not used, from 0 to 1.
+    pub fn fragmentation(&self) -> f32 {
+        if self.allocated == 0 {
+            0.0
+        } else {
+            1.0 - self.used as f32 / self.allocated as f32
+        }
+    }
+}
+
+/// Lifetime usage of a size class of a `ChunkedAllocator`, as reported by
+/// `ChunkedAllocator::class_utilization`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClassUtil {
+    /// Size of the blocks in bytes
+    pub block_size: u64,
+    /// Size of the chunks in bytes
+    pub chunk_size: u64,
+    /// Highest number of chunks allocated at once
+    pub peak_chunks: usize,
+    /// Number of blocks handed out since the allocator was created
+    pub allocations: u64,
+}
+
+/// Blocks of a single size sub-allocated from a single block provided by the caller, see
+/// `ChunkedAllocator::single_block_view`.
+///
+/// ### Type parameters:
+///
+/// - `T`: type of the block this view sub-allocates from.
+#[derive(Debug)]
+pub struct SingleBlockChunked<T> {
+    node: ChunkedNode<T>,
+}
+
+impl<T> SingleBlockChunked<T>
+where
+    T: Block,
+{
+    fn new(block: T, block_size: u64) -> Self {
+        assert_ne!(block_size, 0);
+        assert!(
+            block_size <= block.size(),
+            "Block size {} is larger than the block",
+            block_size
+        );
+        let mut node = ChunkedNode::new(
+            MemoryTypeId(0),
+            block.size(),
+            block_size,
+            FreeList::default(),
+            true,
+        );
+        node.chunks.push(Some(block));
+        node.blocks = node.chunk_blocks(0);
+        node.free.extend((0..node.blocks as u64).map(|block_index| FreeBlock {
+            chunk_index: 0,
+            block_index,
+        }));
+        SingleBlockChunked { node }
+    }
+
+    /// Get the size of the blocks in bytes.
+    pub fn block_size(&self) -> u64 {
+        self.node.block_size
+    }
+
+    /// Get the block this view sub-allocates from.
+    pub fn block(&self) -> &T {
+        self.node.chunk(0)
+    }
+
+    /// Get the number of free blocks.
+    pub fn available(&self) -> usize {
+        self.node.free.len()
+    }
+
+    /// Check if any of the blocks allocated by this view are still in use.
+    pub fn is_used(&self) -> bool {
+        self.node.is_used()
+    }
+
+    /// Allocate a block meeting the requirements. `reqs.type_mask` is ignored, blocks are always
+    /// of the memory type of the provided block.
+    ///
+    /// ### Returns
+    ///
+    /// Returns `MemoryError::TooLarge` if the size or alignment can't be met by the blocks of
+    /// this view, and `MemoryError::OutOfMemory` if all blocks are in use.
+    pub fn alloc<M>(&mut self, reqs: Requirements) -> Result<ChunkedBlock<M>, MemoryError>
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        let block_size = self.node.block_size;
+        let start = self.node.chunk(0).range().start;
+        if reqs.size > block_size || block_size % reqs.alignment != 0
+            || alignment_shift(reqs.alignment, start) != 0
+        {
+            return Err(MemoryError::TooLarge);
+        }
+        self.node.alloc_no_grow().ok_or(MemoryError::OutOfMemory)
+    }
+
+    /// Free a block allocated by this view.
+    pub fn free<M>(&mut self, block: ChunkedBlock<M>)
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        self.node.free_block(block);
+    }
+
+    /// Take the block back once no blocks are in use.
+    ///
+    /// ### Returns
+    ///
+    /// If the view has blocks that are still in use, this will return `Err(self)`.
+    pub fn into_block(mut self) -> Result<T, Self> {
+        if self.is_used() {
+            Err(self)
+        } else {
+            Ok(self.node.chunks[0].take().expect("Block is taken"))
+        }
+    }
+}
+
+/// Block taken out of a `ChunkedAllocator` by `ChunkedAllocator::detach`, together with the chunk
+/// containing it.
+///
+/// ### Type parameters:
+///
+/// - `T`: type of the chunk containing the block.
+#[derive(Debug)]
+pub struct DetachedBlock<T> {
+    chunk: T,
+    range: Range<u64>,
+}
+
+impl<T> DetachedBlock<T> {
+    /// Get the chunk containing the block.
+    pub fn chunk(&self) -> &T {
+        &self.chunk
+    }
+
+    /// Take the chunk to free it to the underlying allocator once the block is no longer used.
+    pub fn into_chunk(self) -> T {
+        self.chunk
+    }
+}
+
+impl<T> Block for DetachedBlock<T>
+where
+    T: Block,
+{
+    type Memory = T::Memory;
+
+    #[inline]
+    fn memory(&self) -> &T::Memory {
+        self.chunk.memory()
+    }
+
+    #[inline]
+    fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+}
+
+/// Memory usage of a `ChunkedAllocator` when it crossed the warning threshold, as passed to the
+/// callback set with `ChunkedAllocator::on_heap_warning`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HeapWarning {
+    /// Total size of chunks allocated by the allocator in bytes
+    pub allocated: u64,
+    /// Total size of blocks used by the allocator in bytes
+    pub used: u64,
+    /// Heap size set with `ChunkedAllocator::set_heap_size`
+    pub heap_size: u64,
+}
+
+/// Identifies a used block of a `ChunkedAllocator`, e.g. as returned by
+/// `ChunkedAllocator::lru_candidate`.
+///
+/// The fields are the same as the parameters of `ChunkedAllocator::alloc_at_offset`. A handle
+/// stays valid until its block is freed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BlockHandle {
+    /// Block size of the size class the block was allocated from
+    pub block_size: u64,
+    /// Index of the chunk the block was allocated from
+    pub chunk_index: usize,
+    /// Offset of the block from the start of the chunk
+    pub offset: u64,
+}
+
+/// Allocations, frees and chunks allocated by a `ChunkedAllocator` within a frame, as returned
+/// by `ChunkedAllocator::churn_stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChurnStats {
+    /// Number of blocks handed out
+    pub allocations: usize,
+    /// Number of blocks freed
+    pub frees: usize,
+    /// Number of chunks allocated from the underlying allocator
+    pub grows: usize,
+}
+
+/// Allocation that made a size class of a `ChunkedAllocator` allocate a new chunk, as passed to
+/// the callback set with `ChunkedAllocator::on_grow`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GrowEvent {
+    /// Size of the new chunk in bytes
+    pub chunk_size: u64,
+    /// Size requested by the allocation
+    pub size: u64,
+    /// Alignment requested by the allocation
+    pub alignment: u64,
+    /// Number of free blocks of the size class before growing, which were not enough to serve
+    /// the allocation
+    pub free_blocks: usize,
+    /// Size of the allocated block in bytes
+    pub block_size: u64,
+}
+
+/// `Block` type returned by `ChunkedAllocator`.
+#[derive(Debug)]
+pub struct ChunkedBlock<M>(pub(crate) RawBlock<M>, pub(crate) ChunkedTag);
+
+/// Result of `ChunkedAllocator::realloc`: the new block, or the error together with the old
+/// block, which stays valid.
+pub type ReallocResult<M> = Result<ChunkedBlock<M>, (MemoryError, ChunkedBlock<M>)>;
+
+impl<M> ChunkedBlock<M> {
+    /// Get index of the chunk the block was allocated from within its size class.
+    pub fn chunk_index(&self) -> usize {
+        self.1.chunk_index
+    }
+
+    /// Get size of the memory to return to the node, including memory of the block it was carved
+    /// from.
+    fn whole_size(&self) -> u64
+    where
+        M: Debug + Any,
+    {
+        self.1.parent.unwrap_or_else(|| self.size())
+    }
+}
+
+/// Move of a used block planned by `ChunkedAllocator::defragment_budgeted`.
+///
+/// The data must be copied from the relocated block, identified by `memory` and `range`, to
+/// `block`. Afterwards the relocated block must be freed and `block` used in its place.
+#[derive(Debug)]
+pub struct Relocation<M> {
+    memory: *const M,
+    range: Range<u64>,
+    block: ChunkedBlock<M>,
+}
+
+unsafe impl<M> Send for Relocation<M> {}
+
+unsafe impl<M> Sync for Relocation<M> {}
+
+impl<M> Relocation<M> {
+    /// Memory of the relocated block.
+    pub fn memory(&self) -> &M {
+        // Valid until the relocated block is freed
+        unsafe { &*self.memory }
+    }
+
+    /// `Range` of the memory the relocated block occupies.
+    pub fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+
+    /// Block the data should be moved to.
+    pub fn block(&self) -> &ChunkedBlock<M> {
+        &self.block
+    }
+
+    /// Take the block the data should be moved to.
+    pub fn into_block(self) -> ChunkedBlock<M> {
+        self.block
+    }
+}
+
+/// Tag of the `ChunkedBlock` used to return it to the node and chunk it came from.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct ChunkedTag {
+    /// Index of the chunk the block came from
+    chunk_index: usize,
+    /// Number of consecutive blocks the block covers
+    span: usize,
+    /// Size of the block this block was carved from by `alloc_allow_oversize_split`
+    parent: Option<u64>,
+    /// Scope the block is accounted to
+    scope: Option<usize>,
+    /// Generation of the first block covered when the block was allocated
+    #[cfg(feature = "checks")]
+    generation: u64,
+    /// Index of the node the block was allocated from
+    #[cfg(feature = "checks")]
+    node: u8,
+}
+
+impl<M> Block for ChunkedBlock<M>
+where
+    M: Debug + Any,
+{
+    type Memory = M;
+
+    #[inline(always)]
     fn memory(&self) -> &M {
         self.0.memory()
     }
 
-    #[inline(always)]
-    fn range(&self) -> Range<u64> {
-        self.0.range()
+    #[inline(always)]
+    fn range(&self) -> Range<u64> {
+        self.0.range()
+    }
+}
+
+#[test]
+#[allow(dead_code)]
+fn test_send_sync() {
+    fn foo<T: Send + Sync>() {}
+    fn bar<M: Send + Sync>() {
+        foo::<ChunkedAllocator<M>>()
+    }
+}
+
+#[test]
+fn test_single_size_class() {
+    let mut allocator = ChunkedAllocator::<()>::new(MemoryTypeId(0), 8, 256, 256);
+    allocator.set_min_free_chunks(256, 2);
+    assert_eq!(allocator.pick_node(1).unwrap(), 0);
+    assert_eq!(allocator.pick_node(256).unwrap(), 0);
+    assert_eq!(allocator.block_size(0), 256);
+    assert_eq!(allocator.chunk_size(0), 256);
+
+    allocator.grow(0);
+    assert_eq!(allocator.nodes.len(), 1);
+    assert_eq!(allocator.nodes[0].blocks_per_chunk(), 1);
+    assert_eq!(allocator.nodes[0].min_free_chunks, 2);
+
+    // Growing again for the same size class must not create a phantom node.
+    allocator.grow(0);
+    assert_eq!(allocator.nodes.len(), 1);
+
+    assert_eq!(allocator.node_index_for_size(100), 0);
+    assert_eq!(allocator.block_size_of_node(0), 256);
+
+    assert_eq!(
+        allocator.class_utilization(),
+        vec![
+            ClassUtil {
+                block_size: 256,
+                chunk_size: 256,
+                peak_chunks: 0,
+                allocations: 0,
+            },
+        ]
+    );
+
+    let clone = allocator.clone_config();
+    assert_eq!(clone.max_chunk_size(), 256);
+    assert!(clone.nodes.is_empty());
+    assert_eq!(clone.min_free_chunks, vec![2]);
+}
+
+#[test]
+fn test_owner_min_alignment() {
+    let allocator = ChunkedAllocator::<()>::new(MemoryTypeId(0), 4, 256, 1 << 20)
+        .with_owner_min_alignment(4096);
+    assert_eq!(allocator.chunk_size(0), 4096);
+    assert_eq!(allocator.chunk_size(2), 4096);
+    assert_eq!(allocator.chunk_size(3), 8192);
+    assert_eq!(allocator.pick_node(256).unwrap(), 0);
+}
+
+#[test]
+#[should_panic(expected = "`owner_min_alignment` is greater than `max_chunk_size`")]
+fn test_owner_min_alignment_above_max_chunk_size() {
+    ChunkedAllocator::<()>::new(MemoryTypeId(0), 4, 256, 4096).with_owner_min_alignment(8192);
+}
+
+#[test]
+fn test_history() {
+    let mut allocator = ChunkedAllocator::<()>::new(MemoryTypeId(0), 4, 256, 1 << 20)
+        .with_history_len(3);
+    for _ in 0..10 {
+        allocator.record_sample();
+        assert!(allocator.history().len() <= 3);
+    }
+    assert_eq!(allocator.history().len(), 3);
+    assert_eq!(allocator.history()[0].fragmentation(), 0.0);
+
+    let sample = ChunkedSample {
+        allocated: 1024,
+        used: 256,
+    };
+    assert_eq!(sample.fragmentation(), 0.75);
+}
+
+#[test]
+fn test_soft_reset() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 2, 256, 1 << 16);
+    let reqs = |size| Requirements {
+        type_mask: 1,
+        size,
+        alignment: 1,
+    };
+
+    let blocks = (0..12)
+        .map(|i| {
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut allocator,
+                &mut owner,
+                &device,
+                (),
+                reqs(256 << (i % 2)),
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(allocator.allocated(), 3 * 512 + 3 * 1024);
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+
+    let freed = allocator.soft_reset::<empty::Backend, _>(&mut owner, &device);
+    assert_eq!(freed, 2 * 512 + 2 * 1024);
+    assert_eq!(allocator.allocated(), 512 + 1024);
+    assert_eq!(owner.reserved(), 512 + 1024);
+
+    // Kept chunks serve allocations without growing
+    let block = MemorySubAllocator::<empty::Backend, _>::alloc(
+        &mut allocator,
+        &mut owner,
+        &device,
+        (),
+        reqs(256),
+    ).unwrap();
+    assert_eq!(owner.allocations(), 6);
+    MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+fn test_on_grow() {
+    extern crate gfx_backend_empty as empty;
+
+    use std::sync::{Arc, Mutex};
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 2, 256, 1 << 16);
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = events.clone();
+    allocator.on_grow(Box::new(move |event| sink.lock().unwrap().push(event)));
+
+    let blocks = (0..3)
+        .map(|_| {
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut allocator,
+                &mut owner,
+                &device,
+                (),
+                Requirements {
+                    type_mask: 1,
+                    size: 200,
+                    alignment: 16,
+                },
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let event = GrowEvent {
+        chunk_size: 512,
+        size: 200,
+        alignment: 16,
+        free_blocks: 0,
+        block_size: 256,
+    };
+    // The second allocation is served by the first chunk
+    assert_eq!(*events.lock().unwrap(), vec![event, event]);
+
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+fn test_churn() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 4, 256, 1 << 16)
+        .with_churn_limit(3, true);
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 256,
+        alignment: 1,
+    };
+    let alloc = |allocator: &mut ChunkedAllocator<_>, owner: &mut DryRunAllocator<()>| {
+        MemorySubAllocator::<empty::Backend, _>::alloc(allocator, owner, &device, (), reqs)
+    };
+
+    let block = alloc(&mut allocator, &mut owner).unwrap();
+    MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    let block = alloc(&mut allocator, &mut owner).unwrap();
+    assert!(!allocator.churn_exceeded());
+    match alloc(&mut allocator, &mut owner) {
+        Err(MemoryError::ChurnLimit) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+    MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    assert!(allocator.churn_exceeded());
+
+    let churn = allocator.frame_boundary();
+    assert_eq!(
+        churn,
+        ChurnStats {
+            allocations: 2,
+            frees: 2,
+            grows: 1,
+        }
+    );
+    assert_eq!(allocator.churn_stats(), ChurnStats::default());
+    let block = alloc(&mut allocator, &mut owner).unwrap();
+    MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+fn test_backing_allocations() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(2), 4, 256, 1 << 20);
+    let mut blocks = [256, 512, 512, 512, 512, 512]
+        .iter()
+        .map(|&size| {
+            let reqs = Requirements {
+                type_mask: 1 << 2,
+                size,
+                alignment: 1,
+            };
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut allocator,
+                &mut owner,
+                &device,
+                (),
+                reqs,
+            ).unwrap()
+        })
+        .collect::<Vec<_>>();
+    // Free the first chunk of the second size class, leaving an empty slot behind
+    for block in blocks.drain(1..5) {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    allocator.shrink::<empty::Backend, _>(&mut owner, &device);
+    assert_eq!(blocks[1].chunk_index(), 1);
+
+    let backing = allocator.backing_allocations().collect::<Vec<_>>();
+    assert_eq!(backing.len(), 2);
+    assert!(::std::ptr::eq(backing[0].1, blocks[0].memory()));
+    assert!(::std::ptr::eq(backing[1].1, blocks[1].memory()));
+    assert_eq!((backing[0].0, backing[0].2), (MemoryTypeId(2), 1024));
+    assert_eq!((backing[1].0, backing[1].2), (MemoryTypeId(2), 2048));
+
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+fn test_lru_candidate() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 4, 256, 1 << 20)
+        .with_lru_tracking(true);
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 256,
+        alignment: 1,
+    };
+    assert_eq!(allocator.lru_candidate(), None);
+
+    let mut blocks = (0..3)
+        .map(|_| {
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut allocator,
+                &mut owner,
+                &device,
+                (),
+                reqs,
+            ).unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(allocator.lru_candidate(), Some(allocator.handle_of(&blocks[0])));
+    allocator.touch(&blocks[0]);
+    let handle = allocator.handle_of(&blocks[1]);
+    assert_eq!(
+        handle,
+        BlockHandle {
+            block_size: 256,
+            chunk_index: 0,
+            offset: 256,
+        }
+    );
+    assert_eq!(allocator.lru_candidate(), Some(handle));
+    let block = blocks.remove(1);
+    MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    assert_eq!(allocator.lru_candidate(), Some(allocator.handle_of(&blocks[1])));
+
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    assert_eq!(allocator.lru_candidate(), None);
+    allocator.shrink::<empty::Backend, _>(&mut owner, &device);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+fn test_lru_candidate_size_classes() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 4, 256, 1 << 16)
+        .with_lru_tracking(true);
+    let mut blocks = [256, 512]
+        .iter()
+        .map(|&size| {
+            let reqs = Requirements {
+                type_mask: 1,
+                size,
+                alignment: 1,
+            };
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut allocator,
+                &mut owner,
+                &device,
+                (),
+                reqs,
+            ).unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(allocator.lru_candidate(), Some(allocator.handle_of(&blocks[0])));
+    // Accesses in different size classes are ordered by the same clock
+    allocator.touch(&blocks[0]);
+    allocator.touch(&blocks[0]);
+    assert_eq!(allocator.lru_candidate(), Some(allocator.handle_of(&blocks[1])));
+    allocator.touch(&blocks[1]);
+    assert_eq!(allocator.lru_candidate(), Some(allocator.handle_of(&blocks[0])));
+
+    for block in blocks.drain(..) {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+fn test_stamps() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 4, 256, 1 << 20)
+        .with_lru_tracking(true)
+        .with_frame_stamps(true);
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 256,
+        alignment: 1,
+    };
+
+    let mut blocks = Vec::new();
+    for frame in 0..2 {
+        allocator.set_frame(frame);
+        blocks.push(
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut allocator,
+                &mut owner,
+                &device,
+                (),
+                reqs,
+            ).unwrap(),
+        );
+    }
+    let handles = blocks
+        .iter()
+        .map(|block| allocator.handle_of(block))
+        .collect::<Vec<_>>();
+    allocator.touch(&blocks[0]);
+    assert_eq!(allocator.lru_candidate(), Some(handles[1]));
+    assert_eq!(allocator.allocations_older_than(2), handles);
+
+    // Disabling one kind of stamp keeps the other
+    allocator = allocator.with_lru_tracking(false);
+    assert_eq!(allocator.lru_candidate(), None);
+    assert_eq!(allocator.allocations_older_than(2), handles);
+
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    assert_eq!(allocator.allocations_older_than(2), vec![]);
+    allocator.shrink::<empty::Backend, _>(&mut owner, &device);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+fn test_allocations_older_than() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 4, 256, 1 << 20)
+        .with_frame_stamps(true);
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 256,
+        alignment: 1,
+    };
+
+    let mut blocks = Vec::new();
+    for &frame in &[3, 1, 1] {
+        allocator.set_frame(frame);
+        blocks.push(
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut allocator,
+                &mut owner,
+                &device,
+                (),
+                reqs,
+            ).unwrap(),
+        );
+    }
+    allocator.set_frame(4);
+    let handles = blocks
+        .iter()
+        .map(|block| allocator.handle_of(block))
+        .collect::<Vec<_>>();
+    assert_eq!(allocator.allocations_older_than(1), vec![]);
+    assert_eq!(allocator.allocations_older_than(2), vec![handles[1], handles[2]]);
+    assert_eq!(
+        allocator.allocations_older_than(4),
+        vec![handles[1], handles[2], handles[0]]
+    );
+
+    let block = blocks.remove(1);
+    MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    assert_eq!(allocator.allocations_older_than(2), vec![handles[2]]);
+
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    assert_eq!(allocator.allocations_older_than(4), vec![]);
+    allocator.shrink::<empty::Backend, _>(&mut owner, &device);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+fn test_heap_warning() {
+    extern crate gfx_backend_empty as empty;
+
+    use std::sync::{Arc, Mutex};
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 1, 1024, 1 << 16);
+    allocator.set_heap_size(Some(4096));
+    allocator.set_warn_fraction(0.5);
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    let sink = warnings.clone();
+    allocator.on_heap_warning(Box::new(move |warning| sink.lock().unwrap().push(warning)));
+
+    let blocks = (0..4)
+        .map(|_| {
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut allocator,
+                &mut owner,
+                &device,
+                (),
+                Requirements {
+                    type_mask: 1,
+                    size: 1000,
+                    alignment: 1,
+                },
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    // Only the grow crossing half of the heap warns
+    assert_eq!(
+        *warnings.lock().unwrap(),
+        vec![
+            HeapWarning {
+                allocated: 2048,
+                used: 2048,
+                heap_size: 4096,
+            },
+        ]
+    );
+
+    let layout = allocator.layout();
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    allocator.shrink::<empty::Backend, _>(&mut owner, &device);
+    // Dropping below the threshold rearms the warning, also for chunks allocated up front
+    let mut allocator = allocator.with_reserved_layout(layout);
+    allocator
+        .reserve_layout::<empty::Backend, _>(&mut owner, &device, ())
+        .unwrap();
+    assert_eq!(
+        warnings.lock().unwrap()[1..],
+        [
+            HeapWarning {
+                allocated: 4096,
+                used: 0,
+                heap_size: 4096,
+            },
+        ]
+    );
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+fn test_auto_shrink() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator =
+        ChunkedAllocator::new(MemoryTypeId(0), 1, 1024, 1 << 16).with_auto_shrink(3);
+    let chunk_size = allocator.chunk_size_of_node(0);
+
+    let mut blocks = (0..4)
+        .map(|_| {
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut allocator,
+                &mut owner,
+                &device,
+                (),
+                Requirements {
+                    type_mask: 1,
+                    size: 1024,
+                    alignment: 1,
+                },
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let last = blocks.pop().unwrap();
+    let mut allocated = Vec::new();
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+        allocated.push(allocator.allocated() / chunk_size);
+    }
+    // Only the third free shrinks the allocator
+    assert_eq!(allocated, vec![4, 4, 1]);
+    assert_eq!(owner.reserved(), chunk_size);
+
+    MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, last);
+    assert_eq!(allocator.allocated(), chunk_size);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+fn test_free_chunk_limit() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 1, 1024, 1 << 16);
+    allocator.set_free_chunk_limit(1024, Some(2));
+    let chunk_size = allocator.chunk_size_of_node(0);
+
+    let mut blocks = (0..5)
+        .map(|_| {
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut allocator,
+                &mut owner,
+                &device,
+                (),
+                Requirements {
+                    type_mask: 1,
+                    size: 1024,
+                    alignment: 1,
+                },
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(allocator.allocated(), 5 * chunk_size);
+
+    for block in blocks.drain(..) {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    // Unused chunks above the limit are released as blocks are freed
+    assert_eq!(allocator.allocated(), 2 * chunk_size);
+    assert_eq!(owner.reserved(), 2 * chunk_size);
+
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+fn test_detach() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 2, 1024, 1 << 16);
+    let mut alloc = || {
+        MemorySubAllocator::<empty::Backend, _>::alloc(
+            &mut allocator,
+            &mut owner,
+            &device,
+            (),
+            Requirements {
+                type_mask: 1,
+                size: 1024,
+                alignment: 1,
+            },
+        ).unwrap()
+    };
+    let (persistent, transient) = (alloc(), alloc());
+    let range = persistent.range();
+
+    // The other block of the chunk is still used
+    let persistent = allocator.detach(persistent).unwrap_err();
+    MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, transient);
+    let detached = allocator.detach(persistent).unwrap();
+    assert_eq!(detached.range(), range);
+    assert!(detached.chunk().contains(&detached));
+    assert_eq!(allocator.allocated(), 0);
+
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+    assert_eq!(owner.reserved(), 2048);
+    MemoryAllocator::<empty::Backend>::free(&mut owner, &device, detached.into_chunk());
+    assert_eq!(owner.reserved(), 0);
+}
+
+#[test]
+fn test_alloc_unaligned() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 4, 64, 1 << 16);
+
+    let blocks = [100, 1, 0, 64]
+        .iter()
+        .map(|&size| {
+            allocator.alloc_unaligned::<empty::Backend, _>(&mut owner, &device, size, ())
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let sizes = blocks.iter().map(Block::size).collect::<Vec<_>>();
+    assert_eq!(sizes, vec![128, 64, 64, 64]);
+    assert!(blocks.iter().all(|block| block.range().start % block.size() == 0));
+    match allocator.alloc_unaligned::<empty::Backend, _>(&mut owner, &device, (1 << 16) + 1, ()) {
+        Err(MemoryError::TooLarge) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+fn test_chunk_granularity() {
+    let mut allocator = ChunkedAllocator::<()>::new(MemoryTypeId(0), 3, 256, 1 << 20)
+        .with_chunk_granularity(4096);
+    assert_eq!(allocator.chunk_size(0), 4096);
+    assert_eq!(allocator.chunk_size(4), 12288);
+    assert_eq!(allocator.chunk_size(12), 1 << 20);
+
+    // Slack of the rounded up chunks is divided into blocks
+    allocator.grow(0);
+    assert_eq!(allocator.nodes[0].blocks_per_chunk(), 16);
+}
+
+#[test]
+fn test_max_chunk_size_boundary() {
+    let max_chunk_size = 1 << 20;
+    let mut allocator = ChunkedAllocator::<()>::new(MemoryTypeId(0), 8, 256, max_chunk_size);
+    let index = allocator.pick_node(max_chunk_size).unwrap();
+    assert_eq!(allocator.block_size(index), max_chunk_size);
+    assert_eq!(allocator.chunk_size(index), max_chunk_size);
+    match allocator.pick_node(max_chunk_size + 1) {
+        Err(MemoryError::TooLarge) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+
+    // No node beyond the largest size class is created.
+    allocator.grow(index);
+    assert_eq!(allocator.nodes.len(), index as usize + 1);
+    assert_eq!(allocator.nodes[index as usize].blocks_per_chunk(), 1);
+}
+
+#[test]
+fn test_alloc_max_chunk_size() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let max_chunk_size = 1 << 20;
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 8, 256, max_chunk_size);
+    let reqs = Requirements {
+        type_mask: 1,
+        size: max_chunk_size,
+        alignment: 1,
+    };
+
+    let block = MemorySubAllocator::<empty::Backend, _>::alloc(
+        &mut allocator,
+        &mut owner,
+        &device,
+        (),
+        reqs,
+    ).unwrap();
+    assert_eq!(block.size(), max_chunk_size);
+    assert_eq!(allocator.nodes.len(), allocator.max_index() as usize + 1);
+    MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    assert_eq!(allocator.shrink::<empty::Backend, _>(&mut owner, &device), max_chunk_size);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+fn test_check_invariants() {
+    let memory = 0u32;
+    let free_block = |block_index| FreeBlock {
+        chunk_index: 0,
+        block_index,
+    };
+    let mut allocator = ChunkedAllocator::<RawBlock<u32>>::new(MemoryTypeId(0), 4, 256, 1 << 20);
+    allocator.grow(1);
+    {
+        let node = &mut allocator.nodes[0];
+        node.chunks.push(Some(RawBlock::new(&memory, 0..1024)));
+        node.blocks += 4;
+        node.free.extend((0..4).map(free_block));
+    }
+    assert_eq!(allocator.check_invariants(), Ok(()));
+
+    allocator.nodes[0].free.push(free_block(4));
+    assert_eq!(
+        allocator.check_invariants(),
+        Err(InvariantViolation::InvalidFreeBlock {
+            node: 0,
+            chunk_index: 0,
+            block_index: 4,
+        })
+    );
+    allocator.nodes[0].free.remove(&free_block(4));
+    allocator.nodes[0].free.push(free_block(1));
+    assert_eq!(
+        allocator.check_invariants(),
+        Err(InvariantViolation::DuplicateFreeBlock {
+            node: 0,
+            chunk_index: 0,
+            block_index: 1,
+        })
+    );
+
+    for chunk in allocator.drain_chunks() {
+        unsafe { chunk.dispose() };
+    }
+}
+
+#[test]
+fn test_reserved_count() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 4, 256, 1 << 20).with_auto_shrink(1);
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 256,
+        alignment: 1,
+    };
+
+    let block = MemorySubAllocator::<empty::Backend, _>::alloc(
+        &mut allocator,
+        &mut owner,
+        &device,
+        (),
+        reqs,
+    ).unwrap();
+    assert_eq!(allocator.handed_out_count(), 1);
+    assert_eq!(allocator.outstanding_allocations(), 1);
+    assert_eq!(allocator.outstanding_allocations_of_node(0), 1);
+    assert_eq!(allocator.outstanding_allocations_of_node(1), 0);
+    allocator.free_deferred(block, 1);
+    assert_eq!(allocator.handed_out_count(), 0);
+    assert_eq!(allocator.outstanding_allocations(), 0);
+    assert_eq!(allocator.reserved_count(), 1);
+    assert!(allocator.is_used());
+    assert_eq!(allocator.collect::<empty::Backend, _>(&mut owner, &device, 0), 0);
+    assert_eq!(allocator.reserved_count(), 1);
+
+    // Collected blocks are freed like any other, so the unused chunk is freed by auto-shrink
+    assert_eq!(allocator.collect::<empty::Backend, _>(&mut owner, &device, 1), 1);
+    assert_eq!(allocator.reserved_count(), 0);
+    assert!(!allocator.is_used());
+    assert_eq!((allocator.allocated(), owner.reserved()), (0, 0));
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+fn test_available_for() {
+    let mut allocator = ChunkedAllocator::<()>::new(MemoryTypeId(1), 8, 256, 1 << 20);
+    let reqs = |type_mask, size| Requirements {
+        type_mask,
+        size,
+        alignment: 1,
+    };
+    assert_eq!(allocator.available_for(&reqs(0b10, 256)), 0);
+    assert!(allocator.nodes.is_empty());
+
+    allocator.grow(0);
+    allocator.nodes[0].release_blocks(
+        FreeBlock {
+            chunk_index: 0,
+            block_index: 0,
+        },
+        3,
+    );
+    assert_eq!(allocator.available_for(&reqs(0b10, 200)), 3);
+    assert_eq!(allocator.available_for(&reqs(0b01, 200)), 0);
+    assert_eq!(allocator.available_for(&reqs(0b10, 512)), 0);
+    assert_eq!(allocator.available_for(&reqs(0b10, 2 << 20)), 0);
+    assert_eq!(allocator.nodes.len(), 1);
+}
+
+#[test]
+fn test_validate_for_alignments() {
+    fn round_to_96(size: u64) -> u64 {
+        (size + 95) / 96 * 96
+    }
+
+    let allocator = ChunkedAllocator::<()>::new(MemoryTypeId(0), 8, 256, 1 << 16);
+    assert_eq!(allocator.validate_for_alignments(&[1, 256, 4096, 1 << 16]), Ok(()));
+    assert_eq!(
+        allocator.validate_for_alignments(&[256, 1 << 17]),
+        Err(AlignmentError::Unsatisfiable(1 << 17))
+    );
+    assert_eq!(
+        allocator.validate_for_alignments(&[3]),
+        Err(AlignmentError::NotPowerOfTwo(3))
+    );
+
+    // Block sizes 96, 192, 288 and 384 are aligned to at most 128 bytes
+    let allocator = ChunkedAllocatorBuilder::new(MemoryTypeId(0))
+        .build_with_selector::<(), _>(RoundingSelector::new(round_to_96, 4, 384))
+        .unwrap();
+    assert_eq!(allocator.validate_for_alignments(&[32, 128]), Ok(()));
+    assert_eq!(
+        allocator.validate_for_alignments(&[256]),
+        Err(AlignmentError::Unsatisfiable(256))
+    );
+}
+
+#[test]
+fn test_would_exceed_cap() {
+    let mut allocator = ChunkedAllocator::<()>::new(MemoryTypeId(1), 8, 256, 1 << 20);
+    let reqs = |type_mask, size| Requirements {
+        type_mask,
+        size,
+        alignment: 1,
+    };
+    assert!(allocator.would_exceed_cap(&reqs(0b10, 256), 2047));
+    assert!(!allocator.would_exceed_cap(&reqs(0b10, 256), 2048));
+    assert!(!allocator.would_exceed_cap(&reqs(0b01, 256), 0));
+    assert!(!allocator.would_exceed_cap(&reqs(0b10, 2 << 20), 0));
+
+    allocator.grow(0);
+    allocator.nodes[0].release_blocks(
+        FreeBlock {
+            chunk_index: 0,
+            block_index: 0,
+        },
+        3,
+    );
+    // Free blocks are allocated without growing
+    assert!(!allocator.would_exceed_cap(&reqs(0b10, 200), 0));
+    assert!(allocator.would_exceed_cap(&reqs(0b10, 512), 4095));
+    assert!(!allocator.would_exceed_cap(&reqs(0b10, 512), 4096));
+}
+
+#[test]
+fn test_recommended_min_block_size() {
+    let limits = Limits {
+        min_buffer_copy_offset_alignment: 4,
+        min_texel_buffer_offset_alignment: 16,
+        min_uniform_buffer_offset_alignment: 256,
+        min_storage_buffer_offset_alignment: 48,
+        ..Limits::default()
+    };
+    assert_eq!(recommended_min_block_size(&limits, &[]), 1);
+    assert_eq!(recommended_min_block_size(&limits, &[Usage::VERTEX]), 1);
+    assert_eq!(
+        recommended_min_block_size(&limits, &[Usage::TRANSFER_DST | Usage::STORAGE_TEXEL]),
+        16
+    );
+    assert_eq!(recommended_min_block_size(&limits, &[Usage::STORAGE]), 64);
+    assert_eq!(
+        recommended_min_block_size(&limits, &[Usage::STORAGE, Usage::UNIFORM]),
+        256
+    );
+}
+
+#[test]
+fn test_pick_node_range() {
+    for min_shift in 0..64 {
+        for max_shift in min_shift..64 {
+            let max_chunk_size = 1u64 << max_shift;
+            let allocator = ChunkedAllocator::<()>::new(
+                MemoryTypeId(0),
+                usize::max_value(),
+                1 << min_shift,
+                max_chunk_size,
+            );
+            let index = allocator.pick_node(max_chunk_size).unwrap();
+            assert_eq!(index, allocator.max_index());
+            assert_eq!(allocator.block_size(index), max_chunk_size);
+            assert_eq!(allocator.chunk_size(index), max_chunk_size);
+            assert_eq!(allocator.chunk_size(0), max_chunk_size);
+        }
+    }
+}
+
+#[test]
+fn test_custom_selector() {
+    struct Explicit(Vec<u64>);
+
+    impl NodeSelector for Explicit {
+        fn node_count(&self) -> u8 {
+            self.0.len() as u8
+        }
+
+        fn node(&self, index: u8) -> NodeSpec {
+            NodeSpec {
+                index,
+                block_size: self.0[index as usize],
+                chunk_size: self.0[index as usize] * 4,
+            }
+        }
+
+        fn select(&self, reqs: &Requirements) -> Option<NodeSpec> {
+            let index = self.node_for_size(max(reqs.size, reqs.alignment));
+            Some(self.node(index))
+        }
+    }
+
+    let allocator = ChunkedAllocatorBuilder::new(MemoryTypeId(0))
+        .build_with_selector::<(), _>(Explicit(vec![96, 256, 1024]))
+        .unwrap();
+    assert_eq!(allocator.min_block_size(), 96);
+    assert_eq!(allocator.max_chunk_size(), 1024);
+    assert_eq!(allocator.chunk_size(1), 1024);
+    assert_eq!(allocator.node_with_block_size(96), Some(0));
+    assert_eq!(allocator.node_with_block_size(128), None);
+
+    let reqs = |size, alignment| Requirements {
+        type_mask: 1,
+        size,
+        alignment,
+    };
+    assert_eq!(allocator.pick_node_for(reqs(80, 16)).ok(), Some(0));
+    assert_eq!(allocator.pick_node_for(reqs(97, 1)).ok(), Some(1));
+    // Blocks of 96 bytes are only aligned to 32 bytes
+    assert!(allocator.pick_node_for(reqs(80, 64)).is_err());
+    // Alignment above the size skips under-aligned size classes
+    assert_eq!(allocator.pick_node_for(reqs(16, 64)).ok(), Some(1));
+
+    let allocator = ChunkedAllocatorBuilder::new(MemoryTypeId(0))
+        .build_with_selector::<(), _>(Explicit(vec![64, 192, 256, 384, 512]))
+        .unwrap();
+    // Rounding up to the alignment would pick under-aligned blocks of 192 bytes
+    assert_eq!(allocator.pick_node_for(reqs(100, 128)).ok(), Some(2));
+    assert_eq!(allocator.pick_node_for(reqs(32, 128)).ok(), Some(2));
+    assert_eq!(allocator.pick_node_for(reqs(32, 64)).ok(), Some(0));
+}
+
+#[test]
+fn test_rounding_selector() {
+    fn round_to_96(size: u64) -> u64 {
+        (size + 95) / 96 * 96
+    }
+
+    let selector = RoundingSelector::new(round_to_96, 4, 384);
+    let block_sizes = (0..selector.node_count())
+        .map(|index| selector.node(index))
+        .map(|spec| (spec.block_size, spec.chunk_size))
+        .collect::<Vec<_>>();
+    assert_eq!(block_sizes, vec![(96, 384), (192, 384), (288, 288), (384, 384)]);
+
+    let allocator = ChunkedAllocatorBuilder::new(MemoryTypeId(0))
+        .build_with_selector::<(), _>(selector)
+        .unwrap();
+    let reqs = |size, alignment| Requirements {
+        type_mask: 1,
+        size,
+        alignment,
+    };
+    assert_eq!(allocator.pick_node_for(reqs(1, 1)).ok(), Some(0));
+    assert_eq!(allocator.pick_node_for(reqs(200, 8)).ok(), Some(2));
+    // Blocks of 96 bytes are only aligned to 32 bytes
+    assert_eq!(allocator.pick_node_for(reqs(80, 64)).ok(), Some(1));
+    assert!(allocator.pick_node_for(reqs(385, 1)).is_err());
+
+    // The default rounding yields the size classes of `PowerOfTwoSelector`
+    let default = PowerOfTwoSelector::new(8, 1, 1 << 16);
+    let rounding = RoundingSelector::new(round_to_power_of_two, 8, 1 << 16);
+    assert_eq!(rounding.node_count(), default.node_count());
+    for index in 0..default.node_count() {
+        assert_eq!(rounding.node(index), default.node(index));
+    }
+    for &size in &[1, 3, 100, 1 << 10, (1 << 16) - 1] {
+        assert_eq!(rounding.node_for_size(size), default.node_for_size(size));
+    }
+}
+
+#[test]
+fn test_exact_max_chunk_size() {
+    let max_chunk_size = 3 << 20;
+    let allocator = ChunkedAllocatorBuilder::new(MemoryTypeId(0))
+        .with_blocks_per_chunk(8)
+        .with_min_block_size(256)
+        .with_max_chunk_size(max_chunk_size)
+        .with_exact_max_chunk_size(true)
+        .build::<()>()
+        .unwrap();
+    let top = allocator.pick_node(max_chunk_size).unwrap();
+    assert_eq!(allocator.max_index(), top);
+    assert_eq!(allocator.block_size(top), max_chunk_size);
+    assert_eq!(allocator.chunk_size(top), max_chunk_size);
+    assert_eq!(allocator.block_size(top - 1), 2 << 20);
+    assert_eq!(allocator.pick_node((2 << 20) + 1).unwrap(), top);
+    // Chunks of smaller size classes are rounded down to whole blocks
+    assert_eq!(allocator.chunk_size(top - 1), 2 << 20);
+    assert_eq!(allocator.chunk_size(top - 2), 3 << 20);
+    assert_eq!(allocator.chunk_size(top - 3), 3 << 20);
+    assert_eq!(allocator.chunk_size(top - 4), 2 << 20);
+
+    let reqs = |size, alignment| Requirements {
+        type_mask: 1,
+        size,
+        alignment,
+    };
+    assert_eq!(allocator.pick_node_for(reqs(3 << 20, 1 << 20)).ok(), Some(top));
+    match allocator.pick_node_for(reqs(3 << 20, 2 << 20)) {
+        Err(MemoryError::TooLarge) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+    match allocator.pick_node_for(reqs(1024, 4 << 20)) {
+        Err(MemoryError::TooLarge) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_min_block_size_above_max_chunk_size() {
+    ChunkedAllocator::<()>::new(MemoryTypeId(0), 8, 512, 256);
+}
+
+#[test]
+fn test_builder_errors() {
+    let builder = ChunkedAllocatorBuilder::new(MemoryTypeId(0))
+        .with_blocks_per_chunk(8)
+        .with_min_block_size(256);
+    let build = |builder: ChunkedAllocatorBuilder| builder.build::<()>().err();
+
+    assert_eq!(
+        build(builder.clone()),
+        Some(ConfigError::Missing("max_chunk_size"))
+    );
+    assert_eq!(
+        build(builder.clone().with_max_chunk_size(1000)),
+        Some(ConfigError::NotPowerOfTwo("max_chunk_size"))
+    );
+    assert_eq!(
+        build(builder.clone().with_max_chunk_size(128)),
+        Some(ConfigError::MinBlockSizeAboveMaxChunkSize)
+    );
+    assert_eq!(
+        build(builder.clone().with_max_chunk_size(1024).with_blocks_per_chunk(0)),
+        Some(ConfigError::Zero("blocks_per_chunk"))
+    );
+    assert_eq!(build(builder.clone().with_max_chunk_size(1024)), None);
+    assert_eq!(
+        build(builder.clone().with_max_chunk_size(1 << 63).with_chunk_granularity(3 << 62)),
+        Some(ConfigError::ChunkSizeOverflow(52))
+    );
+    assert_eq!(
+        build(builder.clone().with_max_chunk_size(1024).with_auto_shrink(0)),
+        Some(ConfigError::Zero("every_n_frees"))
+    );
+    assert_eq!(
+        build(builder.clone().with_max_chunk_size(1024).with_owner_min_alignment(2048)),
+        Some(ConfigError::OwnerMinAlignmentAboveMaxChunkSize)
+    );
+    assert_eq!(
+        build(ChunkedAllocatorBuilder {
+            id: MemoryTypeId(64),
+            ..builder.with_max_chunk_size(1024)
+        }),
+        Some(ConfigError::MemoryTypeOutOfRange(MemoryTypeId(64)))
+    );
+}
+
+#[test]
+#[cfg_attr(not(feature = "checks"), allow(unused_mut))]
+fn test_node_of() {
+    let memory = 0u32;
+    let mut allocator = ChunkedAllocator::<()>::new(MemoryTypeId(0), 8, 256, 1 << 20);
+    allocator.grow(2);
+    let block = |range, span| {
+        ChunkedBlock(RawBlock::new(&memory, range), ChunkedTag { span, ..Default::default() })
+    };
+
+    let mut valid = block(0..1024, 1);
+    let mut whole = block(0..8192, 8);
+    let truncated = block(0..1000, 1);
+    let unknown = block(0..2048, 1);
+    #[cfg(feature = "checks")]
+    {
+        valid.1.node = 2;
+        whole.1.node = 2;
+    }
+    assert_eq!(allocator.node_of(&valid).ok(), Some(2));
+    assert_eq!(allocator.node_of(&whole).ok(), Some(2));
+    assert!(allocator.node_of(&truncated).is_err());
+    assert!(allocator.node_of(&unknown).is_err());
+    let mut split = block(0..1024, 1);
+    split.1.parent = Some(4096);
+    #[cfg(feature = "checks")]
+    {
+        split.1.node = 4;
+    }
+    assert_eq!(allocator.node_of(&split).ok(), None);
+    allocator.grow(4);
+    assert_eq!(allocator.node_of(&split).ok(), Some(4));
+
+    for block in [valid, whole, truncated, unknown, split] {
+        unsafe { block.0.dispose() };
+    }
+}
+
+#[test]
+#[cfg(feature = "checks")]
+fn test_node_mismatch() {
+    let memory = 0u32;
+    let mut allocator = ChunkedAllocator::<()>::new(MemoryTypeId(0), 8, 256, 1 << 20);
+    allocator.grow(2);
+    let mut tag = ChunkedTag {
+        span: 1,
+        ..Default::default()
+    };
+    tag.node = 1;
+    let block = ChunkedBlock(RawBlock::new(&memory, 0..1024), tag);
+    match allocator.node_of(&block) {
+        Err(MemoryError::InvalidBlock) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+    unsafe { block.0.dispose() };
+}
+
+#[test]
+fn test_free_list_order() {
+    let block = |chunk_index, block_index| FreeBlock {
+        chunk_index,
+        block_index,
+    };
+    // Order in which the blocks are reused after freeing the first two of a fresh chunk
+    let orders = [
+        (FreeList::Queue, [0, 1, 2, 3]),
+        (FreeList::Stack, [0, 1, 2, 3]),
+        (FreeList::Ordered, [0, 1, 2, 3]),
+    ];
+    for &(free_list, order) in &orders {
+        let mut free = FreeBlocks::new(free_list);
+        free.extend((0..4).map(|i| block(0, i)));
+        assert_eq!(free.pop(), Some(block(0, 0)));
+        assert_eq!(free.pop(), Some(block(0, 1)));
+        free.push(block(0, 1));
+        free.push(block(0, 0));
+        let reused = (0..4).map(|_| free.pop().unwrap().block_index).collect::<Vec<_>>();
+        assert_eq!(reused, order, "{:?}", free_list);
+
+        free.extend((0..4).map(|i| block(0, i)));
+        assert_eq!(free.pop(), Some(block(0, 0)));
+        assert_eq!(free.pop(), Some(block(0, 1)));
+        free.push(block(0, 0));
+        assert_eq!(free.pop(), Some(block(0, 0)));
+        assert_eq!(free.len(), 2);
+        free.pop();
+
+        free.extend((4..6).map(|i| block(0, i)));
+        free.push(block(0, 1));
+        assert_eq!(free.pop_last(), Some(block(0, 5)));
+        assert_eq!(free.pop_last(), Some(block(0, 4)));
+        assert_eq!(free.len(), 2);
+    }
+}
+
+#[test]
+fn test_defragment() {
+    let memory = [0u32; 2];
+    let mut node = ChunkedNode::new(MemoryTypeId(0), 64, 16, FreeList::Queue, false);
+    for (chunk_index, memory) in memory.iter().enumerate() {
+        node.chunks.push(Some(RawBlock::new(memory, 0..64)));
+        node.blocks += 4;
+        node.free.extend((0..4).map(|block_index| FreeBlock {
+            chunk_index,
+            block_index,
+        }));
+    }
+    let mut blocks = (0..8)
+        .map(|_| Some(node.alloc_no_grow::<u32>().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(node.allocations, 8);
+
+    // Leave one used block in the first chunk and three in the second.
+    for &index in &[0, 1, 2, 7] {
+        node.free_block(blocks[index].take().unwrap());
+    }
+
+    let mut relocations = Vec::new();
+    node.defragment(8, &mut relocations);
+    assert_eq!(relocations.len(), 1);
+    assert!(::std::ptr::eq(relocations[0].memory(), &memory[0]));
+    assert_eq!(relocations[0].range(), 48..64);
+    assert!(::std::ptr::eq(relocations[0].block().memory(), &memory[1]));
+    assert_eq!(relocations[0].block().range(), 48..64);
+
+    // Nothing more to do until relocation is applied.
+    node.defragment(8, &mut relocations);
+    assert_eq!(relocations.len(), 1);
+
+    blocks[7] = Some(relocations.pop().unwrap().into_block());
+    node.free_block(blocks[3].take().unwrap());
+    assert!(node.moving.is_empty());
+    assert!(node.targets.is_empty());
+    assert_eq!(node.free.iter().filter(|free| free.chunk_index == 0).count(), 4);
+
+    for block in blocks.into_iter().flatten() {
+        node.free_block(block);
+    }
+    for chunk in node.chunks.drain(..).flatten() {
+        unsafe { chunk.dispose() };
     }
 }
 
 #[test]
-#[allow(dead_code)]
-fn test_send_sync() {
-    fn foo<T: Send + Sync>() {}
-    fn bar<M: Send + Sync>() {
-        foo::<ChunkedAllocator<M>>()
+fn test_defragment_abandoned() {
+    let memory = [0u32; 2];
+    let mut node = ChunkedNode::new(MemoryTypeId(0), 64, 16, FreeList::Queue, false);
+    for (chunk_index, memory) in memory.iter().enumerate() {
+        node.chunks.push(Some(RawBlock::new(memory, 0..64)));
+        node.blocks += 4;
+        node.free.extend((0..4).map(|block_index| FreeBlock {
+            chunk_index,
+            block_index,
+        }));
+    }
+    let mut blocks = (0..8)
+        .map(|_| Some(node.alloc_no_grow::<u32>().unwrap()))
+        .collect::<Vec<_>>();
+    for &index in &[0, 1, 2, 7] {
+        node.free_block(blocks[index].take().unwrap());
+    }
+
+    let mut relocations = Vec::new();
+    node.defragment(8, &mut relocations);
+    assert_eq!(relocations.len(), 1);
+
+    // Freeing the target instead of the source abandons the relocation
+    node.free_block(relocations.pop().unwrap().into_block());
+    assert!(node.moving.is_empty());
+    assert!(node.targets.is_empty());
+
+    // And it is planned again
+    node.defragment(8, &mut relocations);
+    assert_eq!(relocations.len(), 1);
+    node.free_block(relocations.pop().unwrap().into_block());
+
+    for block in blocks.into_iter().flatten() {
+        node.free_block(block);
+    }
+    for chunk in node.chunks.drain(..).flatten() {
+        unsafe { chunk.dispose() };
+    }
+}
+
+#[test]
+#[cfg(feature = "checks")]
+fn test_generations() {
+    let memory = 0u32;
+    let mut node = ChunkedNode::new(MemoryTypeId(0), 64, 32, FreeList::Queue, false);
+    node.chunks.push(Some(RawBlock::new(&memory, 0..64)));
+    node.blocks += 2;
+    node.free.extend((0..2).map(|block_index| FreeBlock {
+        chunk_index: 0,
+        block_index,
+    }));
+
+    let block = node.alloc_no_grow::<u32>().unwrap();
+    assert_eq!(block.1.generation, 0);
+    node.free_block(block);
+
+    // Same memory is reused with a new generation
+    let block = node.alloc_no_grow::<u32>().unwrap();
+    assert_eq!(block.range(), 0..32);
+    assert_eq!(block.1.generation, 1);
+    node.free_block(block);
+
+    for chunk in node.chunks.drain(..).flatten() {
+        unsafe { chunk.dispose() };
+    }
+}
+
+#[test]
+#[cfg(feature = "debug-backtrace")]
+fn test_backtraces() {
+    let memory = 0u32;
+    let mut node = ChunkedNode::new(MemoryTypeId(0), 64, 32, FreeList::Queue, false);
+    node.chunks.push(Some(RawBlock::new(&memory, 0..64)));
+    node.blocks += 2;
+    node.free.extend((0..2).map(|block_index| FreeBlock {
+        chunk_index: 0,
+        block_index,
+    }));
+
+    let block = node.alloc_no_grow::<u32>().unwrap();
+    assert_eq!(node.backtraces.len(), 1);
+    node.free_block(block);
+    assert!(node.backtraces.is_empty());
+
+    for chunk in node.chunks.drain(..).flatten() {
+        unsafe { chunk.dispose() };
+    }
+}
+
+#[test]
+#[cfg(feature = "testing")]
+fn test_restore_state() {
+    let memory = [0u32; 2];
+    let mut node = ChunkedNode::new(MemoryTypeId(0), 64, 16, FreeList::Queue, false);
+    for (chunk_index, memory) in memory.iter().enumerate() {
+        node.chunks.push(Some(RawBlock::new(memory, 0..64)));
+        node.blocks += 4;
+        node.free.extend((0..4).map(|block_index| FreeBlock {
+            chunk_index,
+            block_index,
+        }));
+    }
+    let mut blocks = (0..8)
+        .map(|_| node.alloc_no_grow::<u32>().unwrap())
+        .collect::<Vec<_>>();
+    for index in (0..8).filter(|index| index % 3 != 0).rev() {
+        node.free_block(blocks.remove(index));
+    }
+    let state = node.save_state();
+
+    let mut restored = ChunkedNode::new(MemoryTypeId(0), 64, 16, FreeList::Queue, false);
+    let chunks = memory
+        .iter()
+        .map(|memory| Some(RawBlock::new(memory, 0..64)))
+        .collect();
+    let mut restored_blocks = restored.restore_state::<u32>(chunks, &state, &[]);
+    assert_eq!(restored.save_state(), state);
+    let ranges = |blocks: &[ChunkedBlock<u32>]| {
+        blocks.iter().map(Block::range).collect::<Vec<_>>()
+    };
+    assert_eq!(ranges(&restored_blocks), ranges(&blocks));
+    // Free blocks are allocated in the same order
+    blocks.push(node.alloc_no_grow().unwrap());
+    restored_blocks.push(restored.alloc_no_grow().unwrap());
+    assert_eq!(ranges(&restored_blocks), ranges(&blocks));
+
+    for (node, blocks) in [(node, blocks), (restored, restored_blocks)] {
+        for block in blocks {
+            unsafe { block.0.dispose() };
+        }
+        for chunk in node.chunks.into_iter().flatten() {
+            unsafe { chunk.dispose() };
+        }
+    }
+}
+
+#[test]
+fn test_alloc_low() {
+    let memory = 0u32;
+    let mut node = ChunkedNode::new(MemoryTypeId(0), 64, 16, FreeList::Queue, false);
+    for &start in &[1024, 0] {
+        let chunk_index = node.chunks.len();
+        node.chunks.push(Some(RawBlock::new(&memory, start..start + 64)));
+        node.blocks += 4;
+        node.free.extend((0..4).map(|block_index| FreeBlock {
+            chunk_index,
+            block_index,
+        }));
+    }
+
+    let block = node.alloc_low::<u32>(64).unwrap();
+    assert_eq!(block.range(), 0..16);
+    assert!(node.alloc_low::<u32>(15).is_none());
+    node.free_block(block);
+
+    for chunk in node.chunks.drain(..).flatten() {
+        unsafe { chunk.dispose() };
+    }
+}
+
+#[test]
+#[cfg(feature = "registry")]
+fn test_alloc_low_registry() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    // Memory type no other test uses, to find the allocator in the registry
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(62), 4, 256, 1 << 16);
+    let reqs = Requirements {
+        type_mask: 1 << 62,
+        size: 256,
+        alignment: 1,
+    };
+    let usage = || {
+        let view = ::registry::snapshot()
+            .into_iter()
+            .find(|view| view.memory_type == MemoryTypeId(62))
+            .unwrap();
+        (view.allocated, view.used)
+    };
+
+    let first = allocator
+        .alloc_low::<empty::Backend, _>(&mut owner, &device, (), reqs, 1024)
+        .unwrap();
+    assert_eq!(usage(), (1024, 256));
+    // Served from the free blocks of the chunk, without growing
+    let second = allocator
+        .alloc_low::<empty::Backend, _>(&mut owner, &device, (), reqs, 1024)
+        .unwrap();
+    assert_eq!(usage(), (1024, 512));
+
+    for block in [first, second] {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    allocator.shrink::<empty::Backend, _>(&mut owner, &device);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+fn test_coloring() {
+    let memory = 0u32;
+    let mut node = ChunkedNode::new(MemoryTypeId(0), 64, 16, FreeList::Queue, false);
+    node.colors = 2;
+    node.chunks.push(Some(RawBlock::new(&memory, 0..64)));
+    node.blocks += 4;
+    node.free.extend((0..4).map(|block_index| FreeBlock {
+        chunk_index: 0,
+        block_index,
+    }));
+
+    // The freed block would be reused first without coloring
+    let block = node.alloc_no_grow::<u32>().unwrap();
+    assert_eq!(block.range(), 0..16);
+    node.free_block(block);
+    let first = node.alloc_no_grow::<u32>().unwrap();
+    assert_eq!(first.range(), 16..32);
+    let second = node.alloc_no_grow::<u32>().unwrap();
+    assert_eq!(second.range(), 0..16);
+    node.free_block(first);
+    node.free_block(second);
+
+    for chunk in node.chunks.drain(..).flatten() {
+        unsafe { chunk.dispose() };
+    }
+}
+
+#[test]
+#[cfg(feature = "paranoid")]
+fn test_corrupt_tag() {
+    let memory = 0u32;
+    let mut node = ChunkedNode::new(MemoryTypeId(0), 64, 32, FreeList::Queue, false);
+    for chunk_index in 0..2 {
+        let start = chunk_index as u64 * 64;
+        node.chunks.push(Some(RawBlock::new(&memory, start..start + 64)));
+        node.blocks += 2;
+        node.free.extend((0..2).map(|block_index| FreeBlock {
+            chunk_index,
+            block_index,
+        }));
+    }
+
+    let mut blocks = (0..3)
+        .map(|_| node.alloc_no_grow::<u32>().unwrap())
+        .collect::<Vec<_>>();
+    let mut block = blocks.pop().unwrap();
+    assert_eq!(block.1.chunk_index, 1);
+    assert!(node.check(&block).is_ok());
+    // Chunk 0 is bound to the same memory, but doesn't contain the block
+    block.1.chunk_index = 0;
+    match node.check(&block) {
+        Err(MemoryError::CorruptTag) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+    block.1.chunk_index = 1;
+
+    blocks.push(block);
+    for block in blocks {
+        node.free_block(block);
+    }
+    for chunk in node.chunks.drain(..).flatten() {
+        unsafe { chunk.dispose() };
+    }
+}
+
+#[test]
+fn test_peek_next() {
+    let memory = 0u32;
+    let mut allocator = ChunkedAllocator::<RawBlock<u32>>::new(MemoryTypeId(0), 4, 256, 1 << 20)
+        .with_free_list(FreeList::Stack);
+    let reqs = |size| Requirements {
+        type_mask: 1,
+        size,
+        alignment: 1,
+    };
+    allocator.grow(0);
+    {
+        let node = &mut allocator.nodes[0];
+        node.chunks.push(Some(RawBlock::new(&memory, 1024..2048)));
+        node.blocks += 4;
+        node.free.extend((0..4).map(|block_index| FreeBlock {
+            chunk_index: 0,
+            block_index,
+        }));
+    }
+    // Size class that would have to grow
+    assert_eq!(allocator.peek_next::<u32>(reqs(512)), None);
+
+    let mut blocks = Vec::new();
+    while let Some((memory, range)) = allocator.peek_next(reqs(100)) {
+        assert_eq!(allocator.peek_next(reqs(100)), Some((memory, range.clone())));
+        let block = allocator.nodes[0].alloc_no_grow::<u32>().unwrap();
+        assert!(::std::ptr::eq(block.memory(), memory));
+        assert_eq!(block.range(), range);
+        blocks.push(block);
+    }
+    assert_eq!(blocks.len(), 4);
+
+    for block in blocks {
+        allocator.nodes[0].free_block(block);
+    }
+    for chunk in allocator.drain_chunks() {
+        unsafe { chunk.dispose() };
+    }
+}
+
+#[test]
+fn test_single_block_view() {
+    let memory = 0u32;
+    let block = RawBlock::new(&memory, 256..1280);
+    let mut view = ChunkedAllocator::single_block_view(block, 256);
+    let reqs = |size, alignment| Requirements {
+        type_mask: 0,
+        size,
+        alignment,
+    };
+
+    let blocks = (0..4)
+        .map(|_| view.alloc::<u32>(reqs(200, 256)).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(blocks[0].range(), 256..512);
+    assert!(view.alloc::<u32>(reqs(200, 1)).is_err());
+    match view.alloc::<u32>(reqs(257, 1)) {
+        Err(MemoryError::TooLarge) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+
+    let mut blocks = blocks.into_iter();
+    let first = blocks.next().unwrap();
+    for block in blocks {
+        view.free(block);
+    }
+    // Block is only handed back once all blocks are free
+    let mut view = view.into_block().unwrap_err();
+    view.free(first);
+    assert_eq!(view.available(), 4);
+    let block = view.into_block().unwrap();
+    assert_eq!(block.range(), 256..1280);
+    unsafe { block.dispose() };
+}
+
+#[test]
+fn test_alloc_in_memory() {
+    let (first, second) = (0u32, 0u32);
+    let mut node = ChunkedNode::new(MemoryTypeId(0), 64, 32, FreeList::Queue, false);
+    for memory in &[&first, &second] {
+        let chunk_index = node.chunks.len();
+        node.chunks.push(Some(RawBlock::new(*memory, 0..64)));
+        node.blocks += 2;
+        node.free.extend((0..2).map(|block_index| FreeBlock {
+            chunk_index,
+            block_index,
+        }));
+    }
+
+    let block = node.alloc_in_memory(&second).unwrap();
+    assert!(::std::ptr::eq(block.memory(), &second));
+    node.free_block(block);
+    assert!(node.alloc_in_memory::<u32>(&0).is_none());
+
+    for chunk in node.chunks.drain(..).flatten() {
+        unsafe { chunk.dispose() };
+    }
+}
+
+#[test]
+fn test_alloc_at() {
+    let memory = 0u32;
+    let mut node = ChunkedNode::new(MemoryTypeId(0), 64, 16, FreeList::Queue, false);
+    node.chunks.push(Some(RawBlock::new(&memory, 0..64)));
+    node.blocks += 4;
+    node.free.extend((0..4).map(|block_index| FreeBlock {
+        chunk_index: 0,
+        block_index,
+    }));
+
+    let block = node.alloc_at::<u32>(0, 32).unwrap();
+    assert_eq!(block.range(), 32..48);
+    assert!(node.alloc_at::<u32>(0, 32).is_err());
+    assert!(node.alloc_at::<u32>(0, 8).is_err());
+    assert!(node.alloc_at::<u32>(0, 64).is_err());
+    assert!(node.alloc_at::<u32>(1, 0).is_err());
+    node.free_block(block);
+    assert_eq!(node.free.len(), 4);
+
+    for chunk in node.chunks.drain(..).flatten() {
+        unsafe { chunk.dispose() };
+    }
+}
+
+#[test]
+fn test_transfer_block() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut source =
+        ChunkedAllocator::new(MemoryTypeId(0), 4, 1024, 1 << 16).with_lru_tracking(true);
+    let mut dest = source.clone_config();
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 1024,
+        alignment: 1,
+    };
+    let mut blocks = (0..3)
+        .map(|_| {
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut source,
+                &mut owner,
+                &device,
+                (),
+                reqs,
+            ).unwrap()
+        })
+        .collect::<Vec<_>>();
+    let chunk_index = blocks[0].chunk_index();
+    source.free_deferred(blocks.remove(2), 0);
+    source.touch(&blocks[0]);
+
+    // Every used block of the chunk has to be passed along
+    match source.transfer_block(1024, chunk_index, &mut blocks[..1], &mut dest) {
+        Err(MemoryError::ChunkInUse) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+    assert_eq!((source.used(), dest.owner), (3072, None));
+    assert_eq!(source.transfer_block(1024, chunk_index, &mut blocks, &mut dest).unwrap(), 0);
+    assert_eq!((source.allocated(), dest.allocated()), (0, 4096));
+    assert!(!source.is_used());
+    assert_eq!(dest.lru_candidate(), Some(dest.handle_of(&blocks[1])));
+    assert_eq!(dest.collect::<empty::Backend, _>(&mut owner, &device, 0), 1);
+    assert_eq!(dest.used(), 2048);
+
+    // Blocks are served from the transferred chunk without growing
+    blocks.push(
+        MemorySubAllocator::<empty::Backend, _>::alloc(&mut dest, &mut owner, &device, (), reqs)
+            .unwrap(),
+    );
+    assert_eq!(owner.allocations(), 1);
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut dest, &mut owner, &device, block);
+    }
+    MemorySubAllocator::<empty::Backend, _>::dispose(source, &mut owner, &device).unwrap();
+    assert_eq!(owner.reserved(), 4096);
+    MemorySubAllocator::<empty::Backend, _>::dispose(dest, &mut owner, &device).unwrap();
+    assert_eq!(owner.reserved(), 0);
+}
+
+#[test]
+#[cfg(feature = "testing")]
+fn test_compact_to_fit() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+    use fault::{FaultInjector, FaultSchedule};
+
+    let device = empty::Device;
+    let mut owner = FaultInjector::new(DryRunAllocator::<()>::new(), FaultSchedule::Never);
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 2, 256, 1 << 16);
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 256,
+        alignment: 1,
+    };
+    let whole = allocator
+        .alloc_whole_chunk::<empty::Backend, _>(&mut owner, &device, (), reqs)
+        .unwrap();
+    let mut blocks = (0..6)
+        .map(|_| {
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut allocator,
+                &mut owner,
+                &device,
+                (),
+                reqs,
+            ).unwrap()
+        })
+        .collect::<Vec<_>>();
+    // Leave one used block in each of three chunks
+    for index in (0..3).rev() {
+        let block = blocks.remove(index * 2 + 1);
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    assert_eq!(allocator.allocated(), 2048);
+
+    // Chunks allocated before the failure are freed again
+    owner.reset(FaultSchedule::Indices(vec![1]));
+    assert!(
+        allocator
+            .compact_to_fit::<empty::Backend, _>(&mut owner, &device, ())
+            .is_empty()
+    );
+    assert_eq!(owner.inner().reserved(), 2048);
+    assert_eq!(allocator.allocated(), 2048);
+
+    // The chunk of the block covering two blocks is left alone
+    owner.reset(FaultSchedule::Never);
+    let relocations = allocator.compact_to_fit::<empty::Backend, _>(&mut owner, &device, ());
+    assert_eq!(relocations.len(), 3);
+    assert_eq!(allocator.allocated(), 3072);
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    allocator.shrink::<empty::Backend, _>(&mut owner, &device);
+    assert_eq!(allocator.allocated(), 1536);
+
+    for relocation in relocations {
+        let block = relocation.into_block();
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, whole);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+    assert_eq!(owner.inner().reserved(), 0);
+}
+
+#[test]
+fn test_fill_chunks() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+    use owned::OwnedChunkedAllocator;
+
+    let device = empty::Device;
+    // Chunks of 768 bytes are served as blocks of 1024 bytes
+    let outer = ChunkedAllocator::new(MemoryTypeId(0), 4, 256, 1 << 16);
+    let mut owner = OwnedChunkedAllocator::new(outer, DryRunAllocator::<()>::new());
+    let mut allocator =
+        ChunkedAllocator::new(MemoryTypeId(0), 3, 256, 1 << 12).with_fill_chunks(true);
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 256,
+        alignment: 1,
+    };
+
+    let blocks = (0..4)
+        .map(|_| {
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut allocator,
+                &mut owner,
+                &device,
+                (),
+                reqs,
+            ).unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(allocator.chunk_count(), 1);
+    assert_eq!((allocator.allocated(), allocator.used()), (1024, 1024));
+    assert!(allocator.defragment_budgeted::<()>(4).is_empty());
+    #[cfg(feature = "metrics")]
+    allocator.export_metrics(&::metrics::NoopRecorder);
+
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    assert_eq!(allocator.shrink::<empty::Backend, _>(&mut owner, &device), 1024);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+    MemoryAllocator::<empty::Backend>::dispose(owner, &device).unwrap();
+}
+
+#[test]
+fn test_soft_reset_fill_chunks() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+    use owned::OwnedChunkedAllocator;
+
+    let device = empty::Device;
+    let outer = ChunkedAllocator::new(MemoryTypeId(0), 4, 256, 1 << 16);
+    let mut owner = OwnedChunkedAllocator::new(outer, DryRunAllocator::<()>::new());
+    let mut allocator =
+        ChunkedAllocator::new(MemoryTypeId(0), 3, 256, 1 << 12).with_fill_chunks(true);
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 256,
+        alignment: 1,
+    };
+
+    let blocks = (0..8)
+        .map(|_| {
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut allocator,
+                &mut owner,
+                &device,
+                (),
+                reqs,
+            ).unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(allocator.chunk_count(), 2);
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    // The released chunk is larger than the 768 bytes requested for it
+    assert_eq!(allocator.soft_reset::<empty::Backend, _>(&mut owner, &device), 1024);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+    MemoryAllocator::<empty::Backend>::dispose(owner, &device).unwrap();
+}
+
+#[test]
+fn test_on_churn_limit() {
+    extern crate gfx_backend_empty as empty;
+
+    use std::sync::{Arc, Mutex};
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 4, 256, 1 << 16)
+        .with_churn_limit(2, false);
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let sink = reports.clone();
+    allocator.on_churn_limit(Box::new(move |churn| sink.lock().unwrap().push(churn)));
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 256,
+        alignment: 1,
+    };
+
+    for frame in 0..2 {
+        // Only the first frame exceeds the limit
+        for _ in 0..(2 - frame) {
+            let block = MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut allocator,
+                &mut owner,
+                &device,
+                (),
+                reqs,
+            ).unwrap();
+            MemorySubAllocator::<empty::Backend, _>::free(
+                &mut allocator,
+                &mut owner,
+                &device,
+                block,
+            );
+        }
+        allocator.frame_boundary();
+    }
+    let churn = ChurnStats {
+        allocations: 2,
+        frees: 2,
+        grows: 1,
+    };
+    assert_eq!(*reports.lock().unwrap(), vec![churn]);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+#[cfg(feature = "testing")]
+fn test_alloc_many() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+    use fault::{FaultInjector, FaultSchedule};
+
+    let device = empty::Device;
+    let schedule = FaultSchedule::Indices(vec![2]);
+    let mut owner = FaultInjector::new(DryRunAllocator::<()>::new(), schedule);
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 1, 256, 1 << 16);
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 256,
+        alignment: 1,
+    };
+
+    // Each block takes a chunk of its own, so the third block fails and the others are freed
+    match allocator.alloc_many::<empty::Backend, _>(&mut owner, &device, (), reqs, 3) {
+        Err(MemoryError::OutOfMemory) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+    assert!(!allocator.is_used());
+    assert_eq!(allocator.allocated(), 512);
+
+    let blocks = allocator
+        .alloc_many::<empty::Backend, _>(&mut owner, &device, (), reqs, 3)
+        .unwrap();
+    assert_eq!(blocks.len(), 3);
+    assert_eq!(allocator.used(), 768);
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+    assert_eq!(owner.inner().reserved(), 0);
+}
+
+#[test]
+fn test_alloc_flexible() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 4, 256, 1 << 16);
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 1024,
+        alignment: 1,
+    };
+    let mut blocks = vec![
+        MemorySubAllocator::<empty::Backend, _>::alloc(
+            &mut allocator,
+            &mut owner,
+            &device,
+            (),
+            reqs,
+        ).unwrap(),
+    ];
+    assert_eq!(allocator.allocated(), 4096);
+
+    // A free block of a larger size class in the range is used instead of growing
+    let block = allocator
+        .alloc_flexible::<empty::Backend, _>(&mut owner, &device, 300, 2048, 1, ())
+        .unwrap();
+    assert_eq!(block.size(), 1024);
+    assert_eq!(allocator.allocated(), 4096);
+    blocks.push(block);
+
+    // Otherwise the smallest size class of at least `min` bytes grows
+    let block = allocator
+        .alloc_flexible::<empty::Backend, _>(&mut owner, &device, 300, 512, 1, ())
+        .unwrap();
+    assert_eq!(block.size(), 512);
+    assert_eq!(allocator.allocated(), 4096 + 2048);
+    blocks.push(block);
+
+    match allocator.alloc_flexible::<empty::Backend, _>(&mut owner, &device, 300, 400, 1, ()) {
+        Err(MemoryError::OutOfMemory) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    allocator.shrink::<empty::Backend, _>(&mut owner, &device);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+#[should_panic(expected = "is empty")]
+fn test_alloc_flexible_empty_range() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 4, 256, 1 << 16);
+    let _ = allocator.alloc_flexible::<empty::Backend, _>(&mut owner, &device, 512, 256, 1, ());
+}
+
+#[test]
+fn test_alloc_mip_tail() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 4, 256, 1 << 16);
+    let reqs = |size| Requirements {
+        type_mask: 1,
+        size,
+        alignment: 4,
+    };
+    // Fill the front of the shared memory object so tails don't start at 0 by chance
+    let mut blocks = vec![
+        MemorySubAllocator::<empty::Backend, _>::alloc(
+            &mut allocator,
+            &mut owner,
+            &device,
+            (),
+            reqs(256),
+        ).unwrap(),
+    ];
+
+    for &(size, granularity, tail_size) in &[(300, 1024, 1024), (1500, 1024, 2048), (0, 512, 512)] {
+        let block = allocator
+            .alloc_mip_tail::<empty::Backend, _>(&mut owner, &device, reqs(size), granularity, ())
+            .unwrap();
+        assert_eq!(block.size(), tail_size);
+        assert_eq!(block.range().start % granularity, 0);
+        blocks.push(block);
+    }
+    // Tails of the same size are packed next to each other
+    let block = allocator
+        .alloc_mip_tail::<empty::Backend, _>(&mut owner, &device, reqs(600), 1024, ())
+        .unwrap();
+    assert_eq!(block.range().start, blocks[1].range().end);
+    blocks.push(block);
+
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    allocator.shrink::<empty::Backend, _>(&mut owner, &device);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+fn test_realloc() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 4, 256, 1 << 16);
+    let reqs = |size| Requirements {
+        type_mask: 1,
+        size,
+        alignment: 1,
+    };
+    let block = MemorySubAllocator::<empty::Backend, _>::alloc(
+        &mut allocator,
+        &mut owner,
+        &device,
+        (),
+        reqs(300),
+    ).unwrap();
+    let range = block.range();
+
+    // Growing within the size class keeps the memory
+    let block = allocator
+        .realloc::<empty::Backend, _>(&mut owner, &device, block, (), reqs(500))
+        .unwrap();
+    assert_eq!(block.range(), range);
+    assert_eq!(allocator.used(), 512);
+
+    // Another size class moves the block
+    let block = allocator
+        .realloc::<empty::Backend, _>(&mut owner, &device, block, (), reqs(1000))
+        .unwrap();
+    assert_eq!(block.size(), 1024);
+    assert_eq!(allocator.used(), 1024);
+    allocator.check_invariants().unwrap();
+
+    // The old block is returned if no new one can be allocated
+    let block = match allocator.realloc::<empty::Backend, _>(
+        &mut owner,
+        &device,
+        block,
+        (),
+        reqs(1 << 17),
+    ) {
+        Err((MemoryError::TooLarge, block)) => block,
+        result => panic!("Unexpected result {:?}", result),
+    };
+    assert_eq!(block.size(), 1024);
+    assert_eq!(allocator.used(), 1024);
+    allocator.check_invariants().unwrap();
+
+    MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    allocator.shrink::<empty::Backend, _>(&mut owner, &device);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+fn test_memory_pressure() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 2, 256, 1 << 16);
+    allocator.set_pressure_threshold(Some(2048));
+    let reqs = |size| Requirements {
+        type_mask: 1,
+        size,
+        alignment: 1,
+    };
+    let blocks = (0..2)
+        .map(|_| {
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut allocator,
+                &mut owner,
+                &device,
+                (),
+                reqs(256),
+            ).unwrap()
+        })
+        .collect::<Vec<_>>();
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    assert_eq!(allocator.allocated(), 512);
+
+    // Growing over the threshold releases the unused chunk first
+    let block = MemorySubAllocator::<empty::Backend, _>::alloc(
+        &mut allocator,
+        &mut owner,
+        &device,
+        (),
+        reqs(1024),
+    ).unwrap();
+    assert_eq!(allocator.allocated(), 2048);
+    assert_eq!(owner.reserved(), 2048);
+    MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+
+    // Chunks kept by `shrink` are freed under memory pressure
+    allocator.set_min_free_chunks(1024, 1);
+    assert_eq!(allocator.shrink::<empty::Backend, _>(&mut owner, &device), 0);
+    assert_eq!(
+        allocator.on_memory_pressure::<empty::Backend, _>(&mut owner, &device),
+        2048
+    );
+    assert_eq!(owner.reserved(), 0);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+fn test_scopes() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 4, 256, 1 << 16);
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 300,
+        alignment: 1,
+    };
+
+    let terrain = allocator.scope("terrain");
+    assert_eq!(allocator.scope("terrain"), terrain);
+    let block = allocator
+        .alloc_scoped::<empty::Backend, _>(&mut owner, &device, terrain, (), reqs)
+        .unwrap();
+    assert_eq!(
+        allocator.scope_usage("terrain"),
+        ScopeStats {
+            bytes: 512,
+            blocks: 1,
+        }
+    );
+    MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    assert_eq!(allocator.scope_usage("terrain"), ScopeStats::default());
+
+    // The slot of a closed scope is reused with a new handle
+    allocator.close_scope(terrain);
+    assert_ne!(allocator.scope("particles"), terrain);
+
+    allocator.shrink::<empty::Backend, _>(&mut owner, &device);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+#[should_panic(expected = "Scope is closed")]
+fn test_closed_scope() {
+    let mut allocator = ChunkedAllocator::<()>::new(MemoryTypeId(0), 4, 256, 1 << 16);
+    let terrain = allocator.scope("terrain");
+    allocator.close_scope(terrain);
+    allocator.scope("particles");
+    allocator.close_scope(terrain);
+}
+
+#[test]
+#[should_panic(expected = "Scope handle of another allocator")]
+fn test_foreign_scope() {
+    let mut allocator = ChunkedAllocator::<()>::new(MemoryTypeId(0), 4, 256, 1 << 16);
+    let mut other = ChunkedAllocator::<()>::new(MemoryTypeId(0), 4, 256, 1 << 16);
+    allocator.scope("terrain");
+    let terrain = other.scope("terrain");
+    allocator.close_scope(terrain);
+}
+
+#[test]
+#[cfg(feature = "checks")]
+fn test_try_free_wrong_node() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(0), 4, 256, 1 << 16);
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 256,
+        alignment: 1,
+    };
+    let mut block = MemorySubAllocator::<empty::Backend, _>::alloc(
+        &mut allocator,
+        &mut owner,
+        &device,
+        (),
+        reqs,
+    ).unwrap();
+
+    // A block whose size doesn't lead back to its node is rejected instead of panicking
+    block.1.node = 1;
+    let mut block = match allocator.try_free::<empty::Backend, _>(&mut owner, &device, block) {
+        Err((MemoryError::InvalidBlock, block)) => block,
+        result => panic!("Unexpected result {:?}", result),
+    };
+    assert!(allocator.is_used());
+
+    block.1.node = 0;
+    allocator
+        .try_free::<empty::Backend, _>(&mut owner, &device, block)
+        .unwrap();
+    allocator.shrink::<empty::Backend, _>(&mut owner, &device);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
+
+#[test]
+fn test_oversize_split_bookkeeping() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    // Memory type no other test uses, to find the allocator in the registry
+    let mut allocator = ChunkedAllocator::new(MemoryTypeId(61), 2, 256, 1 << 16);
+    let reqs = |size| Requirements {
+        type_mask: 1 << 61,
+        size,
+        alignment: 1,
+    };
+    let large = (0..2)
+        .map(|_| {
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut allocator,
+                &mut owner,
+                &device,
+                (),
+                reqs(512),
+            ).unwrap()
+        })
+        .collect::<Vec<_>>();
+    let mut large = large.into_iter();
+    let kept = large.next().unwrap();
+    MemorySubAllocator::<empty::Backend, _>::free(
+        &mut allocator,
+        &mut owner,
+        &device,
+        large.next().unwrap(),
+    );
+    allocator.frame_boundary();
+
+    let split = allocator
+        .alloc_allow_oversize_split::<empty::Backend, _>(&mut owner, &device, (), reqs(256))
+        .unwrap();
+    assert_eq!(split.1.parent, Some(512));
+    assert_eq!(owner.allocations(), 1);
+    assert_eq!(allocator.used(), 1024);
+    assert_eq!(allocator.churn_stats().allocations, 1);
+    #[cfg(feature = "registry")]
+    {
+        let view = ::registry::snapshot()
+            .into_iter()
+            .find(|view| view.memory_type == MemoryTypeId(61))
+            .unwrap();
+        assert_eq!((view.allocated, view.used), (1024, 1024));
+    }
+
+    for block in [kept, split] {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
     }
+    allocator.shrink::<empty::Backend, _>(&mut owner, &device);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
 }
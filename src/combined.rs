@@ -8,7 +8,7 @@ use gfx_hal::memory::Requirements;
 use {MemoryAllocator, MemoryError, MemorySubAllocator};
 use arena::{ArenaAllocator, ArenaBlock};
 use block::{Block, RawBlock};
-use chunked::{ChunkedAllocator, ChunkedBlock};
+use chunked::{ChunkedAllocator, ChunkedBlock, ChunkedTag};
 use root::RootAllocator;
 
 /// Controls what sub allocator is used for an allocation by `CombinedAllocator`
@@ -142,6 +142,10 @@ where
         self.allocations -= 1;
     }
 
+    fn block_memory_type(&self, _block: &CombinedBlock<B::Memory>) -> Option<MemoryTypeId> {
+        Some(self.memory_type())
+    }
+
     fn is_used(&self) -> bool {
         if self.allocations == 0 {
             debug_assert!(!self.arenas.is_used() && !self.chunks.is_used());
@@ -169,7 +173,7 @@ pub struct CombinedBlock<M>(pub(crate) RawBlock<M>, pub(crate) CombinedTag);
 #[derive(Debug)]
 pub(crate) enum CombinedTag {
     Arena(u64),
-    Chunked(usize),
+    Chunked(ChunkedTag),
     Root,
 }
 
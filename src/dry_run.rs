@@ -0,0 +1,173 @@
+use std::collections::BTreeMap;
+
+use gfx_hal::{Backend, MemoryTypeId};
+use gfx_hal::memory::Requirements;
+
+use {alignment_shift, next_instance_id, MemoryAllocator, MemoryError};
+use block::{Block, RawBlock};
+
+/// Call made to the underlying allocator, as recorded by `DryRunAllocator`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OwnerCall {
+    /// A block was allocated.
+    Alloc {
+        /// Memory type the block would be allocated from
+        memory_type: MemoryTypeId,
+        /// Size of the block in bytes
+        size: u64,
+        /// Alignment of the block in bytes
+        alignment: u64,
+    },
+    /// A block was freed.
+    Free {
+        /// Memory type the block was allocated from
+        memory_type: MemoryTypeId,
+        /// Size of the block in bytes
+        size: u64,
+    },
+}
+
+/// Allocator that records the device allocations it would make instead of making them.
+///
+/// Use it as the owner of sub-allocators such as `ChunkedAllocator` to replay a sequence of
+/// allocations offline, e.g. with `gfx-backend-empty`, and see which device allocations the
+/// configuration would trigger. The device is never touched. All blocks share a single
+/// placeholder memory object and are placed at consecutive offsets. The resulting block layout
+/// of the sub-allocator can be inspected with e.g. `ChunkedAllocator::layout`.
+///
+/// ### Type parameters:
+///
+/// - `M`: hal memory type.
+#[derive(Debug)]
+pub struct DryRunAllocator<M> {
+    memory: Box<M>,
+    instance_id: usize,
+    offset: u64,
+    reserved: u64,
+    peak_reserved: u64,
+    blocks: BTreeMap<u64, MemoryTypeId>,
+    calls: Vec<OwnerCall>,
+}
+
+impl<M> Default for DryRunAllocator<M>
+where
+    M: Default,
+{
+    fn default() -> Self {
+        DryRunAllocator::new()
+    }
+}
+
+impl<M> DryRunAllocator<M> {
+    /// Create a new dry run allocator.
+    pub fn new() -> Self
+    where
+        M: Default,
+    {
+        DryRunAllocator {
+            memory: Box::new(M::default()),
+            instance_id: next_instance_id(),
+            offset: 0,
+            reserved: 0,
+            peak_reserved: 0,
+            blocks: BTreeMap::new(),
+            calls: Vec::new(),
+        }
+    }
+
+    /// Get all calls made to this allocator in order.
+    pub fn calls(&self) -> &[OwnerCall] {
+        &self.calls
+    }
+
+    /// Get the number of blocks allocated so far, including freed ones.
+    pub fn allocations(&self) -> usize {
+        self.calls
+            .iter()
+            .filter(|call| match **call {
+                OwnerCall::Alloc { .. } => true,
+                OwnerCall::Free { .. } => false,
+            })
+            .count()
+    }
+
+    /// Get the total size of the blocks currently allocated.
+    pub fn reserved(&self) -> u64 {
+        self.reserved
+    }
+
+    /// Get the highest total size of allocated blocks at any point.
+    pub fn peak_reserved(&self) -> u64 {
+        self.peak_reserved
+    }
+}
+
+impl<B> MemoryAllocator<B> for DryRunAllocator<B::Memory>
+where
+    B: Backend,
+    B::Memory: Default,
+{
+    type Request = ();
+    type Block = RawBlock<B::Memory>;
+
+    fn alloc(
+        &mut self,
+        _device: &B::Device,
+        _request: (),
+        reqs: Requirements,
+    ) -> Result<RawBlock<B::Memory>, MemoryError> {
+        if reqs.type_mask == 0 {
+            return Err(MemoryError::NoCompatibleMemoryType);
+        }
+        let memory_type = MemoryTypeId(reqs.type_mask.trailing_zeros() as usize);
+        let start = self.offset + alignment_shift(reqs.alignment, self.offset);
+        self.offset = start + reqs.size;
+        self.reserved += reqs.size;
+        self.peak_reserved = ::std::cmp::max(self.peak_reserved, self.reserved);
+        self.blocks.insert(start, memory_type);
+        self.calls.push(OwnerCall::Alloc {
+            memory_type,
+            size: reqs.size,
+            alignment: reqs.alignment,
+        });
+        Ok(RawBlock::new(&*self.memory, start..self.offset))
+    }
+
+    fn free(&mut self, _device: &B::Device, block: RawBlock<B::Memory>) {
+        let range = block.range();
+        unsafe { block.dispose() };
+        let memory_type = self.blocks
+            .remove(&range.start)
+            .expect("Block wasn't allocated by this allocator");
+        self.reserved -= range.end - range.start;
+        self.calls.push(OwnerCall::Free {
+            memory_type,
+            size: range.end - range.start,
+        });
+    }
+
+    fn instance_id(&self) -> Option<usize> {
+        Some(self.instance_id)
+    }
+
+    fn is_used(&self) -> bool {
+        !self.blocks.is_empty()
+    }
+
+    fn dispose(self, _device: &B::Device) -> Result<(), Self> {
+        if MemoryAllocator::<B>::is_used(&self) {
+            Err(self)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[test]
+#[allow(dead_code)]
+fn test_send_sync() {
+    fn foo<T: Send + Sync>() {}
+    fn bar<M: Send + Sync>() {
+        foo::<DryRunAllocator<M>>()
+    }
+}
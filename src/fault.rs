@@ -0,0 +1,154 @@
+use gfx_hal::{Backend, MemoryTypeId};
+use gfx_hal::memory::Requirements;
+
+use {MemoryAllocator, MemoryError};
+
+/// Allocations `FaultInjector` fails.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FaultSchedule {
+    /// Never fail.
+    Never,
+    /// Fail every nth allocation, e.g. `EveryNth(3)` fails allocation 2, 5, 8 and so on.
+    EveryNth(usize),
+    /// Fail allocations with the listed indices, counting from zero.
+    Indices(Vec<usize>),
+}
+
+/// Allocator that fails allocations on a schedule, to test handling of out of memory errors.
+///
+/// Allocations are counted from zero, and those matching the schedule return
+/// `MemoryError::OutOfMemory` without reaching the wrapped allocator. All other calls are
+/// forwarded.
+///
+/// ### Type parameters:
+///
+/// - `A`: wrapped allocator
+#[derive(Debug)]
+pub struct FaultInjector<A> {
+    inner: A,
+    schedule: FaultSchedule,
+    allocations: usize,
+}
+
+impl<A> FaultInjector<A> {
+    /// Wrap an allocator.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `inner`: allocator to allocate from
+    /// - `schedule`: allocations to fail
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `schedule` is `EveryNth(0)`.
+    pub fn new(inner: A, schedule: FaultSchedule) -> Self {
+        assert_ne!(schedule, FaultSchedule::EveryNth(0), "Allocations can't fail every 0th");
+        FaultInjector {
+            inner,
+            schedule,
+            allocations: 0,
+        }
+    }
+
+    /// Get the number of allocations attempted since creation or the last `reset`.
+    pub fn allocations(&self) -> usize {
+        self.allocations
+    }
+
+    /// Restart counting allocations from zero with a new schedule.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `schedule` is `EveryNth(0)`.
+    pub fn reset(&mut self, schedule: FaultSchedule) {
+        assert_ne!(schedule, FaultSchedule::EveryNth(0), "Allocations can't fail every 0th");
+        self.schedule = schedule;
+        self.allocations = 0;
+    }
+
+    /// Get the wrapped allocator.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Get the wrapped allocator mutably.
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.inner
+    }
+
+    /// Take the wrapped allocator.
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+
+    /// Count an allocation and check if it must fail.
+    fn fail(&mut self) -> bool {
+        let index = self.allocations;
+        self.allocations += 1;
+        match self.schedule {
+            FaultSchedule::Never => false,
+            FaultSchedule::EveryNth(n) => index % n == n - 1,
+            FaultSchedule::Indices(ref indices) => indices.contains(&index),
+        }
+    }
+}
+
+impl<B, A> MemoryAllocator<B> for FaultInjector<A>
+where
+    B: Backend,
+    A: MemoryAllocator<B>,
+{
+    type Request = A::Request;
+    type Block = A::Block;
+
+    fn alloc(
+        &mut self,
+        device: &B::Device,
+        request: A::Request,
+        reqs: Requirements,
+    ) -> Result<A::Block, MemoryError> {
+        if self.fail() {
+            Err(MemoryError::OutOfMemory)
+        } else {
+            self.inner.alloc(device, request, reqs)
+        }
+    }
+
+    fn free(&mut self, device: &B::Device, block: A::Block) {
+        self.inner.free(device, block)
+    }
+
+    fn block_memory_type(&self, block: &A::Block) -> Option<MemoryTypeId> {
+        self.inner.block_memory_type(block)
+    }
+
+    fn instance_id(&self) -> Option<usize> {
+        self.inner.instance_id()
+    }
+
+    fn is_used(&self) -> bool {
+        self.inner.is_used()
+    }
+
+    fn dispose(self, device: &B::Device) -> Result<(), Self> {
+        let schedule = self.schedule;
+        let allocations = self.allocations;
+        self.inner.dispose(device).map_err(|inner| FaultInjector {
+            inner,
+            schedule,
+            allocations,
+        })
+    }
+}
+
+#[test]
+fn test_schedule() {
+    let mut injector = FaultInjector::new((), FaultSchedule::EveryNth(3));
+    let failed = (0..7).map(|_| injector.fail()).collect::<Vec<_>>();
+    assert_eq!(failed, [false, false, true, false, false, true, false]);
+
+    injector.reset(FaultSchedule::Indices(vec![0, 4]));
+    let failed = (0..5).map(|_| injector.fail()).collect::<Vec<_>>();
+    assert_eq!(failed, [true, false, false, false, true]);
+    assert_eq!(injector.allocations(), 5);
+}
@@ -0,0 +1,299 @@
+use std::any::Any;
+use std::cmp::max;
+use std::fmt::Debug;
+use std::ops::Range;
+
+use gfx_hal::Backend;
+use gfx_hal::memory::Requirements;
+
+use {alignment_shift, MemoryAllocator, MemoryError, MemorySubAllocator};
+use block::Block;
+use chunked::{ChunkedAllocator, ChunkedBlock};
+
+/// Allocator of scratch memory that is only used for a single frame.
+///
+/// Allocations are bumped linearly through blocks taken from a `ChunkedAllocator` of the
+/// requested memory type, from the first block with enough space left, so an allocation that
+/// doesn't fit a block doesn't waste the rest of it. Blocks can't be freed one by one, instead
+/// `end_frame` makes all of the memory available again at once. The blocks taken from the
+/// `ChunkedAllocator`s are kept, so the next frame doesn't allocate at all unless it needs more
+/// memory than the previous ones.
+///
+/// ### Type parameters:
+///
+/// - `T`: type of blocks the `ChunkedAllocator`s sub-allocate from.
+#[derive(Debug)]
+pub struct FrameAllocator<T: Block> {
+    block_size: u64,
+    arenas: Vec<FrameArena<T>>,
+}
+
+impl<T> FrameAllocator<T>
+where
+    T: Block,
+{
+    /// Create a new frame allocator.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `allocators`: allocators to take blocks from, one for each memory type
+    /// - `block_size`: size of the blocks to take from the allocators in bytes, larger
+    ///   allocations take a block of their own size
+    pub fn new(allocators: Vec<ChunkedAllocator<T>>, block_size: u64) -> Self {
+        FrameAllocator {
+            block_size,
+            arenas: allocators
+                .into_iter()
+                .map(|allocator| FrameArena {
+                    allocator,
+                    blocks: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Get the total size of memory allocated during the current frame, including alignment.
+    pub fn used(&self) -> u64 {
+        self.arenas
+            .iter()
+            .flat_map(|arena| arena.blocks.iter())
+            .map(|&(_, offset)| offset)
+            .sum()
+    }
+
+    /// Get the total size of the blocks taken from the allocators.
+    pub fn allocated(&self) -> u64 {
+        self.arenas
+            .iter()
+            .flat_map(|arena| arena.blocks.iter())
+            .map(|(block, _)| block.size())
+            .sum()
+    }
+
+    /// Allocate scratch memory for the current frame.
+    ///
+    /// The first allocator with a memory type allowed by `reqs.type_mask` is used.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator the `ChunkedAllocator`s allocate chunks from
+    /// - `device`: device to allocate the memory from
+    /// - `request`: information required by `owner` to allocate a chunk
+    /// - `reqs`: the requirements the memory must meet
+    ///
+    /// ### Returns
+    ///
+    /// Memory that stays valid until the next call to `end_frame`.
+    pub fn alloc<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        request: A::Request,
+        reqs: Requirements,
+    ) -> Result<FrameBlock<B::Memory>, MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        let block_size = self.block_size;
+        let arena = self.arenas
+            .iter_mut()
+            .find(|arena| (1 << arena.allocator.memory_type().0) & reqs.type_mask != 0)
+            .ok_or(MemoryError::NoCompatibleMemoryType)?;
+        if let Some(block) = arena.bump(reqs) {
+            return Ok(block);
+        }
+
+        let block_reqs = Requirements {
+            type_mask: 1 << arena.allocator.memory_type().0,
+            size: max(block_size, reqs.size),
+            alignment: reqs.alignment,
+        };
+        let block = MemorySubAllocator::<B, A>::alloc(
+            &mut arena.allocator,
+            owner,
+            device,
+            request,
+            block_reqs,
+        )?;
+        arena.blocks.push((block, 0));
+        Ok(arena.bump(reqs).expect("Block is large enough"))
+    }
+
+    /// Make all memory allocated during the frame available for the next one.
+    ///
+    /// All `FrameBlock`s allocated so far must no longer be used.
+    pub fn end_frame(&mut self) {
+        for arena in &mut self.arenas {
+            for &mut (_, ref mut offset) in &mut arena.blocks {
+                *offset = 0;
+            }
+        }
+    }
+
+    /// Return all blocks to the allocators and dispose of them.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator the `ChunkedAllocator`s allocated chunks from
+    /// - `device`: must be the same device all allocations have been made against
+    pub fn dispose<B, A>(self, owner: &mut A, device: &B::Device)
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        for mut arena in self.arenas {
+            for (block, _) in arena.blocks.drain(..) {
+                MemorySubAllocator::<B, A>::free(&mut arena.allocator, owner, device, block);
+            }
+            MemorySubAllocator::<B, A>::dispose(arena.allocator, owner, device)
+                .expect("All blocks are freed");
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FrameArena<T: Block> {
+    allocator: ChunkedAllocator<T>,
+    /// Blocks with the offset of their first free byte relative to their start
+    blocks: Vec<(ChunkedBlock<T::Memory>, u64)>,
+}
+
+impl<T> FrameArena<T>
+where
+    T: Block,
+{
+    /// Bump through the first block the requirements fit in.
+    fn bump(&mut self, reqs: Requirements) -> Option<FrameBlock<T::Memory>> {
+        for &mut (ref block, ref mut offset) in &mut self.blocks {
+            let start = block.range().start + *offset;
+            let start = start + alignment_shift(reqs.alignment, start);
+            if start + reqs.size <= block.range().end {
+                *offset = start + reqs.size - block.range().start;
+                return Some(FrameBlock {
+                    memory: block.memory(),
+                    range: start..start + reqs.size,
+                });
+            }
+        }
+        None
+    }
+}
+
+/// `Block` type returned by `FrameAllocator`.
+///
+/// Unlike other blocks it isn't freed, it becomes invalid after `FrameAllocator::end_frame`.
+#[derive(Debug)]
+pub struct FrameBlock<M> {
+    memory: *const M,
+    range: Range<u64>,
+}
+
+unsafe impl<M> Send for FrameBlock<M> {}
+
+unsafe impl<M> Sync for FrameBlock<M> {}
+
+impl<M> Block for FrameBlock<M>
+where
+    M: Debug + Any,
+{
+    type Memory = M;
+
+    #[inline(always)]
+    fn memory(&self) -> &M {
+        // Has to be valid until the end of the frame
+        unsafe { &*self.memory }
+    }
+
+    #[inline(always)]
+    fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+}
+
+#[test]
+fn test_bump() {
+    use gfx_hal::MemoryTypeId;
+    use block::RawBlock;
+    use chunked::ChunkedTag;
+
+    let memory = 0u32;
+    let mut arena = FrameArena::<RawBlock<u32>> {
+        allocator: ChunkedAllocator::new(MemoryTypeId(0), 8, 256, 1 << 20),
+        blocks: vec![
+            (ChunkedBlock(RawBlock::new(&memory, 256..512), ChunkedTag::default()), 0),
+            (ChunkedBlock(RawBlock::new(&memory, 1024..1280), ChunkedTag::default()), 0),
+        ],
+    };
+    let reqs = |size, alignment| Requirements {
+        type_mask: 1,
+        size,
+        alignment,
+    };
+
+    assert_eq!(arena.bump(reqs(100, 1)).unwrap().range(), 256..356);
+    assert_eq!(arena.bump(reqs(100, 64)).unwrap().range(), 384..484);
+    assert_eq!(arena.bump(reqs(100, 1)).unwrap().range(), 1024..1124);
+    assert!(arena.bump(reqs(200, 1)).is_none());
+    // The rest of the first block is still used
+    assert_eq!(arena.bump(reqs(20, 1)).unwrap().range(), 484..504);
+
+    // Memory is reused after the end of the frame
+    for &mut (_, ref mut offset) in &mut arena.blocks {
+        *offset = 0;
+    }
+    assert_eq!(arena.bump(reqs(100, 1)).unwrap().range(), 256..356);
+
+    for (block, _) in arena.blocks.drain(..) {
+        unsafe { block.0.dispose() };
+    }
+}
+
+#[test]
+fn test_frames() {
+    extern crate gfx_backend_empty as empty;
+
+    use gfx_hal::MemoryTypeId;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let allocators = vec![ChunkedAllocator::new(MemoryTypeId(0), 8, 256, 1 << 16)];
+    let mut allocator = FrameAllocator::new(allocators, 1024);
+    let reqs = |size| Requirements {
+        type_mask: 1,
+        size,
+        alignment: 1,
+    };
+
+    for _ in 0..2 {
+        let sizes = [600, 600, 300, 2000]
+            .iter()
+            .map(|&size| {
+                allocator
+                    .alloc::<empty::Backend, _>(&mut owner, &device, (), reqs(size))
+                    .unwrap()
+                    .size()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, [600, 600, 300, 2000]);
+        // The third allocation fits the rest of the first block, the last one takes its own
+        assert_eq!(allocator.used(), 3500);
+        assert_eq!(allocator.allocated(), 1024 + 1024 + 2048);
+
+        // The blocks are kept for the next frame
+        allocator.end_frame();
+        assert_eq!(allocator.used(), 0);
+        assert_eq!(allocator.allocated(), 4096);
+    }
+    match allocator.alloc::<empty::Backend, _>(&mut owner, &device, (), reqs(1 << 17)) {
+        Err(MemoryError::TooLarge) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+
+    allocator.dispose::<empty::Backend, _>(&mut owner, &device);
+    assert_eq!(owner.reserved(), 0);
+}
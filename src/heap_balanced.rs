@@ -0,0 +1,188 @@
+use gfx_hal::{Backend, MemoryProperties, MemoryTypeId};
+use gfx_hal::memory::{Properties, Requirements};
+
+use {MemoryAllocator, MemoryError, MemorySubAllocator};
+use block::Block;
+use chunked::ChunkedAllocator;
+use multi::{MultiTypeBlock, TypeAllocators};
+
+/// Sub-allocator that spreads allocations over all compatible memory types.
+///
+/// Of the memory types allowed by the requirements that have all requested properties, the one
+/// whose `ChunkedAllocator` currently holds the least memory is chosen. Unlike
+/// `MultiTypeAllocator`, which always prefers the first compatible type, this balances the load
+/// on memory types backed by the same heap. All per-type allocators share the same underlying
+/// allocator, which must be able to allocate any memory type.
+///
+/// ### Type parameters:
+///
+/// - `T`: type of bigger blocks this allocator sub-allocates from.
+#[derive(Debug)]
+pub struct HeapBalancedAllocator<T> {
+    allocators: TypeAllocators<T>,
+}
+
+impl<T> HeapBalancedAllocator<T> {
+    /// Create a new heap balanced allocator from `MemoryProperties` given by a device.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `memory_properties`: memory properties describing the memory available on a device
+    /// - `blocks_per_chunk`: see `ChunkedAllocator`
+    /// - `min_block_size`: see `ChunkedAllocator`
+    /// - `max_chunk_size`: see `ChunkedAllocator`
+    pub fn new(
+        memory_properties: MemoryProperties,
+        blocks_per_chunk: usize,
+        min_block_size: u64,
+        max_chunk_size: u64,
+    ) -> Self {
+        HeapBalancedAllocator {
+            allocators: TypeAllocators::new(
+                memory_properties,
+                blocks_per_chunk,
+                min_block_size,
+                max_chunk_size,
+            ),
+        }
+    }
+
+    /// Get the allocator of the specified memory type.
+    pub fn allocator(&self, id: MemoryTypeId) -> &ChunkedAllocator<T> {
+        self.allocators.allocator(id.0)
+    }
+
+    /// Check if any of the blocks allocated by this allocator are still in use.
+    /// If this function returns `false`, the allocator can be `dispose`d.
+    pub fn is_used(&self) -> bool {
+        self.allocators.is_used()
+    }
+
+    /// Get the total size of all blocks allocated by this allocator.
+    pub fn used(&self) -> u64 {
+        self.allocators.used()
+    }
+
+    /// Get the total size of all chunks allocated by this allocator.
+    pub fn allocated(&self) -> u64 {
+        self.allocators.allocated()
+    }
+
+    /// Pick the compatible memory type holding the least memory.
+    fn select(&self, type_mask: u64, properties: Properties) -> Option<usize>
+    where
+        T: Block,
+    {
+        (0..self.allocators.len())
+            .filter(|&index| self.allocators.compatible(index, type_mask, properties))
+            .min_by_key(|&index| self.allocators.allocator(index).owner_bytes_held())
+    }
+}
+
+impl<B, O, T> MemorySubAllocator<B, O> for HeapBalancedAllocator<T>
+where
+    B: Backend,
+    T: Block<Memory = B::Memory>,
+    O: MemoryAllocator<B, Block = T>,
+{
+    type Request = (Properties, O::Request);
+    type Block = MultiTypeBlock<B::Memory>;
+
+    fn alloc(
+        &mut self,
+        owner: &mut O,
+        device: &B::Device,
+        (prop, request): (Properties, O::Request),
+        reqs: Requirements,
+    ) -> Result<MultiTypeBlock<B::Memory>, MemoryError> {
+        let index = self.select(reqs.type_mask, prop)
+            .ok_or(MemoryError::NoCompatibleMemoryType)?;
+        self.allocators.alloc(index, owner, device, request, reqs)
+    }
+
+    fn free(&mut self, owner: &mut O, device: &B::Device, block: MultiTypeBlock<B::Memory>) {
+        self.allocators.free(owner, device, block);
+    }
+
+    fn dispose(self, owner: &mut O, device: &B::Device) -> Result<(), Self> {
+        if self.is_used() {
+            Err(self)
+        } else {
+            self.allocators.dispose(owner, device);
+            Ok(())
+        }
+    }
+}
+
+#[test]
+#[allow(dead_code)]
+fn test_send_sync() {
+    fn foo<T: Send + Sync>() {}
+    fn bar<M: Send + Sync>() {
+        foo::<HeapBalancedAllocator<M>>()
+    }
+}
+
+#[test]
+fn test_balance() {
+    extern crate gfx_backend_empty as empty;
+
+    use gfx_hal::MemoryType;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let memory_type = |properties| MemoryType {
+        properties,
+        heap_index: 0,
+    };
+    let memory_properties = MemoryProperties {
+        memory_types: vec![
+            memory_type(Properties::DEVICE_LOCAL),
+            memory_type(Properties::DEVICE_LOCAL),
+            memory_type(Properties::COHERENT),
+        ],
+        memory_heaps: vec![1 << 30],
+    };
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = HeapBalancedAllocator::new(memory_properties, 1, 256, 1 << 16);
+    let mut alloc = |type_mask, properties| {
+        let reqs = Requirements {
+            type_mask,
+            size: 256,
+            alignment: 1,
+        };
+        MemorySubAllocator::<empty::Backend, _>::alloc(
+            &mut allocator,
+            &mut owner,
+            &device,
+            (properties, ()),
+            reqs,
+        )
+    };
+
+    // Each block takes a chunk of its own, so the device local types take turns
+    let mut blocks = (0..4)
+        .map(|_| alloc(!0, Properties::DEVICE_LOCAL).unwrap())
+        .collect::<Vec<_>>();
+    blocks.push(alloc(0b010, Properties::DEVICE_LOCAL).unwrap());
+    blocks.push(alloc(!0, Properties::COHERENT).unwrap());
+    match alloc(0b001, Properties::COHERENT) {
+        Err(MemoryError::NoCompatibleMemoryType) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+    let types = blocks
+        .iter()
+        .map(|block| block.memory_type().0)
+        .collect::<Vec<_>>();
+    assert_eq!(types, vec![0, 1, 0, 1, 1, 2]);
+    // The next block goes to the type holding less memory
+    blocks.push(alloc(!0, Properties::DEVICE_LOCAL).unwrap());
+    assert_eq!(blocks[6].memory_type(), MemoryTypeId(0));
+
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    assert!(!allocator.is_used());
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
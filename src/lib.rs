@@ -38,33 +38,90 @@
 #![deny(unused_imports)]
 #![deny(unused_must_use)]
 
+#[cfg(feature = "debug-backtrace")]
+extern crate backtrace;
 extern crate gfx_hal;
+#[cfg(feature = "metrics")]
+#[macro_use]
+extern crate metrics;
 extern crate relevant;
 
 pub use arena::{ArenaAllocator, ArenaBlock};
 pub use block::{Block, RawBlock};
-pub use chunked::{ChunkedAllocator, ChunkedBlock};
+pub use bump::BumpChunk;
+pub use cap::CapAllocator;
+pub use chunked::{recommended_min_block_size, round_to_power_of_two, AlignmentError, BlockHandle,
+                  ChunkedAllocator, ChunkedAllocatorBuilder, ChunkedBlock, ChunkedLayout,
+                  ChunkedSample, ChunkedView, ChurnStats, ClassUtil, ConfigError, DetachedBlock,
+                  FreeList, GrowEvent, HeapWarning, InvariantViolation, NodeSelector, NodeSpec,
+                  PowerOfTwoSelector, ReallocResult, Relocation, RoundingSelector, ScopeHandle,
+                  ScopeStats, SingleBlockChunked, SizeClassView};
+#[cfg(feature = "testing")]
+pub use chunked::ChunkedState;
 pub use combined::{CombinedAllocator, CombinedBlock, Type};
+pub use dry_run::{DryRunAllocator, OwnerCall};
 pub use factory::{Factory, FactoryError, Item};
+#[cfg(feature = "testing")]
+pub use fault::{FaultInjector, FaultSchedule};
+pub use frame::{FrameAllocator, FrameBlock};
+pub use heap_balanced::HeapBalancedAllocator;
+pub use lifetime::{Lifetime, LifetimeAllocator, LifetimeBlock};
+pub use mixed::MixedSizeNode;
+pub use multi::{select_memory_type, MultiTypeAllocator, MultiTypeBlock};
+pub use multi_buffered::{FrameBlocks, MultiBufferedAllocator};
+pub use owned::OwnedChunkedAllocator;
+pub use packed::{PackedAllocator, PackedBlock, SearchStats};
+pub use quota::QuotaAllocator;
+pub use retry::RetryAllocator;
 pub use root::RootAllocator;
+pub use single_thread::SingleThreadGuard;
 pub use smart::{SmartAllocator, SmartBlock};
+pub use sub_buffer::SubBufferAllocator;
+pub use tiered::TieredAllocator;
+pub use upload::{Upload, UploadAllocator};
 
 use std::cmp::PartialOrd;
 use std::error::Error;
 use std::fmt::{self, Debug};
 use std::ops::{Add, BitOr, Sub};
 
-use gfx_hal::Backend;
+use gfx_hal::{Backend, MemoryTypeId};
 use gfx_hal::device::OutOfMemory;
+use gfx_hal::mapping;
 use gfx_hal::memory::Requirements;
 
 mod arena;
 mod block;
+mod bump;
+mod cap;
 mod chunked;
 mod combined;
+mod dry_run;
 mod factory;
+#[cfg(feature = "testing")]
+mod fault;
+mod frame;
+mod heap_balanced;
+mod lifetime;
+mod mixed;
+mod multi;
+mod multi_buffered;
+mod owned;
+mod packed;
+mod quota;
+#[cfg(feature = "registry")]
+pub mod registry;
+mod retry;
 mod root;
+mod single_thread;
 mod smart;
+mod sub_buffer;
+mod tiered;
+mod upload;
+
+/// Number of memory types representable in `Requirements::type_mask`.
+/// Allocators panic if created with a `MemoryTypeId` that is not less than this.
+pub const MAX_MEMORY_TYPES: usize = 64;
 
 /// Possible errors that may be returned from allocators.
 #[derive(Debug, Clone)]
@@ -74,6 +131,31 @@ pub enum MemoryError {
 
     /// All compatible memory is exhausted.
     OutOfMemory,
+
+    /// Freed block doesn't cover exactly the memory it was allocated with.
+    PartialFree,
+
+    /// Chunk can't be freed because some of its blocks are in use.
+    ChunkInUse,
+
+    /// Block doesn't belong to this allocator.
+    InvalidBlock,
+
+    /// Requested block is already allocated.
+    BlockInUse,
+
+    /// Memory can't be mapped.
+    MappingFailed,
+
+    /// Requested size or alignment is larger than the allocator supports.
+    TooLarge,
+
+    /// Allocator reached its limit of allocations and frees within a frame.
+    ChurnLimit,
+
+    /// Tag of the freed block doesn't match the chunk containing it.
+    /// Only detected with the `paranoid` feature.
+    CorruptTag,
 }
 
 impl From<OutOfMemory> for MemoryError {
@@ -82,6 +164,15 @@ impl From<OutOfMemory> for MemoryError {
     }
 }
 
+impl From<mapping::Error> for MemoryError {
+    fn from(error: mapping::Error) -> Self {
+        match error {
+            mapping::Error::OutOfMemory => MemoryError::OutOfMemory,
+            _ => MemoryError::MappingFailed,
+        }
+    }
+}
+
 impl fmt::Display for MemoryError {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.write_str(self.description())
@@ -93,6 +184,14 @@ impl Error for MemoryError {
         match *self {
             MemoryError::NoCompatibleMemoryType => "No compatible memory",
             MemoryError::OutOfMemory => "Out of memory",
+            MemoryError::PartialFree => "Partial free of allocated block",
+            MemoryError::ChunkInUse => "Chunk has used blocks",
+            MemoryError::InvalidBlock => "Block wasn't allocated by this allocator",
+            MemoryError::BlockInUse => "Block is already allocated",
+            MemoryError::MappingFailed => "Failed to map memory",
+            MemoryError::TooLarge => "Requested block is too large",
+            MemoryError::ChurnLimit => "Too many allocations and frees in this frame",
+            MemoryError::CorruptTag => "Block tag doesn't match the chunk containing it",
         }
     }
 }
@@ -114,7 +213,7 @@ pub trait MemoryAllocator<B: Backend>: Debug {
     /// ### Parameters:
     ///
     /// - `device`: device to allocate the memory from, must always be the same for an instance
-    ///             of the allocator
+    ///   of the allocator
     /// - `info`: information required to allocate a block of memory
     /// - `req`: the requirements the memory block must meet
     ///
@@ -139,6 +238,23 @@ pub trait MemoryAllocator<B: Backend>: Debug {
     /// - `block`: block of memory to free
     fn free(&mut self, device: &B::Device, block: Self::Block);
 
+    /// Get the memory type of a block allocated by this allocator.
+    ///
+    /// Sub-allocators use this to verify they received memory of the type they requested.
+    /// Allocators that can't tell return `None`, in which case they are trusted to honor
+    /// `Requirements::type_mask`.
+    fn block_memory_type(&self, _block: &Self::Block) -> Option<MemoryTypeId> {
+        None
+    }
+
+    /// Get an ID unique to this instance of the allocator.
+    ///
+    /// Sub-allocators use this to check they are always given the same owner, e.g. when disposed.
+    /// Allocators that return `None` are not checked.
+    fn instance_id(&self) -> Option<usize> {
+        None
+    }
+
     /// Check if any of the blocks allocated by this allocator are still in use.
     /// If this function returns `false`, the allocator can be `dispose`d.
     fn is_used(&self) -> bool;
@@ -174,11 +290,11 @@ pub trait MemorySubAllocator<B: Backend, O> {
     /// ### Parameters:
     ///
     /// - `owner`: allocator used to allocate memory in bigger chunks, must always be the same
-    ///            for an instance of this sub allocator
+    ///   for an instance of this sub allocator
     /// - `device`: device to allocate the memory from, must always be the same for an instance
-    ///             of the allocator
+    ///   of the allocator
     /// - `info`: information required to allocate a block of memory, may contain additional
-    ///           requirements and/or hints for allocation.
+    ///   requirements and/or hints for allocation.
     /// - `reqs`: the requirements the memory block must meet
     ///
     /// ### Returns
@@ -223,6 +339,13 @@ pub trait MemorySubAllocator<B: Backend, O> {
         Self: Sized;
 }
 
+/// Get a new ID for `MemoryAllocator::instance_id`.
+pub(crate) fn next_instance_id() -> usize {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
 /// Calculate shift from specified offset required to satisfy alignment.
 pub fn alignment_shift<T>(alignment: T, offset: T) -> T
 where
@@ -0,0 +1,173 @@
+use std::any::Any;
+use std::fmt::Debug;
+use std::ops::Range;
+
+use gfx_hal::{Backend, MemoryTypeId};
+use gfx_hal::memory::Requirements;
+
+use {MemoryAllocator, MemoryError, MemorySubAllocator};
+use block::Block;
+use chunked::{ChunkedAllocator, ChunkedBlock};
+
+/// Expected lifetime of an allocation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Lifetime {
+    /// Memory used for a single frame or transfer, e.g. staging buffers.
+    Transient,
+
+    /// Memory used for a few frames.
+    ShortLived,
+
+    /// Memory used for a long time, e.g. textures and meshes of a level.
+    Persistent,
+}
+
+impl Lifetime {
+    fn index(&self) -> usize {
+        match *self {
+            Lifetime::Transient => 0,
+            Lifetime::ShortLived => 1,
+            Lifetime::Persistent => 2,
+        }
+    }
+}
+
+/// Sub-allocator that keeps allocations of different expected lifetimes in separate chunks.
+///
+/// Each `Lifetime` is served by its own `ChunkedAllocator`, so short-lived blocks don't pin
+/// chunks shared with long-lived ones. Chunks emptied by transient allocations can then be
+/// released wholesale with `shrink`.
+///
+/// ### Type parameters:
+///
+/// - `T`: type of bigger blocks this allocator sub-allocates from.
+#[derive(Debug)]
+pub struct LifetimeAllocator<T> {
+    allocators: Vec<ChunkedAllocator<T>>,
+}
+
+impl<T> LifetimeAllocator<T> {
+    /// Create a new lifetime allocator.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `id`: ID of the memory type this allocator allocates from.
+    /// - `blocks_per_chunk`: see `ChunkedAllocator`
+    /// - `min_block_size`: see `ChunkedAllocator`
+    /// - `max_chunk_size`: see `ChunkedAllocator`
+    pub fn new(
+        id: MemoryTypeId,
+        blocks_per_chunk: usize,
+        min_block_size: u64,
+        max_chunk_size: u64,
+    ) -> Self {
+        LifetimeAllocator {
+            allocators: (0..3)
+                .map(|_| {
+                    ChunkedAllocator::new(id, blocks_per_chunk, min_block_size, max_chunk_size)
+                })
+                .collect(),
+        }
+    }
+
+    /// Get the allocator serving the specified lifetime.
+    pub fn allocator(&self, lifetime: Lifetime) -> &ChunkedAllocator<T> {
+        &self.allocators[lifetime.index()]
+    }
+
+    /// Get the allocator serving the specified lifetime mutably, e.g. to `shrink` it.
+    pub fn allocator_mut(&mut self, lifetime: Lifetime) -> &mut ChunkedAllocator<T> {
+        &mut self.allocators[lifetime.index()]
+    }
+
+    /// Check if any of the blocks allocated by this allocator are still in use.
+    /// If this function returns `false`, the allocator can be `dispose`d.
+    pub fn is_used(&self) -> bool {
+        self.allocators.iter().any(ChunkedAllocator::is_used)
+    }
+
+    /// Get the total size of all blocks allocated by this allocator.
+    pub fn used(&self) -> u64 {
+        self.allocators.iter().map(ChunkedAllocator::used).sum()
+    }
+
+    /// Get the total size of all chunks allocated by this allocator.
+    pub fn allocated(&self) -> u64 {
+        self.allocators.iter().map(ChunkedAllocator::allocated).sum()
+    }
+}
+
+impl<B, O, T> MemorySubAllocator<B, O> for LifetimeAllocator<T>
+where
+    B: Backend,
+    T: Block<Memory = B::Memory>,
+    O: MemoryAllocator<B, Block = T>,
+{
+    type Request = (Lifetime, O::Request);
+    type Block = LifetimeBlock<B::Memory>;
+
+    fn alloc(
+        &mut self,
+        owner: &mut O,
+        device: &B::Device,
+        (lifetime, request): (Lifetime, O::Request),
+        reqs: Requirements,
+    ) -> Result<LifetimeBlock<B::Memory>, MemoryError> {
+        self.allocators[lifetime.index()]
+            .alloc(owner, device, request, reqs)
+            .map(|block| LifetimeBlock(block, lifetime))
+    }
+
+    fn free(&mut self, owner: &mut O, device: &B::Device, block: LifetimeBlock<B::Memory>) {
+        let LifetimeBlock(block, lifetime) = block;
+        self.allocators[lifetime.index()].free(owner, device, block);
+    }
+
+    fn dispose(mut self, owner: &mut O, device: &B::Device) -> Result<(), Self> {
+        if self.is_used() {
+            Err(self)
+        } else {
+            for allocator in self.allocators.drain(..) {
+                allocator.dispose(owner, device).unwrap();
+            }
+            Ok(())
+        }
+    }
+}
+
+/// `Block` type returned by `LifetimeAllocator`.
+#[derive(Debug)]
+pub struct LifetimeBlock<M>(pub(crate) ChunkedBlock<M>, pub(crate) Lifetime);
+
+impl<M> LifetimeBlock<M> {
+    /// Get lifetime the block was allocated with.
+    pub fn lifetime(&self) -> Lifetime {
+        self.1
+    }
+}
+
+impl<M> Block for LifetimeBlock<M>
+where
+    M: Debug + Any,
+{
+    type Memory = M;
+
+    #[inline(always)]
+    fn memory(&self) -> &M {
+        self.0.memory()
+    }
+
+    #[inline(always)]
+    fn range(&self) -> Range<u64> {
+        self.0.range()
+    }
+}
+
+#[test]
+#[allow(dead_code)]
+fn test_send_sync() {
+    fn foo<T: Send + Sync>() {}
+    fn bar<M: Send + Sync>() {
+        foo::<LifetimeAllocator<M>>()
+    }
+}
@@ -0,0 +1,195 @@
+use std::cmp::max;
+
+use gfx_hal::{Backend, MemoryTypeId};
+use gfx_hal::memory::Requirements;
+
+use {MemoryAllocator, MemoryError, MemorySubAllocator};
+use block::Block;
+use packed::{PackedAllocator, PackedBlock, SearchStats};
+
+/// Sub-allocator that places blocks of all size classes into shared chunks.
+///
+/// Sizes are rounded up to a power of two of at least `min_block_size` and blocks are aligned to
+/// their size, same as with `ChunkedAllocator`. But instead of a separate list of chunks per size
+/// class, blocks of every size are placed into the first hole of a chunk that fits them. Related
+/// allocations of varying size, e.g. a large tile followed by its smaller detail buffers, thus
+/// end up next to each other in one chunk.
+///
+/// This trades simplicity for locality: allocating searches the holes of the chunks instead of
+/// taking the next free block of a size class, and holes left by freed blocks can only be reused
+/// by blocks that fit into them. Prefer `ChunkedAllocator` unless the locality matters.
+///
+/// ### Type parameters:
+///
+/// - `T`: type of blocks this allocator sub-allocates from.
+#[derive(Debug)]
+pub struct MixedSizeNode<T> {
+    min_block_size: u64,
+    chunks: PackedAllocator<T>,
+}
+
+impl<T> MixedSizeNode<T> {
+    /// Create a new mixed size allocator.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `id`: ID of the memory type this allocator allocates from.
+    /// - `min_block_size`: The minimum block size used by this allocator in bytes.
+    /// - `chunk_size`: The size of chunks allocated from the underlying allocator in bytes.
+    ///   Requests for larger blocks fail with `MemoryError::TooLarge`.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `id` is not less than `MAX_MEMORY_TYPES`, `min_block_size` or `chunk_size` are
+    /// not a power of two, or if `min_block_size` is greater than `chunk_size`.
+    pub fn new(id: MemoryTypeId, min_block_size: u64, chunk_size: u64) -> Self {
+        assert!(min_block_size.is_power_of_two());
+        assert!(chunk_size.is_power_of_two());
+        assert!(min_block_size <= chunk_size);
+        MixedSizeNode {
+            min_block_size,
+            chunks: PackedAllocator::new(id, chunk_size),
+        }
+    }
+
+    /// Get the size of the block allocated for a request of `size` bytes.
+    pub fn block_size_for(&self, size: u64) -> u64 {
+        max(size, self.min_block_size).next_power_of_two()
+    }
+
+    /// Check if any of the blocks allocated by this allocator are still in use.
+    /// If this function returns `false`, the allocator can be `dispose`d.
+    pub fn is_used(&self) -> bool {
+        self.chunks.is_used()
+    }
+
+    /// Get memory type of the allocator
+    pub fn memory_type(&self) -> MemoryTypeId {
+        self.chunks.memory_type()
+    }
+
+    /// Get the minimum block size in bytes
+    pub fn min_block_size(&self) -> u64 {
+        self.min_block_size
+    }
+
+    /// Get the size of chunks in bytes
+    pub fn chunk_size(&self) -> u64 {
+        self.chunks.chunk_size()
+    }
+
+    /// Retrieves the block backing an allocation.
+    pub fn underlying_block<M>(&self, block: &PackedBlock<M>) -> &T {
+        self.chunks.underlying_block(block)
+    }
+
+    /// Get statistics of the search for holes fitting allocated blocks.
+    pub fn search_stats(&self) -> SearchStats {
+        self.chunks.search_stats()
+    }
+
+    /// Get the total size of all blocks allocated by this allocator.
+    pub fn used(&self) -> u64 {
+        self.chunks.used()
+    }
+
+    /// Get the total size of all chunks allocated by this allocator.
+    pub fn allocated(&self) -> u64
+    where
+        T: Block,
+    {
+        self.chunks.allocated()
+    }
+}
+
+impl<B, O, T> MemorySubAllocator<B, O> for MixedSizeNode<T>
+where
+    B: Backend,
+    T: Block<Memory = B::Memory>,
+    O: MemoryAllocator<B, Block = T>,
+{
+    type Request = O::Request;
+    type Block = PackedBlock<B::Memory>;
+
+    fn alloc(
+        &mut self,
+        owner: &mut O,
+        device: &B::Device,
+        request: O::Request,
+        reqs: Requirements,
+    ) -> Result<PackedBlock<B::Memory>, MemoryError> {
+        let block_size = self.block_size_for(max(reqs.size, reqs.alignment));
+        if block_size > self.chunk_size() {
+            return Err(MemoryError::TooLarge);
+        }
+        let reqs = Requirements {
+            type_mask: reqs.type_mask,
+            size: block_size,
+            alignment: block_size,
+        };
+        MemorySubAllocator::<B, O>::alloc(&mut self.chunks, owner, device, request, reqs)
+    }
+
+    fn free(&mut self, owner: &mut O, device: &B::Device, block: PackedBlock<B::Memory>) {
+        MemorySubAllocator::<B, O>::free(&mut self.chunks, owner, device, block)
+    }
+
+    fn dispose(self, owner: &mut O, device: &B::Device) -> Result<(), Self> {
+        let min_block_size = self.min_block_size;
+        MemorySubAllocator::<B, O>::dispose(self.chunks, owner, device).map_err(|chunks| {
+            MixedSizeNode {
+                min_block_size,
+                chunks,
+            }
+        })
+    }
+}
+
+#[test]
+fn test_mixed_sizes() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = MixedSizeNode::new(MemoryTypeId(0), 64, 4096);
+    let reqs = |size| Requirements {
+        type_mask: 1,
+        size,
+        alignment: 1,
+    };
+
+    let blocks = [1024, 200, 100, 1024]
+        .iter()
+        .map(|&size| {
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut allocator,
+                &mut owner,
+                &device,
+                (),
+                reqs(size),
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let ranges = blocks.iter().map(Block::range).collect::<Vec<_>>();
+    assert_eq!(ranges, vec![0..1024, 1024..1280, 1280..1408, 2048..3072]);
+    assert_eq!(owner.allocations(), 1);
+    match MemorySubAllocator::<empty::Backend, _>::alloc(
+        &mut allocator,
+        &mut owner,
+        &device,
+        (),
+        reqs(4097),
+    ) {
+        Err(MemoryError::TooLarge) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+    assert_eq!(owner.reserved(), 0);
+}
@@ -0,0 +1,381 @@
+use std::any::Any;
+use std::fmt::Debug;
+use std::ops::Range;
+
+use gfx_hal::{Backend, MemoryProperties, MemoryType, MemoryTypeId};
+use gfx_hal::memory::{Properties, Requirements};
+
+use {MemoryAllocator, MemoryError, MemorySubAllocator, MAX_MEMORY_TYPES};
+use block::Block;
+use chunked::{ChunkedAllocator, ChunkedBlock};
+
+/// Sub-allocator that picks memory type for each allocation and sub-allocates it with the
+/// `ChunkedAllocator` of that type.
+///
+/// The first memory type that is allowed by the requirements and has all requested properties
+/// is chosen. All per-type allocators share the same underlying allocator, which must be able to
+/// allocate any memory type.
+///
+/// ### Type parameters:
+///
+/// - `T`: type of bigger blocks this allocator sub-allocates from.
+#[derive(Debug)]
+pub struct MultiTypeAllocator<T> {
+    allocators: TypeAllocators<T>,
+}
+
+impl<T> MultiTypeAllocator<T> {
+    /// Create a new multi-type allocator from `MemoryProperties` given by a device.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `memory_properties`: memory properties describing the memory available on a device
+    /// - `blocks_per_chunk`: see `ChunkedAllocator`
+    /// - `min_block_size`: see `ChunkedAllocator`
+    /// - `max_chunk_size`: see `ChunkedAllocator`
+    pub fn new(
+        memory_properties: MemoryProperties,
+        blocks_per_chunk: usize,
+        min_block_size: u64,
+        max_chunk_size: u64,
+    ) -> Self {
+        MultiTypeAllocator {
+            allocators: TypeAllocators::new(
+                memory_properties,
+                blocks_per_chunk,
+                min_block_size,
+                max_chunk_size,
+            ),
+        }
+    }
+
+    /// Get the allocator of the specified memory type.
+    pub fn allocator(&self, id: MemoryTypeId) -> &ChunkedAllocator<T> {
+        self.allocators.allocator(id.0)
+    }
+
+    /// Get properties of the block
+    pub fn properties<M>(&self, block: &MultiTypeBlock<M>) -> Properties {
+        self.allocators.memory_type(block.1).properties
+    }
+
+    /// Check if any of the blocks allocated by this allocator are still in use.
+    /// If this function returns `false`, the allocator can be `dispose`d.
+    pub fn is_used(&self) -> bool {
+        self.allocators.is_used()
+    }
+
+    /// Get the total size of all blocks allocated by this allocator.
+    pub fn used(&self) -> u64 {
+        self.allocators.used()
+    }
+
+    /// Get the total size of all chunks allocated by this allocator.
+    pub fn allocated(&self) -> u64 {
+        self.allocators.allocated()
+    }
+}
+
+impl<B, O, T> MemorySubAllocator<B, O> for MultiTypeAllocator<T>
+where
+    B: Backend,
+    T: Block<Memory = B::Memory>,
+    O: MemoryAllocator<B, Block = T>,
+{
+    type Request = (Properties, O::Request);
+    type Block = MultiTypeBlock<B::Memory>;
+
+    fn alloc(
+        &mut self,
+        owner: &mut O,
+        device: &B::Device,
+        (prop, request): (Properties, O::Request),
+        reqs: Requirements,
+    ) -> Result<MultiTypeBlock<B::Memory>, MemoryError> {
+        // Find first compatible memory type
+        let index = select(
+            self.allocators.memory_types(),
+            reqs.type_mask,
+            prop,
+            Properties::empty(),
+        ).ok_or(MemoryError::NoCompatibleMemoryType)?
+            .0;
+
+        self.allocators.alloc(index, owner, device, request, reqs)
+    }
+
+    fn free(&mut self, owner: &mut O, device: &B::Device, block: MultiTypeBlock<B::Memory>) {
+        self.allocators.free(owner, device, block);
+    }
+
+    fn dispose(self, owner: &mut O, device: &B::Device) -> Result<(), Self> {
+        if self.is_used() {
+            Err(self)
+        } else {
+            self.allocators.dispose(owner, device);
+            Ok(())
+        }
+    }
+}
+
+/// `ChunkedAllocator`s of all memory types of a device, for allocators that pick a memory type
+/// for each allocation.
+#[derive(Debug)]
+pub(crate) struct TypeAllocators<T> {
+    allocators: Vec<(MemoryType, ChunkedAllocator<T>)>,
+}
+
+impl<T> TypeAllocators<T> {
+    /// Create an allocator for each memory type in `memory_properties`, see `ChunkedAllocator`
+    /// for the other parameters.
+    pub(crate) fn new(
+        memory_properties: MemoryProperties,
+        blocks_per_chunk: usize,
+        min_block_size: u64,
+        max_chunk_size: u64,
+    ) -> Self {
+        TypeAllocators {
+            allocators: memory_properties
+                .memory_types
+                .into_iter()
+                .enumerate()
+                .map(|(index, memory_type)| {
+                    (
+                        memory_type,
+                        ChunkedAllocator::new(
+                            MemoryTypeId(index),
+                            blocks_per_chunk,
+                            min_block_size,
+                            max_chunk_size,
+                        ),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Get the number of memory types.
+    pub(crate) fn len(&self) -> usize {
+        self.allocators.len()
+    }
+
+    /// Get the memory type at `index`.
+    pub(crate) fn memory_type(&self, index: usize) -> MemoryType {
+        self.allocators[index].0
+    }
+
+    /// Iterate over all memory types in order.
+    pub(crate) fn memory_types<'a>(&'a self) -> impl Iterator<Item = MemoryType> + Clone + 'a {
+        self.allocators.iter().map(|&(memory_type, _)| memory_type)
+    }
+
+    /// Get the allocator of the memory type at `index`.
+    pub(crate) fn allocator(&self, index: usize) -> &ChunkedAllocator<T> {
+        &self.allocators[index].1
+    }
+
+    /// Check if the memory type at `index` is allowed by `type_mask` and has all `properties`.
+    pub(crate) fn compatible(&self, index: usize, type_mask: u64, properties: Properties) -> bool {
+        index < MAX_MEMORY_TYPES
+            && ((1 << index) & type_mask) != 0
+            && self.allocators[index].0.properties.contains(properties)
+    }
+
+    pub(crate) fn is_used(&self) -> bool {
+        self.allocators
+            .iter()
+            .any(|(_, allocator)| allocator.is_used())
+    }
+
+    pub(crate) fn used(&self) -> u64 {
+        self.allocators.iter().map(|alloc| alloc.1.used()).sum()
+    }
+
+    pub(crate) fn allocated(&self) -> u64 {
+        self.allocators.iter().map(|alloc| alloc.1.allocated()).sum()
+    }
+
+    /// Allocate a block from the allocator of the memory type at `index`.
+    pub(crate) fn alloc<B, O>(
+        &mut self,
+        index: usize,
+        owner: &mut O,
+        device: &B::Device,
+        request: O::Request,
+        reqs: Requirements,
+    ) -> Result<MultiTypeBlock<B::Memory>, MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        O: MemoryAllocator<B, Block = T>,
+    {
+        self.allocators[index]
+            .1
+            .alloc(owner, device, request, reqs)
+            .map(|block| MultiTypeBlock(block, index))
+    }
+
+    /// Free a block to the allocator of its memory type.
+    pub(crate) fn free<B, O>(
+        &mut self,
+        owner: &mut O,
+        device: &B::Device,
+        block: MultiTypeBlock<B::Memory>,
+    ) where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        O: MemoryAllocator<B, Block = T>,
+    {
+        let MultiTypeBlock(block, index) = block;
+        self.allocators[index].1.free(owner, device, block);
+    }
+
+    /// Dispose of all allocators, none of which must be used.
+    pub(crate) fn dispose<B, O>(self, owner: &mut O, device: &B::Device)
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        O: MemoryAllocator<B, Block = T>,
+    {
+        for (_, allocator) in self.allocators {
+            allocator.dispose(owner, device).unwrap();
+        }
+    }
+}
+
+/// Select memory type allowed by `type_mask` that has all `required` properties.
+///
+/// The first memory type that also has all `preferred` properties is chosen. If there is no
+/// such memory type, the first one that has the `required` properties is chosen instead.
+///
+/// ### Parameters:
+///
+/// - `memory_properties`: memory properties describing the memory available on a device
+/// - `type_mask`: bit mask of allowed memory types, as in `Requirements::type_mask`
+/// - `required`: properties the memory type must have
+/// - `preferred`: properties the memory type should have
+pub fn select_memory_type(
+    memory_properties: &MemoryProperties,
+    type_mask: u64,
+    required: Properties,
+    preferred: Properties,
+) -> Option<MemoryTypeId> {
+    select(
+        memory_properties.memory_types.iter().cloned(),
+        type_mask,
+        required,
+        preferred,
+    )
+}
+
+fn select<I>(
+    memory_types: I,
+    type_mask: u64,
+    required: Properties,
+    preferred: Properties,
+) -> Option<MemoryTypeId>
+where
+    I: Iterator<Item = MemoryType> + Clone,
+{
+    let find = |properties: Properties| {
+        memory_types
+            .clone()
+            .enumerate()
+            .position(|(index, memory_type)| {
+                index < MAX_MEMORY_TYPES
+                    && ((1 << index) & type_mask) != 0
+                    && memory_type.properties.contains(properties)
+            })
+            .map(MemoryTypeId)
+    };
+    find(required | preferred).or_else(|| find(required))
+}
+
+/// `Block` type returned by `MultiTypeAllocator`.
+#[derive(Debug)]
+pub struct MultiTypeBlock<M>(pub(crate) ChunkedBlock<M>, pub(crate) usize);
+
+impl<M> MultiTypeBlock<M> {
+    /// Get memory type chosen for the block.
+    pub fn memory_type(&self) -> MemoryTypeId {
+        MemoryTypeId(self.1)
+    }
+}
+
+impl<M> Block for MultiTypeBlock<M>
+where
+    M: Debug + Any,
+{
+    type Memory = M;
+
+    #[inline(always)]
+    fn memory(&self) -> &M {
+        self.0.memory()
+    }
+
+    #[inline(always)]
+    fn range(&self) -> Range<u64> {
+        self.0.range()
+    }
+}
+
+#[test]
+#[allow(dead_code)]
+fn test_send_sync() {
+    fn foo<T: Send + Sync>() {}
+    fn bar<M: Send + Sync>() {
+        foo::<MultiTypeAllocator<M>>()
+    }
+}
+
+#[test]
+fn test_select_memory_type() {
+    let memory_type = |properties| MemoryType {
+        properties,
+        heap_index: 0,
+    };
+    let memory_properties = MemoryProperties {
+        memory_types: vec![
+            memory_type(Properties::DEVICE_LOCAL),
+            memory_type(Properties::COHERENT),
+            memory_type(Properties::COHERENT | Properties::CPU_CACHED),
+        ],
+        memory_heaps: vec![1 << 30],
+    };
+    let select = |type_mask, required, preferred| {
+        select_memory_type(&memory_properties, type_mask, required, preferred)
+    };
+
+    assert_eq!(
+        select(!0, Properties::COHERENT, Properties::CPU_CACHED),
+        Some(MemoryTypeId(2))
+    );
+    assert_eq!(
+        select(0b011, Properties::COHERENT, Properties::CPU_CACHED),
+        Some(MemoryTypeId(1))
+    );
+    assert_eq!(
+        select(0b001, Properties::COHERENT, Properties::empty()),
+        None
+    );
+}
+
+#[test]
+fn test_select_memory_type_out_of_range() {
+    let memory_type = |properties| MemoryType {
+        properties,
+        heap_index: 0,
+    };
+    let mut memory_types = vec![memory_type(Properties::empty()); MAX_MEMORY_TYPES];
+    memory_types.push(memory_type(Properties::DEVICE_LOCAL));
+    let memory_properties = MemoryProperties {
+        memory_types,
+        memory_heaps: vec![1 << 30],
+    };
+
+    // Memory types past `MAX_MEMORY_TYPES` cannot be in the mask
+    assert_eq!(
+        select_memory_type(&memory_properties, !0, Properties::DEVICE_LOCAL, Properties::empty()),
+        None
+    );
+}
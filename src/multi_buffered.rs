@@ -0,0 +1,223 @@
+use std::ops::Index;
+
+use gfx_hal::Backend;
+use gfx_hal::memory::Requirements;
+
+use {MemoryError, MemorySubAllocator};
+
+/// Allocator of per-frame copies of memory for frames in flight.
+///
+/// Every allocation returns one block per frame from the wrapped sub-allocator, all allocated
+/// with the same requirements, so they come from the same size class. All blocks are freed
+/// together.
+///
+/// ### Type parameters:
+///
+/// - `A`: wrapped sub-allocator
+#[derive(Debug)]
+pub struct MultiBufferedAllocator<A> {
+    inner: A,
+    frames: usize,
+}
+
+impl<A> MultiBufferedAllocator<A> {
+    /// Wrap a sub-allocator.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `inner`: sub-allocator to allocate blocks from
+    /// - `frames`: number of frames in flight
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `frames` is zero.
+    pub fn new(inner: A, frames: usize) -> Self {
+        assert_ne!(frames, 0);
+        MultiBufferedAllocator { inner, frames }
+    }
+
+    /// Get the number of frames in flight.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Get the wrapped sub-allocator.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Get the wrapped sub-allocator mutably.
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.inner
+    }
+
+    /// Take the wrapped sub-allocator.
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+
+    /// Allocate a block for every frame.
+    ///
+    /// If any allocation fails, the blocks allocated so far are freed and the error is returned.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator the wrapped sub-allocator allocates from
+    /// - `device`: device to allocate the memory from
+    /// - `request`: information required by the wrapped sub-allocator, repeated for every frame
+    /// - `reqs`: the requirements each memory block must meet
+    pub fn alloc<B, O>(
+        &mut self,
+        owner: &mut O,
+        device: &B::Device,
+        request: A::Request,
+        reqs: Requirements,
+    ) -> Result<FrameBlocks<A::Block>, MemoryError>
+    where
+        B: Backend,
+        A: MemorySubAllocator<B, O>,
+        A::Request: Clone,
+    {
+        let mut blocks = Vec::with_capacity(self.frames);
+        for _ in 0..self.frames {
+            match self.inner.alloc(owner, device, request.clone(), reqs) {
+                Ok(block) => blocks.push(block),
+                Err(error) => {
+                    for block in blocks {
+                        self.inner.free(owner, device, block);
+                    }
+                    return Err(error);
+                }
+            }
+        }
+        Ok(FrameBlocks(blocks))
+    }
+
+    /// Free the blocks of all frames.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator the wrapped sub-allocator allocates from
+    /// - `device`: same device that was used to allocate the blocks
+    /// - `blocks`: blocks returned by `alloc`
+    pub fn free<B, O>(&mut self, owner: &mut O, device: &B::Device, blocks: FrameBlocks<A::Block>)
+    where
+        B: Backend,
+        A: MemorySubAllocator<B, O>,
+    {
+        assert_eq!(blocks.len(), self.frames);
+        for block in blocks.0 {
+            self.inner.free(owner, device, block);
+        }
+    }
+}
+
+/// Blocks allocated by `MultiBufferedAllocator`, one per frame.
+#[derive(Debug)]
+pub struct FrameBlocks<T>(Vec<T>);
+
+impl<T> FrameBlocks<T> {
+    /// Get the block of the specified frame.
+    pub fn frame(&self, frame: usize) -> &T {
+        &self.0[frame]
+    }
+
+    /// Get the number of frames.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Check if there are no frames. Never true for blocks returned by `alloc`.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterate over blocks of all frames.
+    pub fn iter(&self) -> ::std::slice::Iter<'_, T> {
+        self.0.iter()
+    }
+}
+
+impl<T> Index<usize> for FrameBlocks<T> {
+    type Output = T;
+
+    fn index(&self, frame: usize) -> &T {
+        self.frame(frame)
+    }
+}
+
+#[test]
+fn test_multi_buffered() {
+    extern crate gfx_backend_empty as empty;
+
+    use gfx_hal::MemoryTypeId;
+
+    use block::Block;
+    use chunked::ChunkedAllocator;
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let chunked = ChunkedAllocator::new(MemoryTypeId(0), 4, 256, 1 << 16);
+    let mut allocator = MultiBufferedAllocator::new(chunked, 3);
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 200,
+        alignment: 1,
+    };
+
+    let blocks = allocator
+        .alloc::<empty::Backend, _>(&mut owner, &device, (), reqs)
+        .unwrap();
+    assert_eq!(blocks.len(), 3);
+    assert_eq!(blocks[1].range(), blocks.frame(1).range());
+    let mut ranges = blocks.iter().map(|block| block.range()).collect::<Vec<_>>();
+    ranges.sort_by_key(|range| range.start);
+    ranges.dedup();
+    assert_eq!(ranges.len(), 3);
+    assert!(blocks.iter().all(|block| block.size() == 256));
+    assert_eq!(allocator.inner().used(), 768);
+
+    allocator.free::<empty::Backend, _>(&mut owner, &device, blocks);
+    assert_eq!(allocator.inner().used(), 0);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator.into_inner(), &mut owner, &device)
+        .unwrap();
+}
+
+#[test]
+#[cfg(feature = "testing")]
+fn test_multi_buffered_rollback() {
+    extern crate gfx_backend_empty as empty;
+
+    use gfx_hal::MemoryTypeId;
+
+    use chunked::ChunkedAllocator;
+    use dry_run::DryRunAllocator;
+    use fault::{FaultInjector, FaultSchedule};
+
+    let device = empty::Device;
+    let schedule = FaultSchedule::Indices(vec![2]);
+    let mut owner = FaultInjector::new(DryRunAllocator::<()>::new(), schedule);
+    let chunked = ChunkedAllocator::new(MemoryTypeId(0), 1, 256, 1 << 16);
+    let mut allocator = MultiBufferedAllocator::new(chunked, 3);
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 256,
+        alignment: 1,
+    };
+
+    // The third frame fails, the blocks of the first two are freed again
+    match allocator.alloc::<empty::Backend, _>(&mut owner, &device, (), reqs) {
+        Err(MemoryError::OutOfMemory) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+    assert_eq!(allocator.inner().used(), 0);
+
+    let blocks = allocator
+        .alloc::<empty::Backend, _>(&mut owner, &device, (), reqs)
+        .unwrap();
+    assert_eq!(blocks.len(), 3);
+    allocator.free::<empty::Backend, _>(&mut owner, &device, blocks);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator.into_inner(), &mut owner, &device)
+        .unwrap();
+}
@@ -0,0 +1,176 @@
+use std::fmt::Debug;
+
+use gfx_hal::{Backend, MemoryTypeId};
+use gfx_hal::memory::Requirements;
+
+use {next_instance_id, MemoryAllocator, MemoryError, MemorySubAllocator};
+use block::Block;
+use chunked::{ChunkedAllocator, ChunkedBlock, NodeSelector, PowerOfTwoSelector};
+
+/// `ChunkedAllocator` paired with the allocator it allocates chunks from.
+///
+/// Unlike `ChunkedAllocator` itself this implements `MemoryAllocator`, so a fully configured
+/// chunked allocator can be used wherever a `MemoryAllocator` is expected, e.g. as the owner of
+/// another `ChunkedAllocator` to sub-allocate from its blocks.
+///
+/// ### Type parameters:
+///
+/// - `T`: type of blocks the `ChunkedAllocator` sub-allocates from.
+/// - `A`: allocator the chunks are allocated from.
+/// - `S`: strategy defining the size classes of the `ChunkedAllocator`.
+#[derive(Debug)]
+pub struct OwnedChunkedAllocator<T, A, S = PowerOfTwoSelector> {
+    chunks: ChunkedAllocator<T, S>,
+    owner: A,
+    instance_id: usize,
+}
+
+impl<T, A, S> OwnedChunkedAllocator<T, A, S> {
+    /// Pair a chunked allocator with the allocator to allocate its chunks from.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `chunks`: allocator to sub-allocate blocks with, must not have been used with another
+    ///   owner
+    /// - `owner`: allocator to allocate chunks from
+    pub fn new(chunks: ChunkedAllocator<T, S>, owner: A) -> Self {
+        OwnedChunkedAllocator {
+            chunks,
+            owner,
+            instance_id: next_instance_id(),
+        }
+    }
+
+    /// Get the chunked allocator.
+    pub fn chunks(&self) -> &ChunkedAllocator<T, S> {
+        &self.chunks
+    }
+
+    /// Get the chunked allocator mutably.
+    pub fn chunks_mut(&mut self) -> &mut ChunkedAllocator<T, S> {
+        &mut self.chunks
+    }
+
+    /// Get the allocator chunks are allocated from.
+    pub fn owner(&self) -> &A {
+        &self.owner
+    }
+
+    /// Get the allocator chunks are allocated from mutably.
+    pub fn owner_mut(&mut self) -> &mut A {
+        &mut self.owner
+    }
+
+    /// Get both allocators mutably, e.g. to call methods of the chunked allocator that take the
+    /// owner.
+    pub fn split_mut(&mut self) -> (&mut ChunkedAllocator<T, S>, &mut A) {
+        (&mut self.chunks, &mut self.owner)
+    }
+
+    /// Take both allocators.
+    pub fn into_inner(self) -> (ChunkedAllocator<T, S>, A) {
+        (self.chunks, self.owner)
+    }
+}
+
+impl<B, T, A, S> MemoryAllocator<B> for OwnedChunkedAllocator<T, A, S>
+where
+    B: Backend,
+    T: Block<Memory = B::Memory>,
+    A: MemoryAllocator<B, Block = T>,
+    S: NodeSelector + Clone + Debug,
+{
+    type Request = A::Request;
+    type Block = ChunkedBlock<B::Memory>;
+
+    fn alloc(
+        &mut self,
+        device: &B::Device,
+        request: A::Request,
+        reqs: Requirements,
+    ) -> Result<ChunkedBlock<B::Memory>, MemoryError> {
+        MemorySubAllocator::<B, A>::alloc(&mut self.chunks, &mut self.owner, device, request, reqs)
+    }
+
+    fn free(&mut self, device: &B::Device, block: ChunkedBlock<B::Memory>) {
+        MemorySubAllocator::<B, A>::free(&mut self.chunks, &mut self.owner, device, block)
+    }
+
+    fn block_memory_type(&self, _block: &ChunkedBlock<B::Memory>) -> Option<MemoryTypeId> {
+        Some(self.chunks.memory_type())
+    }
+
+    fn instance_id(&self) -> Option<usize> {
+        Some(self.instance_id)
+    }
+
+    fn is_used(&self) -> bool {
+        self.chunks.is_used()
+    }
+
+    fn dispose(self, device: &B::Device) -> Result<(), Self> {
+        if self.chunks.is_used() {
+            return Err(self);
+        }
+        let OwnedChunkedAllocator {
+            chunks,
+            mut owner,
+            instance_id,
+        } = self;
+        // Keep the configuration in case the owner can't be disposed yet
+        let empty = chunks.clone_config();
+        MemorySubAllocator::<B, A>::dispose(chunks, &mut owner, device)
+            .expect("Chunked allocator is not used");
+        owner.dispose(device).map_err(|owner| OwnedChunkedAllocator {
+            chunks: empty,
+            owner,
+            instance_id,
+        })
+    }
+}
+
+#[test]
+fn test_nested() {
+    extern crate gfx_backend_empty as empty;
+
+    use gfx_hal::MemoryTypeId;
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let root = DryRunAllocator::<()>::new();
+    let outer = ChunkedAllocator::new(MemoryTypeId(0), 4, 1024, 1 << 16);
+    let mut owner = OwnedChunkedAllocator::new(outer, root);
+    let mut inner = ChunkedAllocator::new(MemoryTypeId(0), 8, 64, 1024);
+    let reqs = |size| Requirements {
+        type_mask: 1,
+        size,
+        alignment: 1,
+    };
+
+    let blocks = (0..20)
+        .map(|i| {
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut inner,
+                &mut owner,
+                &device,
+                (),
+                reqs(64 << (i % 3)),
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    // Chunks of the inner allocator all fit into a single chunk of the outer one
+    assert_eq!(owner.owner().allocations(), 1);
+    assert!(owner.chunks().used() >= inner.allocated());
+    let mut ranges = blocks.iter().map(Block::range).collect::<Vec<_>>();
+    ranges.sort_by_key(|range| range.start);
+    assert!(ranges.windows(2).all(|pair| pair[0].end <= pair[1].start));
+    assert!(ranges.last().unwrap().end <= owner.owner().reserved());
+
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut inner, &mut owner, &device, block);
+    }
+    MemorySubAllocator::<empty::Backend, _>::dispose(inner, &mut owner, &device).unwrap();
+    assert!(!MemoryAllocator::<empty::Backend>::is_used(&owner));
+    MemoryAllocator::<empty::Backend>::dispose(owner, &device).unwrap();
+}
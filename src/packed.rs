@@ -0,0 +1,428 @@
+use std::any::Any;
+use std::cmp::max;
+use std::fmt::Debug;
+use std::ops::Range;
+
+use gfx_hal::{Backend, MemoryTypeId};
+use gfx_hal::memory::Requirements;
+
+use {alignment_shift, MemoryAllocator, MemoryError, MemorySubAllocator, MAX_MEMORY_TYPES};
+use block::{Block, RawBlock};
+
+/// Sub-allocator that packs blocks of arbitrary size densely.
+///
+/// This allocator allocates chunks in increments of `chunk_size` bytes, and places blocks into
+/// the first hole of a chunk that fits them at their natural alignment. Sizes are not rounded,
+/// except that zero sized blocks take one byte, and adjacent holes are merged when blocks are
+/// freed. A chunk is returned to the underlying allocator as soon as all of its blocks are
+/// freed.
+///
+/// This allocator is suited for many small allocations of mixed sizes, such as structures packed
+/// into a single storage buffer.
+///
+/// ### Type parameters:
+///
+/// - `T`: type of blocks this allocator sub-allocates from.
+#[derive(Debug)]
+pub struct PackedAllocator<T> {
+    id: MemoryTypeId,
+    chunk_size: u64,
+    chunks: Vec<Option<PackedChunk<T>>>,
+    search_stats: SearchStats,
+}
+
+impl<T> PackedAllocator<T> {
+    /// Create a new packed allocator.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `id`: ID of the memory type this allocator allocates from.
+    /// - `chunk_size`: The minimum size of the chunks allocated from the underlying allocator
+    ///   in bytes. All memory is allocated in increments of `chunk_size`.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `id` is not less than `MAX_MEMORY_TYPES`.
+    pub fn new(id: MemoryTypeId, chunk_size: u64) -> Self {
+        assert!(id.0 < MAX_MEMORY_TYPES, "Memory type {:?} is out of range", id);
+        PackedAllocator {
+            id,
+            chunk_size,
+            chunks: Vec::new(),
+            search_stats: SearchStats::default(),
+        }
+    }
+
+    /// Check if any of the blocks allocated by this allocator are still in use.
+    /// If this function returns `false`, the allocator can be `dispose`d.
+    pub fn is_used(&self) -> bool {
+        self.chunks.iter().any(|chunk| chunk.is_some())
+    }
+
+    /// Get memory type of the allocator
+    pub fn memory_type(&self) -> MemoryTypeId {
+        self.id
+    }
+
+    /// Get the minimum size of each chunk in bytes
+    pub fn chunk_size(&self) -> u64 {
+        self.chunk_size
+    }
+
+    /// Retrieves the block backing an allocation.
+    pub fn underlying_block<M>(&self, block: &PackedBlock<M>) -> &T {
+        &self.chunks[block.1].as_ref().unwrap().block
+    }
+
+    /// Get statistics of the search for holes fitting allocated blocks.
+    pub fn search_stats(&self) -> SearchStats {
+        self.search_stats
+    }
+
+    /// Get the total size of all blocks allocated by this allocator.
+    pub fn used(&self) -> u64 {
+        self.chunks
+            .iter()
+            .filter_map(Option::as_ref)
+            .map(|chunk| chunk.used)
+            .sum()
+    }
+
+    /// Get the total size of all chunks allocated by this allocator.
+    pub fn allocated(&self) -> u64
+    where
+        T: Block,
+    {
+        self.chunks
+            .iter()
+            .filter_map(Option::as_ref)
+            .map(|chunk| chunk.block.size())
+            .sum()
+    }
+}
+
+impl<B, O, T> MemorySubAllocator<B, O> for PackedAllocator<T>
+where
+    B: Backend,
+    T: Block<Memory = B::Memory>,
+    O: MemoryAllocator<B, Block = T>,
+{
+    type Request = O::Request;
+    type Block = PackedBlock<B::Memory>;
+
+    fn alloc(
+        &mut self,
+        owner: &mut O,
+        device: &B::Device,
+        request: O::Request,
+        reqs: Requirements,
+    ) -> Result<PackedBlock<B::Memory>, MemoryError> {
+        if (1 << self.id.0) & reqs.type_mask == 0 {
+            return Err(MemoryError::NoCompatibleMemoryType);
+        }
+
+        self.search_stats.allocations += 1;
+        // Empty blocks would not keep their chunk in use
+        let reqs = Requirements {
+            size: max(reqs.size, 1),
+            ..reqs
+        };
+
+        // First fit in existing chunks
+        for (index, chunk) in self.chunks.iter_mut().enumerate() {
+            if let Some(ref mut chunk) = *chunk {
+                if let Some(block) = chunk.alloc(reqs, &mut self.search_stats.examined) {
+                    return Ok(PackedBlock(block, index));
+                }
+            }
+        }
+
+        let size = ((reqs.size - 1) / self.chunk_size + 1) * self.chunk_size;
+        let chunk_requirements = Requirements {
+            type_mask: 1 << self.id.0,
+            size,
+            alignment: reqs.alignment,
+        };
+        let mut chunk = PackedChunk::new(owner.alloc(device, request, chunk_requirements)?);
+        let block = chunk
+            .alloc(reqs, &mut self.search_stats.examined)
+            .expect("Chunk is large enough");
+
+        // Reuse a slot of a freed chunk or append a new one
+        let index = self.chunks
+            .iter()
+            .position(|chunk| chunk.is_none())
+            .unwrap_or(self.chunks.len());
+        if index == self.chunks.len() {
+            self.chunks.push(Some(chunk));
+        } else {
+            self.chunks[index] = Some(chunk);
+        }
+        Ok(PackedBlock(block, index))
+    }
+
+    fn free(&mut self, owner: &mut O, device: &B::Device, block: PackedBlock<B::Memory>) {
+        let PackedBlock(block, index) = block;
+        let unused = {
+            let chunk = self.chunks[index].as_mut().unwrap();
+            chunk.free(block);
+            !chunk.is_used()
+        };
+        if unused {
+            let chunk = self.chunks[index].take().unwrap();
+            owner.free(device, chunk.block);
+        }
+    }
+
+    fn dispose(self, _owner: &mut O, _device: &B::Device) -> Result<(), Self> {
+        // Chunks are freed as soon as they are unused.
+        if self.is_used() {
+            Err(self)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PackedChunk<T> {
+    used: u64,
+    holes: FreeRanges,
+    block: T,
+}
+
+impl<T> PackedChunk<T> {
+    fn new(block: T) -> Self
+    where
+        T: Block,
+    {
+        PackedChunk {
+            used: 0,
+            holes: FreeRanges::new(block.range()),
+            block,
+        }
+    }
+
+    fn alloc<M>(&mut self, reqs: Requirements, examined: &mut u64) -> Option<RawBlock<M>>
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        let range = self.holes.alloc(reqs.size, reqs.alignment, examined)?;
+        self.used += reqs.size;
+        Some(RawBlock::new(self.block.memory(), range))
+    }
+
+    fn free<M>(&mut self, block: RawBlock<M>)
+    where
+        M: Debug + Any,
+        T: Block<Memory = M>,
+    {
+        assert!(self.block.contains(&block));
+        let range = block.range();
+        unsafe { block.dispose() };
+        self.used -= range.end - range.start;
+        self.holes.free(range);
+    }
+
+    fn is_used(&self) -> bool {
+        self.used != 0
+    }
+}
+
+/// Free ranges sorted by offset. Adjacent ranges are always merged.
+#[derive(Debug)]
+pub(crate) struct FreeRanges {
+    holes: Vec<Range<u64>>,
+}
+
+impl FreeRanges {
+    /// Create free ranges covering `range` entirely.
+    pub(crate) fn new(range: Range<u64>) -> Self {
+        FreeRanges { holes: vec![range] }
+    }
+
+    /// Take aligned range of specified size from the first hole that fits it.
+    /// Adds the number of holes examined to `examined`.
+    pub(crate) fn alloc(
+        &mut self,
+        size: u64,
+        alignment: u64,
+        examined: &mut u64,
+    ) -> Option<Range<u64>> {
+        let (index, range) = self.holes
+            .iter()
+            .enumerate()
+            .inspect(|_| *examined += 1)
+            .filter_map(|(index, hole)| {
+                let start = hole.start + alignment_shift(alignment, hole.start);
+                let end = start + size;
+                if end <= hole.end {
+                    Some((index, start..end))
+                } else {
+                    None
+                }
+            })
+            .next()?;
+
+        // Keep leftovers on both sides of the range as holes
+        let hole = self.holes.remove(index);
+        if range.end < hole.end {
+            self.holes.insert(index, range.end..hole.end);
+        }
+        if hole.start < range.start {
+            self.holes.insert(index, hole.start..range.start);
+        }
+        Some(range)
+    }
+
+    /// Check if any part of `range` is free.
+    pub(crate) fn overlaps(&self, range: &Range<u64>) -> bool {
+        self.holes
+            .iter()
+            .any(|hole| hole.start < range.end && range.start < hole.end)
+    }
+
+    /// Return range taken by `alloc`.
+    pub(crate) fn free(&mut self, range: Range<u64>) {
+        let index = self.holes
+            .iter()
+            .position(|hole| hole.start >= range.end)
+            .unwrap_or(self.holes.len());
+        debug_assert!(index == 0 || self.holes[index - 1].end <= range.start);
+
+        // Merge with neighbour holes
+        let prev = index > 0 && self.holes[index - 1].end == range.start;
+        let next = index < self.holes.len() && self.holes[index].start == range.end;
+        match (prev, next) {
+            (true, true) => {
+                let next = self.holes.remove(index);
+                self.holes[index - 1].end = next.end;
+            }
+            (true, false) => self.holes[index - 1].end = range.end,
+            (false, true) => self.holes[index].start = range.start,
+            (false, false) => self.holes.insert(index, range),
+        }
+    }
+}
+
+/// Statistics of the first-fit search for free ranges.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    /// Number of allocations
+    pub allocations: u64,
+    /// Number of free ranges examined by all allocations
+    pub examined: u64,
+}
+
+impl SearchStats {
+    /// Get the average number of free ranges examined per allocation.
+    pub fn average(&self) -> f64 {
+        if self.allocations == 0 {
+            0.0
+        } else {
+            self.examined as f64 / self.allocations as f64
+        }
+    }
+}
+
+/// `Block` type returned by `PackedAllocator`.
+#[derive(Debug)]
+pub struct PackedBlock<M>(pub(crate) RawBlock<M>, pub(crate) usize);
+
+impl<M> Block for PackedBlock<M>
+where
+    M: Debug + Any,
+{
+    type Memory = M;
+
+    #[inline(always)]
+    fn memory(&self) -> &M {
+        self.0.memory()
+    }
+
+    #[inline(always)]
+    fn range(&self) -> Range<u64> {
+        self.0.range()
+    }
+}
+
+#[test]
+#[allow(dead_code)]
+fn test_send_sync() {
+    fn foo<T: Send + Sync>() {}
+    fn bar<M: Send + Sync>() {
+        foo::<PackedAllocator<M>>()
+    }
+}
+
+#[test]
+fn test_merge_holes() {
+    let memory = 0u32;
+    let reqs = |size, alignment| Requirements {
+        type_mask: 1,
+        size,
+        alignment,
+    };
+
+    let mut examined = 0;
+    let mut chunk = PackedChunk::new(RawBlock::new(&memory, 0..256));
+    let a = chunk.alloc::<u32>(reqs(24, 8), &mut examined).unwrap();
+    let b = chunk.alloc::<u32>(reqs(12, 4), &mut examined).unwrap();
+    let c = chunk.alloc::<u32>(reqs(64, 64), &mut examined).unwrap();
+    assert_eq!(a.range(), 0..24);
+    assert_eq!(b.range(), 24..36);
+    assert_eq!(c.range(), 64..128);
+    assert_eq!(chunk.holes.holes, vec![36..64, 128..256]);
+    assert_eq!(examined, 3);
+
+    chunk.free(b);
+    assert_eq!(chunk.holes.holes, vec![24..64, 128..256]);
+    chunk.free(a);
+    assert_eq!(chunk.holes.holes, vec![0..64, 128..256]);
+    chunk.free(c);
+    assert_eq!(chunk.holes.holes, vec![0..256]);
+    assert!(!chunk.is_used());
+
+    unsafe { chunk.block.dispose() };
+}
+
+#[test]
+fn test_packed() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = PackedAllocator::new(MemoryTypeId(0), 1024);
+    let blocks = [0, 100, 2000]
+        .iter()
+        .map(|&size| {
+            let reqs = Requirements {
+                type_mask: 1,
+                size,
+                alignment: 4,
+            };
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut allocator,
+                &mut owner,
+                &device,
+                (),
+                reqs,
+            ).unwrap()
+        })
+        .collect::<Vec<_>>();
+    // The empty block takes a byte, so the next one is placed at the next aligned offset
+    assert_eq!(blocks[0].range(), 0..1);
+    assert_eq!(blocks[1].range(), 4..104);
+    assert_eq!(blocks[2].size(), 2000);
+    assert_eq!((allocator.used(), allocator.allocated()), (2101, 3072));
+
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    assert!(!allocator.is_used());
+    assert_eq!(owner.reserved(), 0);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
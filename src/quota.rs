@@ -0,0 +1,232 @@
+use std::cmp::max;
+
+use gfx_hal::{Backend, MemoryProperties, MemoryTypeId};
+use gfx_hal::memory::{Properties, Requirements};
+
+use {MemoryAllocator, MemoryError, MemorySubAllocator};
+use block::Block;
+use chunked::ChunkedAllocator;
+use multi::{MultiTypeBlock, TypeAllocators};
+
+/// Sub-allocator with soft per-type quotas, that spills allocations over to a fallback memory
+/// type when a quota is hit.
+///
+/// The first memory type that is allowed by the requirements and has all requested properties
+/// is chosen, same as with `MultiTypeAllocator`. If the allocation would make the
+/// `ChunkedAllocator` of that type hold more memory than its quota, it is routed to the
+/// spillover type of the chosen type instead, provided the requirements allow it. Quotas are
+/// soft, if there is no usable spillover type the allocation is made from the chosen type
+/// anyway. This is useful on devices where all memory types share a single heap.
+///
+/// ### Type parameters:
+///
+/// - `T`: type of bigger blocks this allocator sub-allocates from.
+#[derive(Debug)]
+pub struct QuotaAllocator<T> {
+    allocators: TypeAllocators<T>,
+    quotas: Vec<Option<u64>>,
+    spillover: Vec<Option<MemoryTypeId>>,
+}
+
+impl<T> QuotaAllocator<T> {
+    /// Create a new quota allocator from `MemoryProperties` given by a device.
+    /// No memory type has a quota initially.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `memory_properties`: memory properties describing the memory available on a device
+    /// - `blocks_per_chunk`: see `ChunkedAllocator`
+    /// - `min_block_size`: see `ChunkedAllocator`
+    /// - `max_chunk_size`: see `ChunkedAllocator`
+    pub fn new(
+        memory_properties: MemoryProperties,
+        blocks_per_chunk: usize,
+        min_block_size: u64,
+        max_chunk_size: u64,
+    ) -> Self {
+        let count = memory_properties.memory_types.len();
+        QuotaAllocator {
+            allocators: TypeAllocators::new(
+                memory_properties,
+                blocks_per_chunk,
+                min_block_size,
+                max_chunk_size,
+            ),
+            quotas: vec![None; count],
+            spillover: vec![None; count],
+        }
+    }
+
+    /// Get the allocator of the specified memory type.
+    pub fn allocator(&self, id: MemoryTypeId) -> &ChunkedAllocator<T> {
+        self.allocators.allocator(id.0)
+    }
+
+    /// Get the quota of the specified memory type.
+    pub fn quota(&self, id: MemoryTypeId) -> Option<u64> {
+        self.quotas[id.0]
+    }
+
+    /// Set the number of bytes the allocator of the memory type may hold before allocations
+    /// spill over. `None` removes the quota.
+    pub fn set_quota(&mut self, id: MemoryTypeId, quota: Option<u64>) {
+        self.quotas[id.0] = quota;
+    }
+
+    /// Set the memory type allocations spill over to when the quota of `id` is hit.
+    ///
+    /// The spillover type is used regardless of its own quota and doesn't spill over further.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `spillover` is not a memory type of the allocator.
+    pub fn set_spillover(&mut self, id: MemoryTypeId, spillover: Option<MemoryTypeId>) {
+        if let Some(spillover) = spillover {
+            assert!(
+                spillover.0 < self.allocators.len(),
+                "Memory type {:?} is out of range",
+                spillover
+            );
+        }
+        self.spillover[id.0] = spillover;
+    }
+
+    /// Check if any of the blocks allocated by this allocator are still in use.
+    /// If this function returns `false`, the allocator can be `dispose`d.
+    pub fn is_used(&self) -> bool {
+        self.allocators.is_used()
+    }
+
+    /// Get the total size of all blocks allocated by this allocator.
+    pub fn used(&self) -> u64 {
+        self.allocators.used()
+    }
+
+    /// Get the total size of all chunks allocated by this allocator.
+    pub fn allocated(&self) -> u64 {
+        self.allocators.allocated()
+    }
+
+    /// Check if allocating a block meeting the requirements would exceed the quota.
+    fn exceeds_quota(&self, index: usize, reqs: &Requirements) -> bool
+    where
+        T: Block,
+    {
+        let quota = match self.quotas[index] {
+            Some(quota) => quota,
+            None => return false,
+        };
+        let allocator = self.allocators.allocator(index);
+        let size = max(reqs.size, reqs.alignment);
+        if allocator.available_for(reqs) > 0 || size == 0 || size > allocator.max_chunk_size() {
+            // Allocation doesn't grow the allocator
+            return false;
+        }
+        let chunk_size = allocator.chunk_size_of_node(allocator.node_index_for_size(size));
+        allocator.owner_bytes_held() + chunk_size > quota
+    }
+}
+
+impl<B, O, T> MemorySubAllocator<B, O> for QuotaAllocator<T>
+where
+    B: Backend,
+    T: Block<Memory = B::Memory>,
+    O: MemoryAllocator<B, Block = T>,
+{
+    type Request = (Properties, O::Request);
+    type Block = MultiTypeBlock<B::Memory>;
+
+    fn alloc(
+        &mut self,
+        owner: &mut O,
+        device: &B::Device,
+        (prop, request): (Properties, O::Request),
+        reqs: Requirements,
+    ) -> Result<MultiTypeBlock<B::Memory>, MemoryError> {
+        let mut index = (0..self.allocators.len())
+            .find(|&index| self.allocators.compatible(index, reqs.type_mask, prop))
+            .ok_or(MemoryError::NoCompatibleMemoryType)?;
+        if self.exceeds_quota(index, &reqs) {
+            if let Some(spillover) = self.spillover[index] {
+                if self.allocators.compatible(spillover.0, reqs.type_mask, prop) {
+                    index = spillover.0;
+                }
+            }
+        }
+
+        self.allocators.alloc(index, owner, device, request, reqs)
+    }
+
+    fn free(&mut self, owner: &mut O, device: &B::Device, block: MultiTypeBlock<B::Memory>) {
+        self.allocators.free(owner, device, block);
+    }
+
+    fn dispose(self, owner: &mut O, device: &B::Device) -> Result<(), Self> {
+        if self.is_used() {
+            Err(self)
+        } else {
+            self.allocators.dispose(owner, device);
+            Ok(())
+        }
+    }
+}
+
+#[test]
+#[allow(dead_code)]
+fn test_send_sync() {
+    fn foo<T: Send + Sync>() {}
+    fn bar<M: Send + Sync>() {
+        foo::<QuotaAllocator<M>>()
+    }
+}
+
+#[test]
+fn test_spillover() {
+    extern crate gfx_backend_empty as empty;
+
+    use gfx_hal::MemoryType;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let memory_type = MemoryType {
+        properties: Properties::DEVICE_LOCAL,
+        heap_index: 0,
+    };
+    let memory_properties = MemoryProperties {
+        memory_types: vec![memory_type, memory_type],
+        memory_heaps: vec![1 << 30],
+    };
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator = QuotaAllocator::new(memory_properties, 4, 256, 1 << 16);
+    allocator.set_quota(MemoryTypeId(0), Some(1024));
+    allocator.set_spillover(MemoryTypeId(0), Some(MemoryTypeId(1)));
+    let reqs = Requirements {
+        type_mask: !0,
+        size: 256,
+        alignment: 1,
+    };
+
+    let blocks = (0..6)
+        .map(|_| {
+            MemorySubAllocator::<empty::Backend, _>::alloc(
+                &mut allocator,
+                &mut owner,
+                &device,
+                (Properties::DEVICE_LOCAL, ()),
+                reqs,
+            ).unwrap()
+        })
+        .collect::<Vec<_>>();
+    // First chunk fits into the quota, the rest spills over
+    let types = blocks
+        .iter()
+        .map(|block| block.memory_type().0)
+        .collect::<Vec<_>>();
+    assert_eq!(types, vec![0, 0, 0, 0, 1, 1]);
+
+    for block in blocks {
+        MemorySubAllocator::<empty::Backend, _>::free(&mut allocator, &mut owner, &device, block);
+    }
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator, &mut owner, &device).unwrap();
+}
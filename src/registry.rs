@@ -0,0 +1,113 @@
+//! Global registry of live `ChunkedAllocator`s, for tools such as profilers that want to
+//! enumerate allocators without being handed references to them.
+//!
+//! Only available with the `registry` feature. Every `ChunkedAllocator` registers itself when
+//! created and is removed from the registry when dropped or disposed.
+
+use std::sync::{Arc, Mutex, MutexGuard, Once, Weak};
+
+use gfx_hal::MemoryTypeId;
+
+use next_instance_id;
+
+static REGISTRY_INIT: Once = Once::new();
+static mut REGISTRY: *const Mutex<Vec<Weak<Entry>>> = ::std::ptr::null();
+
+/// Lock the registry, creating it on first use.
+fn registry() -> MutexGuard<'static, Vec<Weak<Entry>>> {
+    let registry = unsafe {
+        REGISTRY_INIT.call_once(|| {
+            REGISTRY = Box::into_raw(Box::new(Mutex::new(Vec::new())));
+        });
+        // Never freed once created
+        &*REGISTRY
+    };
+    registry.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+/// View of a live allocator returned by `snapshot`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AllocatorView {
+    /// Unique ID of the allocator
+    pub id: usize,
+    /// Memory type of the allocator
+    pub memory_type: MemoryTypeId,
+    /// Total size of chunks allocated by the allocator in bytes
+    pub allocated: u64,
+    /// Total size of blocks used by the allocator in bytes
+    pub used: u64,
+}
+
+/// Get views of all live allocators in the order they were created.
+///
+/// Usage is updated by allocations, frees and `shrink`, so it may lag behind for other
+/// operations until the next of those.
+pub fn snapshot() -> Vec<AllocatorView> {
+    registry()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .map(|entry| {
+            let (allocated, used) = *entry.usage();
+            AllocatorView {
+                id: entry.id,
+                memory_type: entry.memory_type,
+                allocated,
+                used,
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+struct Entry {
+    id: usize,
+    memory_type: MemoryTypeId,
+    /// Allocated and used bytes
+    usage: Mutex<(u64, u64)>,
+}
+
+impl Entry {
+    fn usage(&self) -> MutexGuard<'_, (u64, u64)> {
+        self.usage.lock().unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+/// Entry of an allocator in the registry, removed again when dropped.
+#[derive(Debug)]
+pub(crate) struct Registration(Arc<Entry>);
+
+impl Registration {
+    pub(crate) fn new(memory_type: MemoryTypeId) -> Self {
+        let entry = Arc::new(Entry {
+            id: next_instance_id(),
+            memory_type,
+            usage: Mutex::new((0, 0)),
+        });
+        registry().push(Arc::downgrade(&entry));
+        Registration(entry)
+    }
+
+    pub(crate) fn set_usage(&self, allocated: u64, used: u64) {
+        *self.0.usage() = (allocated, used);
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let entry = Arc::downgrade(&self.0);
+        registry().retain(|other| !other.ptr_eq(&entry) && other.upgrade().is_some());
+    }
+}
+
+#[test]
+fn test_snapshot() {
+    let registration = Registration::new(MemoryTypeId(3));
+    registration.set_usage(1024, 256);
+    let id = registration.0.id;
+    let view = snapshot().into_iter().find(|view| view.id == id).unwrap();
+    assert_eq!(view.memory_type, MemoryTypeId(3));
+    assert_eq!((view.allocated, view.used), (1024, 256));
+
+    drop(registration);
+    assert!(snapshot().iter().all(|view| view.id != id));
+}
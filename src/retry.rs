@@ -0,0 +1,191 @@
+use std::fmt;
+
+use gfx_hal::{Backend, MemoryTypeId};
+use gfx_hal::memory::Requirements;
+
+use {MemoryAllocator, MemoryError};
+
+/// Allocator that runs an eviction callback and retries once when the wrapped allocator is out
+/// of memory.
+///
+/// The callback should free memory that is no longer needed, e.g. unused textures, and return
+/// `true` if it freed anything. The allocation is retried only in that case, otherwise
+/// `MemoryError::OutOfMemory` is returned.
+///
+/// ### Type parameters:
+///
+/// - `A`: wrapped allocator
+/// - `F`: eviction callback
+pub struct RetryAllocator<A, F> {
+    inner: A,
+    evict: F,
+}
+
+impl<A, F> RetryAllocator<A, F>
+where
+    F: FnMut() -> bool,
+{
+    /// Wrap an allocator.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `inner`: allocator to allocate from
+    /// - `evict`: callback that frees memory, returns `true` if anything was freed
+    pub fn new(inner: A, evict: F) -> Self {
+        RetryAllocator { inner, evict }
+    }
+
+    /// Get the wrapped allocator.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Get the wrapped allocator mutably.
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.inner
+    }
+
+    /// Take the wrapped allocator.
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+}
+
+impl<A, F> fmt::Debug for RetryAllocator<A, F>
+where
+    A: fmt::Debug,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("RetryAllocator")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<B, A, F> MemoryAllocator<B> for RetryAllocator<A, F>
+where
+    B: Backend,
+    A: MemoryAllocator<B>,
+    A::Request: Clone,
+    F: FnMut() -> bool,
+{
+    type Request = A::Request;
+    type Block = A::Block;
+
+    fn alloc(
+        &mut self,
+        device: &B::Device,
+        request: A::Request,
+        reqs: Requirements,
+    ) -> Result<A::Block, MemoryError> {
+        match self.inner.alloc(device, request.clone(), reqs) {
+            Err(MemoryError::OutOfMemory) => {
+                if (self.evict)() {
+                    self.inner.alloc(device, request, reqs)
+                } else {
+                    Err(MemoryError::OutOfMemory)
+                }
+            }
+            result => result,
+        }
+    }
+
+    fn free(&mut self, device: &B::Device, block: A::Block) {
+        self.inner.free(device, block)
+    }
+
+    fn block_memory_type(&self, block: &A::Block) -> Option<MemoryTypeId> {
+        self.inner.block_memory_type(block)
+    }
+
+    fn instance_id(&self) -> Option<usize> {
+        self.inner.instance_id()
+    }
+
+    fn is_used(&self) -> bool {
+        self.inner.is_used()
+    }
+
+    fn dispose(self, device: &B::Device) -> Result<(), Self> {
+        let evict = self.evict;
+        self.inner
+            .dispose(device)
+            .map_err(|inner| RetryAllocator { inner, evict })
+    }
+}
+
+#[test]
+#[cfg(feature = "testing")]
+fn test_retry() {
+    extern crate gfx_backend_empty as empty;
+
+    use std::cell::Cell;
+
+    use dry_run::DryRunAllocator;
+    use fault::{FaultInjector, FaultSchedule};
+
+    let device = empty::Device;
+    let evictions = Cell::new(0);
+    let schedule = FaultSchedule::Indices(vec![0]);
+    let inner = FaultInjector::new(DryRunAllocator::<()>::new(), schedule);
+    let mut allocator = RetryAllocator::new(inner, || {
+        evictions.set(evictions.get() + 1);
+        true
+    });
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 256,
+        alignment: 1,
+    };
+
+    // The first attempt fails, the retry after eviction succeeds
+    let block =
+        MemoryAllocator::<empty::Backend>::alloc(&mut allocator, &device, (), reqs).unwrap();
+    assert_eq!(evictions.get(), 1);
+    assert_eq!(allocator.inner().allocations(), 2);
+
+    // Allocations that succeed do not evict
+    let other =
+        MemoryAllocator::<empty::Backend>::alloc(&mut allocator, &device, (), reqs).unwrap();
+    assert_eq!(evictions.get(), 1);
+
+    // The allocation is retried only once
+    allocator.inner_mut().reset(FaultSchedule::EveryNth(1));
+    match MemoryAllocator::<empty::Backend>::alloc(&mut allocator, &device, (), reqs) {
+        Err(MemoryError::OutOfMemory) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+    assert_eq!(evictions.get(), 2);
+    assert_eq!(allocator.inner().allocations(), 2);
+
+    MemoryAllocator::<empty::Backend>::free(&mut allocator, &device, block);
+    MemoryAllocator::<empty::Backend>::free(&mut allocator, &device, other);
+    MemoryAllocator::<empty::Backend>::dispose(allocator, &device).unwrap();
+}
+
+#[test]
+#[cfg(feature = "testing")]
+fn test_retry_nothing_evicted() {
+    extern crate gfx_backend_empty as empty;
+
+    use dry_run::DryRunAllocator;
+    use fault::{FaultInjector, FaultSchedule};
+
+    let device = empty::Device;
+    let schedule = FaultSchedule::Indices(vec![0]);
+    let inner = FaultInjector::new(DryRunAllocator::<()>::new(), schedule);
+    let mut allocator = RetryAllocator::new(inner, || false);
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 256,
+        alignment: 1,
+    };
+
+    // Nothing was freed, so the allocation is not retried
+    match MemoryAllocator::<empty::Backend>::alloc(&mut allocator, &device, (), reqs) {
+        Err(MemoryError::OutOfMemory) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+    assert_eq!(allocator.inner().allocations(), 1);
+    MemoryAllocator::<empty::Backend>::dispose(allocator, &device).unwrap();
+}
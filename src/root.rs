@@ -3,7 +3,7 @@ use std::marker::PhantomData;
 use gfx_hal::{Backend, Device, MemoryTypeId};
 use gfx_hal::memory::Requirements;
 
-use {MemoryAllocator, MemoryError};
+use {next_instance_id, MemoryAllocator, MemoryError};
 use block::{Block, RawBlock};
 use relevant::Relevant;
 
@@ -16,6 +16,7 @@ use relevant::Relevant;
 pub struct RootAllocator<B> {
     relevant: Relevant,
     id: MemoryTypeId,
+    instance_id: usize,
     used: u64,
     pd: PhantomData<fn() -> B>,
 }
@@ -30,6 +31,7 @@ impl<B> RootAllocator<B> {
         RootAllocator {
             relevant: Relevant,
             id,
+            instance_id: next_instance_id(),
             used: 0,
             pd: PhantomData,
         }
@@ -73,6 +75,14 @@ where
         self.used -= size;
     }
 
+    fn block_memory_type(&self, _block: &RawBlock<B::Memory>) -> Option<MemoryTypeId> {
+        Some(self.id)
+    }
+
+    fn instance_id(&self) -> Option<usize> {
+        Some(self.instance_id)
+    }
+
     fn is_used(&self) -> bool {
         self.used != 0
     }
@@ -0,0 +1,137 @@
+#[cfg(debug_assertions)]
+use std::thread::{self, ThreadId};
+
+use gfx_hal::{Backend, MemoryTypeId};
+use gfx_hal::memory::Requirements;
+
+use {MemoryAllocator, MemoryError};
+
+/// Allocator that checks it is only ever used from a single thread.
+///
+/// The thread of the first call is recorded, and every later call `debug_assert!`s it comes
+/// from the same thread. This catches accidental use from several threads, which a wrapper with
+/// proper synchronization would be required for. In release builds this is a plain passthrough.
+///
+/// ### Type parameters:
+///
+/// - `A`: wrapped allocator
+#[derive(Debug)]
+pub struct SingleThreadGuard<A> {
+    inner: A,
+    #[cfg(debug_assertions)]
+    thread: Option<ThreadId>,
+}
+
+impl<A> SingleThreadGuard<A> {
+    /// Wrap an allocator.
+    pub fn new(inner: A) -> Self {
+        SingleThreadGuard {
+            inner,
+            #[cfg(debug_assertions)]
+            thread: None,
+        }
+    }
+
+    /// Get the wrapped allocator.
+    pub fn inner(&self) -> &A {
+        self.check();
+        &self.inner
+    }
+
+    /// Get the wrapped allocator mutably.
+    pub fn inner_mut(&mut self) -> &mut A {
+        self.record();
+        &mut self.inner
+    }
+
+    /// Take the wrapped allocator.
+    pub fn into_inner(self) -> A {
+        self.check();
+        self.inner
+    }
+
+    #[cfg(debug_assertions)]
+    fn check(&self) {
+        if let Some(thread) = self.thread {
+            debug_assert_eq!(
+                thread,
+                thread::current().id(),
+                "Allocator is used from more than one thread"
+            );
+        }
+    }
+
+    #[cfg(not(debug_assertions))]
+    #[inline(always)]
+    fn check(&self) {}
+
+    #[cfg(debug_assertions)]
+    fn record(&mut self) {
+        self.check();
+        self.thread = Some(thread::current().id());
+    }
+
+    #[cfg(not(debug_assertions))]
+    #[inline(always)]
+    fn record(&mut self) {}
+}
+
+impl<B, A> MemoryAllocator<B> for SingleThreadGuard<A>
+where
+    B: Backend,
+    A: MemoryAllocator<B>,
+{
+    type Request = A::Request;
+    type Block = A::Block;
+
+    fn alloc(
+        &mut self,
+        device: &B::Device,
+        request: A::Request,
+        reqs: Requirements,
+    ) -> Result<A::Block, MemoryError> {
+        self.record();
+        self.inner.alloc(device, request, reqs)
+    }
+
+    fn free(&mut self, device: &B::Device, block: A::Block) {
+        self.record();
+        self.inner.free(device, block)
+    }
+
+    fn block_memory_type(&self, block: &A::Block) -> Option<MemoryTypeId> {
+        self.check();
+        self.inner.block_memory_type(block)
+    }
+
+    fn instance_id(&self) -> Option<usize> {
+        self.check();
+        self.inner.instance_id()
+    }
+
+    fn is_used(&self) -> bool {
+        self.check();
+        self.inner.is_used()
+    }
+
+    fn dispose(self, device: &B::Device) -> Result<(), Self> {
+        self.check();
+        #[cfg(debug_assertions)]
+        let thread = self.thread;
+        self.inner.dispose(device).map_err(|inner| SingleThreadGuard {
+            inner,
+            #[cfg(debug_assertions)]
+            thread,
+        })
+    }
+}
+
+#[test]
+fn test_other_thread() {
+    let mut guard = SingleThreadGuard::new(());
+    guard.inner_mut();
+    let result = ::std::thread::spawn(move || {
+        guard.inner();
+    }).join();
+    assert_eq!(result.is_err(), cfg!(debug_assertions));
+}
@@ -147,6 +147,10 @@ where
         self.allocators[index].1.free(device, block);
     }
 
+    fn block_memory_type(&self, block: &SmartBlock<B::Memory>) -> Option<MemoryTypeId> {
+        Some(MemoryTypeId(block.1))
+    }
+
     fn is_used(&self) -> bool {
         self.allocators
             .iter()
@@ -0,0 +1,178 @@
+use std::cmp::max;
+use std::ops::Range;
+
+use MemoryError;
+use block::Block;
+use packed::{FreeRanges, SearchStats};
+
+/// Allocator of offsets within a single block, e.g. one bound to a big buffer.
+///
+/// Instead of new memory blocks this allocator hands out aligned ranges of offsets relative to
+/// the start of the block. Those are suitable for use as offsets into a buffer bound to the
+/// block. Ranges are packed densely and adjacent free ranges are merged when freed.
+///
+/// ### Type parameters:
+///
+/// - `T`: type of the block offsets are allocated within.
+#[derive(Debug)]
+pub struct SubBufferAllocator<T> {
+    block: T,
+    free: FreeRanges,
+    used: u64,
+    allocations: usize,
+    search_stats: SearchStats,
+}
+
+impl<T> SubBufferAllocator<T>
+where
+    T: Block,
+{
+    /// Create an allocator handing out offsets within `block`.
+    pub fn new(block: T) -> Self {
+        SubBufferAllocator {
+            free: FreeRanges::new(0..block.size()),
+            block,
+            used: 0,
+            allocations: 0,
+            search_stats: SearchStats::default(),
+        }
+    }
+
+    /// Get the block offsets are allocated within.
+    pub fn block(&self) -> &T {
+        &self.block
+    }
+
+    /// Check if any of the ranges allocated by this allocator are still in use.
+    pub fn is_used(&self) -> bool {
+        self.allocations != 0
+    }
+
+    /// Get the total size of all ranges allocated by this allocator.
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    /// Get statistics of the search for free ranges.
+    pub fn search_stats(&self) -> SearchStats {
+        self.search_stats
+    }
+
+    /// Allocate a range of offsets.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `size`: size of the range in bytes
+    /// - `alignment`: alignment of the range start relative to the start of the block
+    ///
+    /// ### Returns
+    ///
+    /// Returns range of offsets relative to the start of the block, which is one byte long if
+    /// `size` is 0. If no free range is large enough, `MemoryError::OutOfMemory` is returned.
+    pub fn alloc(&mut self, size: u64, alignment: u64) -> Result<Range<u64>, MemoryError> {
+        // Empty ranges could not be told apart from free ones when freed
+        let size = max(size, 1);
+        self.search_stats.allocations += 1;
+        let range = self.free
+            .alloc(size, alignment, &mut self.search_stats.examined)
+            .ok_or(MemoryError::OutOfMemory)?;
+        self.used += size;
+        self.allocations += 1;
+        Ok(range)
+    }
+
+    /// Free a range of offsets.
+    ///
+    /// The range must be allocated from this allocator.
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `range` is empty, is not within the block or is already free, in part or
+    /// entirely.
+    pub fn free(&mut self, range: Range<u64>) {
+        assert!(
+            range.start < range.end && range.end <= self.block.size(),
+            "Range {:?} is not within the block",
+            range
+        );
+        assert!(!self.free.overlaps(&range), "Range {:?} is already free", range);
+        self.used -= range.end - range.start;
+        self.allocations -= 1;
+        self.free.free(range);
+    }
+
+    /// Take the block back once no ranges are in use.
+    ///
+    /// ### Returns
+    ///
+    /// If the allocator has ranges that are still in use, this will return `Err(self)`.
+    pub fn into_block(self) -> Result<T, Self> {
+        if self.is_used() {
+            Err(self)
+        } else {
+            Ok(self.block)
+        }
+    }
+}
+
+#[test]
+fn test_offsets() {
+    let memory = 0u32;
+    let block = ::block::RawBlock::new(&memory, 1024..2048);
+    let mut allocator = SubBufferAllocator::new(block);
+
+    let a = allocator.alloc(100, 256).unwrap();
+    let b = allocator.alloc(100, 256).unwrap();
+    assert_eq!(a, 0..100);
+    assert_eq!(b, 256..356);
+    assert!(allocator.alloc(1024, 1).is_err());
+
+    allocator.free(a);
+    allocator.free(b);
+    assert_eq!(allocator.alloc(1024, 1).unwrap(), 0..1024);
+    allocator.free(0..1024);
+
+    let block = allocator.into_block().unwrap();
+    unsafe { block.dispose() };
+}
+
+#[test]
+fn test_zero_size() {
+    let memory = 0u32;
+    let block = ::block::RawBlock::new(&memory, 0..1024);
+    let mut allocator = SubBufferAllocator::new(block);
+
+    let a = allocator.alloc(0, 4).unwrap();
+    let b = allocator.alloc(0, 4).unwrap();
+    assert_eq!(a, 0..1);
+    assert_eq!(b, 4..5);
+    assert_eq!(allocator.used(), 2);
+
+    allocator.free(a);
+    allocator.free(b);
+    assert!(!allocator.is_used());
+    let block = allocator.into_block().unwrap();
+    unsafe { block.dispose() };
+}
+
+#[test]
+#[should_panic(expected = "is not within the block")]
+fn test_free_outside() {
+    let memory = 0u32;
+    let block = ::block::RawBlock::new(&memory, 0..1024);
+    let mut allocator = SubBufferAllocator::new(block);
+    allocator.alloc(1024, 1).unwrap();
+    allocator.free(1000..1100);
+}
+
+#[test]
+#[should_panic(expected = "is already free")]
+fn test_double_free() {
+    let memory = 0u32;
+    let block = ::block::RawBlock::new(&memory, 0..1024);
+    let mut allocator = SubBufferAllocator::new(block);
+    let a = allocator.alloc(100, 1).unwrap();
+    allocator.alloc(100, 1).unwrap();
+    allocator.free(a);
+    allocator.free(50..150);
+}
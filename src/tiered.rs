@@ -0,0 +1,283 @@
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use gfx_hal::{Backend, MemoryTypeId};
+use gfx_hal::memory::Requirements;
+
+use {next_instance_id, MemoryAllocator, MemoryError, MemorySubAllocator};
+use block::Block;
+use chunked::{ChunkedAllocator, ChunkedBlock, NodeSelector, PowerOfTwoSelector};
+
+/// Thread-local `ChunkedAllocator` in front of a global allocator shared by all threads.
+///
+/// Blocks are allocated from and freed to the thread-local allocator without locking. Only when
+/// it has no free block for an allocation the global allocator is locked, once, to take a chunk
+/// that the next blocks of that size are served from. Every `flush_every` frees the unused
+/// chunks of the thread-local allocator are returned to the global allocator, again under a
+/// single lock.
+///
+/// ### Type parameters:
+///
+/// - `T`: type of blocks the global allocator allocates.
+/// - `A`: global allocator shared between threads.
+/// - `S`: strategy defining the size classes of the thread-local allocator.
+#[derive(Debug)]
+pub struct TieredAllocator<T, A, S = PowerOfTwoSelector> {
+    local: ChunkedAllocator<T, S>,
+    global: Arc<Mutex<A>>,
+    /// Instance ID of the global allocator, cached to check the owner without locking
+    global_id: Option<Option<usize>>,
+    flush_every: usize,
+    frees: usize,
+    instance_id: usize,
+}
+
+impl<T, A, S> TieredAllocator<T, A, S> {
+    /// Put a thread-local allocator in front of the global allocator.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `local`: allocator to serve the blocks of this thread from, must not have been used
+    ///   with another owner
+    /// - `global`: allocator to take chunks from, shared with the other threads
+    /// - `flush_every`: number of frees after which unused chunks are returned to `global`
+    ///
+    /// ### Panics
+    ///
+    /// Panics if `flush_every` is 0.
+    pub fn new(local: ChunkedAllocator<T, S>, global: Arc<Mutex<A>>, flush_every: usize) -> Self {
+        assert_ne!(flush_every, 0, "Flush interval must not be 0");
+        TieredAllocator {
+            local,
+            global,
+            global_id: None,
+            flush_every,
+            frees: 0,
+            instance_id: next_instance_id(),
+        }
+    }
+
+    /// Get the thread-local allocator.
+    pub fn local(&self) -> &ChunkedAllocator<T, S> {
+        &self.local
+    }
+
+    /// Get the global allocator.
+    pub fn global(&self) -> &Arc<Mutex<A>> {
+        &self.global
+    }
+
+    /// Return all unused chunks of the thread-local allocator to the global allocator.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `device`: same device that was used to allocate the chunks
+    ///
+    /// ### Returns
+    ///
+    /// The total size of the returned chunks in bytes.
+    pub fn flush<B>(&mut self, device: &B::Device) -> u64
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+        S: NodeSelector,
+    {
+        self.frees = 0;
+        let mut global = lock(&self.global);
+        self.local.shrink(&mut *global, device)
+    }
+}
+
+fn lock<'a, A>(global: &'a Mutex<A>) -> MutexGuard<'a, A> {
+    global.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+/// Owner handle given to the thread-local allocator, locking the global allocator only when
+/// the thread-local allocator actually calls it.
+#[derive(Debug)]
+struct Global<'a, A: 'a> {
+    global: &'a Mutex<A>,
+    id: Option<usize>,
+}
+
+impl<'a, B, A> MemoryAllocator<B> for Global<'a, A>
+where
+    B: Backend,
+    A: MemoryAllocator<B>,
+{
+    type Request = A::Request;
+    type Block = A::Block;
+
+    fn alloc(
+        &mut self,
+        device: &B::Device,
+        request: A::Request,
+        reqs: Requirements,
+    ) -> Result<A::Block, MemoryError> {
+        lock(self.global).alloc(device, request, reqs)
+    }
+
+    fn free(&mut self, device: &B::Device, block: A::Block) {
+        lock(self.global).free(device, block)
+    }
+
+    fn block_memory_type(&self, block: &A::Block) -> Option<MemoryTypeId> {
+        lock(self.global).block_memory_type(block)
+    }
+
+    fn instance_id(&self) -> Option<usize> {
+        self.id
+    }
+
+    fn is_used(&self) -> bool {
+        lock(self.global).is_used()
+    }
+
+    fn dispose(self, _device: &B::Device) -> Result<(), Self> {
+        // The global allocator is shared and outlives the handle
+        Err(self)
+    }
+}
+
+impl<B, T, A, S> MemoryAllocator<B> for TieredAllocator<T, A, S>
+where
+    B: Backend,
+    T: Block<Memory = B::Memory>,
+    A: MemoryAllocator<B, Block = T>,
+    S: NodeSelector + Debug,
+{
+    type Request = A::Request;
+    type Block = ChunkedBlock<B::Memory>;
+
+    fn alloc(
+        &mut self,
+        device: &B::Device,
+        request: A::Request,
+        reqs: Requirements,
+    ) -> Result<ChunkedBlock<B::Memory>, MemoryError> {
+        let global_id = match self.global_id {
+            Some(id) if self.local.available_for(&reqs) > 0 => id,
+            _ => {
+                // Take a chunk and serve the block from it under a single lock
+                let mut global = lock(&self.global);
+                self.global_id = Some(global.instance_id());
+                return MemorySubAllocator::<B, A>::alloc(
+                    &mut self.local,
+                    &mut *global,
+                    device,
+                    request,
+                    reqs,
+                );
+            }
+        };
+        let mut owner = Global {
+            global: &self.global,
+            id: global_id,
+        };
+        MemorySubAllocator::<B, _>::alloc(&mut self.local, &mut owner, device, request, reqs)
+    }
+
+    fn free(&mut self, device: &B::Device, block: ChunkedBlock<B::Memory>) {
+        {
+            let global_id = match self.global_id {
+                Some(id) => id,
+                None => lock(&self.global).instance_id(),
+            };
+            let mut owner = Global {
+                global: &self.global,
+                id: global_id,
+            };
+            MemorySubAllocator::<B, _>::free(&mut self.local, &mut owner, device, block);
+        }
+        self.frees += 1;
+        if self.frees >= self.flush_every {
+            self.flush(device);
+        }
+    }
+
+    fn block_memory_type(&self, _block: &ChunkedBlock<B::Memory>) -> Option<MemoryTypeId> {
+        Some(self.local.memory_type())
+    }
+
+    fn instance_id(&self) -> Option<usize> {
+        Some(self.instance_id)
+    }
+
+    fn is_used(&self) -> bool {
+        self.local.is_used()
+    }
+
+    fn dispose(self, device: &B::Device) -> Result<(), Self> {
+        if self.local.is_used() {
+            return Err(self);
+        }
+        let TieredAllocator { local, global, .. } = self;
+        let mut guard = lock(&global);
+        MemorySubAllocator::<B, A>::dispose(local, &mut *guard, device)
+            .expect("Thread-local allocator is not used");
+        Ok(())
+    }
+}
+
+#[test]
+#[allow(dead_code)]
+fn test_send_sync() {
+    fn foo<T: Send>() {}
+    fn bar<M: Send + Sync, A: Send>() {
+        foo::<TieredAllocator<M, A>>()
+    }
+}
+
+#[test]
+fn test_tiered() {
+    extern crate gfx_backend_empty as empty;
+
+    use std::thread;
+
+    use dry_run::DryRunAllocator;
+
+    let global = Arc::new(Mutex::new(DryRunAllocator::<()>::new()));
+    let reqs = Requirements {
+        type_mask: 1,
+        size: 256,
+        alignment: 1,
+    };
+
+    let threads = (0..2)
+        .map(|_| {
+            let global = global.clone();
+            thread::spawn(move || {
+                let device = empty::Device;
+                let local = ChunkedAllocator::new(MemoryTypeId(0), 4, 256, 1 << 16);
+                let mut allocator = TieredAllocator::new(local, global, 6);
+                let blocks = (0..6)
+                    .map(|_| {
+                        MemoryAllocator::<empty::Backend>::alloc(
+                            &mut allocator,
+                            &device,
+                            (),
+                            reqs,
+                        ).unwrap()
+                    })
+                    .collect::<Vec<_>>();
+                // Two chunks of 4 blocks each are taken from the global allocator
+                assert_eq!(allocator.local().allocated(), 2048);
+
+                for block in blocks {
+                    MemoryAllocator::<empty::Backend>::free(&mut allocator, &device, block);
+                }
+                // The sixth free flushes both chunks back
+                assert_eq!(allocator.local().allocated(), 0);
+                MemoryAllocator::<empty::Backend>::dispose(allocator, &device).unwrap();
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let global = lock(&global);
+    assert_eq!(global.allocations(), 4);
+    assert!(!MemoryAllocator::<empty::Backend>::is_used(&*global));
+}
@@ -0,0 +1,361 @@
+use std::any::Any;
+use std::fmt::Debug;
+use std::ops::Range;
+
+use gfx_hal::{Backend, Device};
+use gfx_hal::memory::Requirements;
+
+use {MemoryAllocator, MemoryError, MemorySubAllocator};
+use block::Block;
+use chunked::{ChunkedAllocator, ChunkedBlock};
+
+/// Allocator of staging memory for uploads that is ready to be written by the CPU.
+///
+/// Blocks are sub-allocated with a `ChunkedAllocator`, which must allocate from a CPU visible
+/// memory type. The chunks are mapped when first used and stay mapped while they hold uploads,
+/// so each upload comes with a pointer to write its data to and the memory and offset to copy
+/// it from on the GPU.
+///
+/// Every chunk must be a separate memory object, e.g. allocated with `RootAllocator`, as a
+/// memory object can only be mapped once. Allocating an upload panics otherwise.
+///
+/// ### Type parameters:
+///
+/// - `T`: type of blocks the `ChunkedAllocator` sub-allocates from.
+#[derive(Debug)]
+pub struct UploadAllocator<T> {
+    allocator: ChunkedAllocator<T>,
+    mappings: Vec<Mapping>,
+}
+
+/// Mapped chunk.
+#[derive(Debug)]
+struct Mapping {
+    /// Address of the memory object of the chunk
+    memory: usize,
+    /// Pointer to the start of the chunk
+    ptr: usize,
+    /// Offset of the chunk within the memory object
+    offset: u64,
+    /// Number of uploads allocated from the chunk
+    uploads: usize,
+}
+
+impl<T> UploadAllocator<T> {
+    /// Create a new upload allocator.
+    ///
+    /// The pressure threshold, the free chunk limits and automatic shrinking of `allocator` are
+    /// disabled, as chunks must be unmapped before they are freed.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `allocator`: allocator of a CPU visible memory type to allocate blocks from
+    pub fn new(mut allocator: ChunkedAllocator<T>) -> Self {
+        allocator.set_pressure_threshold(None);
+        allocator.clear_free_chunk_limits();
+        allocator.disable_auto_shrink();
+        UploadAllocator {
+            allocator,
+            mappings: Vec::new(),
+        }
+    }
+
+    /// Get the allocator blocks are sub-allocated from.
+    pub fn allocator(&self) -> &ChunkedAllocator<T> {
+        &self.allocator
+    }
+
+    /// Allocate a block for an upload and get the pointer to write its data to.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator the `ChunkedAllocator` allocates chunks from
+    /// - `device`: device to allocate and map the memory with
+    /// - `request`: information required by `owner` to allocate a chunk
+    /// - `size`: size of the data to upload in bytes
+    ///
+    /// ### Panics
+    ///
+    /// Panics if the chunk of the upload shares its memory object with another mapped chunk.
+    pub fn alloc_upload<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        request: A::Request,
+        size: u64,
+    ) -> Result<Upload<B::Memory>, MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        self.alloc_mapped::<B, A, _>(owner, device, request, size, |memory, range| {
+            device.map_memory(memory, range).map_err(Into::into)
+        })
+    }
+
+    /// Allocate a block for an upload, mapping its chunk with `map` unless it is mapped already.
+    fn alloc_mapped<B, A, F>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        request: A::Request,
+        size: u64,
+        map: F,
+    ) -> Result<Upload<B::Memory>, MemoryError>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+        F: FnOnce(&B::Memory, Range<u64>) -> Result<*mut u8, MemoryError>,
+    {
+        let reqs = Requirements {
+            type_mask: 1 << self.allocator.memory_type().0,
+            size,
+            alignment: 1,
+        };
+        let block = MemorySubAllocator::<B, A>::alloc(
+            &mut self.allocator,
+            owner,
+            device,
+            request,
+            reqs,
+        )?;
+
+        let index = {
+            let chunk = self.allocator.underlying_block(&block);
+            let memory = chunk.memory() as *const _ as usize;
+            match self.mappings
+                .iter()
+                .position(|mapping| mapping.memory == memory)
+            {
+                Some(index) => {
+                    assert_eq!(
+                        self.mappings[index].offset,
+                        chunk.range().start,
+                        "Chunks of uploads must be separate memory objects"
+                    );
+                    Ok(index)
+                }
+                None => Err((memory, chunk.range())),
+            }
+        };
+        let index = match index {
+            Ok(index) => index,
+            Err((memory, range)) => match map(block.memory(), range.clone()) {
+                Ok(ptr) => {
+                    self.mappings.push(Mapping {
+                        memory,
+                        ptr: ptr as usize,
+                        offset: range.start,
+                        uploads: 0,
+                    });
+                    self.mappings.len() - 1
+                }
+                Err(error) => {
+                    MemorySubAllocator::<B, A>::free(&mut self.allocator, owner, device, block);
+                    return Err(error);
+                }
+            },
+        };
+
+        let mapping = &mut self.mappings[index];
+        mapping.uploads += 1;
+        let ptr = mapping.ptr + (block.range().start - mapping.offset) as usize;
+        Ok(Upload {
+            ptr: ptr as *mut u8,
+            block,
+        })
+    }
+
+    /// Free the block of an upload once the GPU finished copying from it.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator the `ChunkedAllocator` allocated chunks from
+    /// - `device`: same device that was used to allocate the upload
+    /// - `upload`: upload to free
+    pub fn free_upload<B, A>(
+        &mut self,
+        owner: &mut A,
+        device: &B::Device,
+        upload: Upload<B::Memory>,
+    ) where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        let memory = upload.block.memory() as *const _ as usize;
+        let mapping = self.mappings
+            .iter_mut()
+            .find(|mapping| mapping.memory == memory)
+            .expect("Upload wasn't allocated by this allocator");
+        mapping.uploads -= 1;
+        MemorySubAllocator::<B, A>::free(&mut self.allocator, owner, device, upload.block);
+    }
+
+    /// Unmap chunks that hold no uploads and free the unused ones.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator the `ChunkedAllocator` allocated chunks from
+    /// - `device`: same device that was used to allocate the uploads
+    ///
+    /// ### Returns
+    ///
+    /// The total size of the freed chunks in bytes.
+    pub fn shrink<B, A>(&mut self, owner: &mut A, device: &B::Device) -> u64
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        self.unmap(false, |memory: &B::Memory| device.unmap_memory(memory));
+        self.allocator.shrink(owner, device)
+    }
+
+    /// Unmap all chunks and dispose of the `ChunkedAllocator`.
+    ///
+    /// ### Parameters:
+    ///
+    /// - `owner`: allocator the `ChunkedAllocator` allocated chunks from
+    /// - `device`: must be the same device all allocations have been made against
+    ///
+    /// ### Returns
+    ///
+    /// If there are uploads that are still allocated, this will return the allocator boxed in
+    /// `Err`.
+    pub fn dispose<B, A>(mut self, owner: &mut A, device: &B::Device) -> Result<(), Box<Self>>
+    where
+        B: Backend,
+        T: Block<Memory = B::Memory>,
+        A: MemoryAllocator<B, Block = T>,
+    {
+        if self.allocator.is_used() {
+            return Err(Box::new(self));
+        }
+        self.unmap(true, |memory: &B::Memory| device.unmap_memory(memory));
+        MemorySubAllocator::<B, A>::dispose(self.allocator, owner, device)
+            .map_err(|allocator| {
+                Box::new(UploadAllocator {
+                    allocator,
+                    mappings: Vec::new(),
+                })
+            })
+    }
+
+    /// Unmap chunks that hold no uploads, or all of them, with `unmap`.
+    fn unmap<M, F>(&mut self, all: bool, mut unmap: F)
+    where
+        T: Block<Memory = M>,
+        F: FnMut(&M),
+    {
+        self.mappings.retain(|mapping| {
+            if mapping.uploads == 0 || all {
+                let memory = unsafe { &*(mapping.memory as *const M) };
+                unmap(memory);
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+/// Upload allocated by `UploadAllocator`.
+///
+/// The data is written to `ptr`, and then copied on the GPU from `memory` at `offset`.
+#[derive(Debug)]
+pub struct Upload<M> {
+    ptr: *mut u8,
+    block: ChunkedBlock<M>,
+}
+
+impl<M> Upload<M>
+where
+    M: Debug + Any,
+{
+    /// Pointer to write the data of the upload to, valid for `block().size()` bytes.
+    pub fn ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    /// Memory to copy the data of the upload from.
+    pub fn memory(&self) -> &M {
+        self.block.memory()
+    }
+
+    /// Offset of the data of the upload within `memory`.
+    pub fn offset(&self) -> u64 {
+        self.block.range().start
+    }
+
+    /// Block of the upload.
+    pub fn block(&self) -> &ChunkedBlock<M> {
+        &self.block
+    }
+}
+
+#[test]
+#[allow(dead_code)]
+fn test_send_sync() {
+    fn foo<T: Send + Sync>() {}
+    fn bar<M: Send + Sync>() {
+        foo::<UploadAllocator<M>>()
+    }
+}
+
+#[test]
+fn test_upload() {
+    extern crate gfx_backend_empty as empty;
+
+    use gfx_hal::MemoryTypeId;
+
+    use dry_run::DryRunAllocator;
+
+    let device = empty::Device;
+    let mut owner = DryRunAllocator::<()>::new();
+    let mut allocator =
+        UploadAllocator::new(ChunkedAllocator::new(MemoryTypeId(0), 4, 256, 1 << 10));
+    let mut data = [0u8; 1024];
+    let ptr = data.as_mut_ptr();
+    let mut maps = Vec::new();
+
+    let uploads = (0..2)
+        .map(|_| {
+            allocator
+                .alloc_mapped::<empty::Backend, _, _>(&mut owner, &device, (), 256, |_, range| {
+                    maps.push(range);
+                    Ok(ptr)
+                })
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    // Both uploads are written through the single mapping of their chunk
+    assert_eq!(maps, vec![0..1024]);
+    for upload in &uploads {
+        assert_eq!(upload.ptr() as u64 - ptr as u64, upload.offset());
+    }
+
+    for upload in uploads {
+        allocator.free_upload::<empty::Backend, _>(&mut owner, &device, upload);
+    }
+    let mut unmaps = 0;
+    allocator.unmap(false, |_: &()| unmaps += 1);
+    assert_eq!(unmaps, 1);
+    assert_eq!(allocator.allocator.shrink::<empty::Backend, _>(&mut owner, &device), 1024);
+
+    // A new chunk is mapped again
+    let upload = allocator
+        .alloc_mapped::<empty::Backend, _, _>(&mut owner, &device, (), 256, |_, range| {
+            maps.push(range);
+            Ok(ptr)
+        })
+        .unwrap();
+    assert_eq!(maps.len(), 2);
+    allocator.free_upload::<empty::Backend, _>(&mut owner, &device, upload);
+    allocator.unmap(true, |_: &()| unmaps += 1);
+    assert_eq!(unmaps, 2);
+    MemorySubAllocator::<empty::Backend, _>::dispose(allocator.allocator, &mut owner, &device)
+        .unwrap();
+}